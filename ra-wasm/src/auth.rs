@@ -0,0 +1,527 @@
+//! Authorization analysis for contract entry points.
+//!
+//! For every `#[contractimpl]` function taking an `Address`, this walks the
+//! body in evaluation order and tracks which addresses have called
+//! `require_auth` (or `require_auth_for_args`) on *all* paths reaching each
+//! storage write or token transfer. Branches are merged by intersection, loop
+//! bodies and closures don't count since they may never run.
+use ide::TextRange;
+use syntax::{
+    ast::{self, HasArgList, HasLoopBody, HasName},
+    AstNode, SourceFile, SyntaxNode,
+};
+
+use crate::{
+    lints::{Lint, LintDiagnostic},
+    return_types::MarkerSeverity,
+    soroban,
+};
+
+pub(crate) struct EntryPointAuth {
+    pub(crate) range: TextRange,
+    pub(crate) addresses: Vec<String>,
+    /// `require_auth` calls, with the address they authorize.
+    pub(crate) auth_calls: Vec<(String, TextRange)>,
+    /// Addresses authorized on every path through the function.
+    pub(crate) authorized: Vec<String>,
+    pub(crate) unauthorized_ops: Vec<UnauthorizedOp>,
+}
+
+/// A storage write reached before any address is authorized, or a token
+/// transfer out of an address before it is.
+pub(crate) struct UnauthorizedOp {
+    pub(crate) what: &'static str,
+    pub(crate) range: TextRange,
+    /// The `Address` parameter the tokens are moved out of.
+    pub(crate) address: Option<String>,
+    /// Branches on the way to the operation that skip `require_auth`.
+    pub(crate) skipped: Vec<String>,
+}
+
+pub(crate) fn analyze(file: &SourceFile) -> Vec<EntryPointAuth> {
+    soroban::entry_points(file)
+        .into_iter()
+        .filter_map(|entry| {
+            let addresses = address_params(&entry.func);
+            if addresses.is_empty() {
+                return None;
+            }
+            let body = entry.func.body()?;
+            let mut walker = Walker {
+                addresses: &addresses,
+                auth_calls: Vec::new(),
+                ops: Vec::new(),
+                exits: Vec::new(),
+            };
+            let mut state = State::default();
+            walker.walk(body.syntax(), &mut state);
+
+            let exits = walker.exits.into_iter().chain(Some(state));
+            let end = State::merge(exits.map(|it| (it, String::new())).collect());
+            Some(EntryPointAuth {
+                range: entry.func.syntax().text_range(),
+                authorized: end.authorized,
+                auth_calls: walker.auth_calls,
+                unauthorized_ops: walker.ops,
+                addresses,
+            })
+        })
+        .collect()
+}
+
+fn address_params(func: &ast::Fn) -> Vec<String> {
+    func.param_list()
+        .into_iter()
+        .flat_map(|list| list.params())
+        .filter_map(|param| {
+            let ty = param.ty()?.syntax().text().to_string();
+            let ty = ty.trim_start_matches('&');
+            if ty != "Address" && !ty.ends_with("::Address") {
+                return None;
+            }
+            match param.pat()? {
+                ast::Pat::IdentPat(pat) => Some(pat.name()?.text().to_string()),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+#[derive(Clone)]
+struct State {
+    reachable: bool,
+    authorized: Vec<String>,
+    skipped: Vec<String>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State { reachable: true, authorized: Vec::new(), skipped: Vec::new() }
+    }
+}
+
+impl State {
+    /// Joins the states flowing out of alternative branches, each labelled
+    /// with a description used when the branch skips an authorization.
+    fn merge(branches: Vec<(State, String)>) -> State {
+        let branches: Vec<_> = branches.into_iter().filter(|(it, _)| it.reachable).collect();
+        if branches.is_empty() {
+            return State { reachable: false, ..State::default() };
+        }
+        let mut res = State::default();
+        for (state, label) in &branches {
+            let is_behind = branches.iter().any(|(other, _)| {
+                other.authorized.iter().any(|addr| !state.authorized.contains(addr))
+            });
+            for skipped in &state.skipped {
+                if !res.skipped.contains(skipped) {
+                    res.skipped.push(skipped.clone());
+                }
+            }
+            if is_behind && !label.is_empty() && !res.skipped.contains(label) {
+                res.skipped.push(label.clone());
+            }
+        }
+        res.authorized = branches[0]
+            .0
+            .authorized
+            .iter()
+            .filter(|addr| branches.iter().all(|(it, _)| it.authorized.contains(addr)))
+            .cloned()
+            .collect();
+        res
+    }
+}
+
+struct Walker<'a> {
+    addresses: &'a [String],
+    auth_calls: Vec<(String, TextRange)>,
+    ops: Vec<UnauthorizedOp>,
+    /// States at early returns (`return`, `?`).
+    exits: Vec<State>,
+}
+
+impl Walker<'_> {
+    fn walk(&mut self, node: &SyntaxNode, state: &mut State) {
+        let expr = match ast::Expr::cast(node.clone()) {
+            Some(expr) => expr,
+            None => return self.walk_children(node, state),
+        };
+        match expr {
+            ast::Expr::IfExpr(if_expr) => {
+                let cond = if_expr.condition();
+                if let Some(cond) = &cond {
+                    self.walk(cond.syntax(), state);
+                }
+                let cond = cond.map(|it| snippet(it.syntax())).unwrap_or_default();
+                let mut then_state = state.clone();
+                if let Some(then_branch) = if_expr.then_branch() {
+                    self.walk(then_branch.syntax(), &mut then_state);
+                }
+                let mut else_state = state.clone();
+                match if_expr.else_branch() {
+                    Some(ast::ElseBranch::Block(block)) => {
+                        self.walk(block.syntax(), &mut else_state)
+                    }
+                    Some(ast::ElseBranch::IfExpr(it)) => self.walk(it.syntax(), &mut else_state),
+                    None => {}
+                }
+                *state = State::merge(vec![
+                    (then_state, format!("`if {}` branch", cond)),
+                    (else_state, format!("`else` branch of `if {}`", cond)),
+                ]);
+            }
+            ast::Expr::MatchExpr(match_expr) => {
+                let scrutinee = match_expr.expr();
+                if let Some(scrutinee) = &scrutinee {
+                    self.walk(scrutinee.syntax(), state);
+                }
+                let scrutinee = scrutinee.map(|it| snippet(it.syntax())).unwrap_or_default();
+                let arms = match_expr.match_arm_list().into_iter().flat_map(|it| it.arms());
+                let branches = arms
+                    .map(|arm| {
+                        let mut arm_state = state.clone();
+                        if let Some(guard) = arm.guard() {
+                            self.walk(guard.syntax(), &mut arm_state);
+                        }
+                        if let Some(expr) = arm.expr() {
+                            self.walk(expr.syntax(), &mut arm_state);
+                        }
+                        let pat = arm.pat().map(|it| snippet(it.syntax())).unwrap_or_default();
+                        (arm_state, format!("`{}` arm of `match {}`", pat, scrutinee))
+                    })
+                    .collect();
+                *state = State::merge(branches);
+            }
+            ast::Expr::WhileExpr(while_expr) => {
+                if let Some(cond) = while_expr.condition() {
+                    self.walk(cond.syntax(), state);
+                }
+                if let Some(body) = while_expr.loop_body() {
+                    self.walk(body.syntax(), &mut state.clone());
+                }
+            }
+            ast::Expr::ForExpr(for_expr) => {
+                if let Some(iterable) = for_expr.iterable() {
+                    self.walk(iterable.syntax(), state);
+                }
+                if let Some(body) = for_expr.loop_body() {
+                    self.walk(body.syntax(), &mut state.clone());
+                }
+            }
+            ast::Expr::ClosureExpr(closure) => {
+                if let Some(body) = closure.body() {
+                    self.walk(body.syntax(), &mut state.clone());
+                }
+            }
+            ast::Expr::ReturnExpr(ret) => {
+                if let Some(expr) = ret.expr() {
+                    self.walk(expr.syntax(), state);
+                }
+                self.exits.push(state.clone());
+                state.reachable = false;
+            }
+            ast::Expr::TryExpr(try_expr) => {
+                if let Some(expr) = try_expr.expr() {
+                    self.walk(expr.syntax(), state);
+                }
+                self.exits.push(state.clone());
+            }
+            ast::Expr::MacroCall(mac) => {
+                let name = mac.path().and_then(|it| it.segment()?.name_ref());
+                let diverges = name.map_or(false, |it| {
+                    ["panic", "panic_with_error", "unreachable", "todo", "unimplemented"]
+                        .contains(&it.text().as_str())
+                });
+                if diverges {
+                    state.reachable = false;
+                }
+            }
+            ast::Expr::MethodCallExpr(call) => {
+                self.walk_children(node, state);
+                let name = soroban::method_name(&call).unwrap_or_default();
+                if name == "require_auth" || name == "require_auth_for_args" {
+                    let address = call.receiver().and_then(|it| self.address(&it));
+                    self.authorize(address, call.syntax().text_range(), state);
+                } else if soroban::storage_kind(&call).is_some()
+                    && (name == "set" || name == "remove")
+                {
+                    self.sensitive("storage write", None, call.syntax().text_range(), state);
+                } else if ["transfer", "transfer_from", "burn", "burn_from"]
+                    .contains(&name.as_str())
+                {
+                    // The address the tokens are taken from, or the spender,
+                    // comes first.
+                    let arg = call.arg_list().and_then(|it| it.args().next());
+                    let address = arg.and_then(|it| self.address(&it));
+                    self.sensitive("token transfer", address, call.syntax().text_range(), state);
+                }
+            }
+            ast::Expr::CallExpr(call) => {
+                self.walk_children(node, state);
+                let is_require_auth = match call.expr() {
+                    Some(ast::Expr::PathExpr(path)) => {
+                        let name = path.path().and_then(|it| it.segment()?.name_ref());
+                        name.map_or(false, |it| it.text() == "require_auth")
+                    }
+                    _ => false,
+                };
+                if is_require_auth {
+                    let arg = call.arg_list().and_then(|it| it.args().next());
+                    let address = arg.and_then(|it| self.address(&it));
+                    self.authorize(address, call.syntax().text_range(), state);
+                }
+            }
+            _ => self.walk_children(node, state),
+        }
+    }
+
+    /// Walks sub-expressions in source order, skipping nested items. Macro
+    /// calls in statement position parse as items but may diverge.
+    fn walk_children(&mut self, node: &SyntaxNode, state: &mut State) {
+        for child in node.children() {
+            if !ast::Item::can_cast(child.kind()) || ast::MacroCall::can_cast(child.kind()) {
+                self.walk(&child, state);
+            }
+        }
+    }
+
+    fn authorize(&mut self, address: Option<String>, range: TextRange, state: &mut State) {
+        let address = match address {
+            Some(it) if state.reachable => it,
+            _ => return,
+        };
+        if !state.authorized.contains(&address) {
+            state.authorized.push(address.clone());
+        }
+        self.auth_calls.push((address, range));
+    }
+
+    /// Records `what` at `range` unless `address`, or any address when the
+    /// operation isn't on one of the parameters, is authorized.
+    fn sensitive(
+        &mut self,
+        what: &'static str,
+        address: Option<String>,
+        range: TextRange,
+        state: &State,
+    ) {
+        let is_authorized = match &address {
+            Some(address) => state.authorized.contains(address),
+            None => !state.authorized.is_empty(),
+        };
+        if state.reachable && !is_authorized {
+            let skipped = state.skipped.clone();
+            self.ops.push(UnauthorizedOp { what, range, address, skipped });
+        }
+    }
+
+    /// Resolves `from`, `&from` or `(from)` to an `Address` parameter.
+    fn address(&self, expr: &ast::Expr) -> Option<String> {
+        match expr {
+            ast::Expr::RefExpr(it) => self.address(&it.expr()?),
+            ast::Expr::ParenExpr(it) => self.address(&it.expr()?),
+            ast::Expr::PathExpr(it) => {
+                let name = it.path()?.as_single_name_ref()?.text().to_string();
+                if self.addresses.contains(&name) {
+                    Some(name)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Short, single-line rendering of a condition or pattern for messages.
+fn snippet(node: &SyntaxNode) -> String {
+    let text = node.text().to_string();
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() > 30 {
+        format!("{}…", text.chars().take(30).collect::<String>())
+    } else {
+        text
+    }
+}
+
+/// Reports storage writes and token transfers that can run before any of the
+/// entry point's `Address` arguments called `require_auth`.
+pub(crate) struct MissingAuth;
+
+impl Lint for MissingAuth {
+    fn check(&self, file: &SourceFile, acc: &mut Vec<LintDiagnostic>) {
+        for entry in analyze(file) {
+            let addresses =
+                entry.addresses.iter().map(|it| format!("`{}`", it)).collect::<Vec<_>>().join(", ");
+            for op in entry.unauthorized_ops {
+                let mut message = match &op.address {
+                    Some(address) => format!(
+                        "{} out of `{}` happens before it is authorized with `require_auth`",
+                        op.what, address
+                    ),
+                    None => format!(
+                        "{} happens before any of {} is authorized with `require_auth`",
+                        op.what, addresses
+                    ),
+                };
+                if !op.skipped.is_empty() {
+                    message.push_str(&format!(
+                        "; `require_auth` is skipped on the {}",
+                        op.skipped.join(", ")
+                    ));
+                }
+                acc.push(LintDiagnostic {
+                    code: "soroban-missing-auth",
+                    message,
+                    severity: MarkerSeverity::Warning,
+                    range: op.range,
+                    fix: None,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use syntax::SourceFile;
+
+    /// The unauthorized operations of the entry point `func`, with the
+    /// branches skipping `require_auth`.
+    fn check(func: &str) -> Vec<(String, Vec<String>)> {
+        let text =
+            format!("#[contract]\npub struct C;\n#[contractimpl]\nimpl C {{\n{}\n}}\n", func);
+        let file = SourceFile::parse(&text).tree();
+        super::analyze(&file)
+            .into_iter()
+            .flat_map(|it| it.unauthorized_ops)
+            .map(|op| (text[op.range].to_string(), op.skipped))
+            .collect()
+    }
+
+    fn authorized(func: &str) -> Vec<String> {
+        let text = format!("#[contractimpl]\nimpl C {{\n{}\n}}\n", func);
+        let file = SourceFile::parse(&text).tree();
+        super::analyze(&file).into_iter().flat_map(|it| it.authorized).collect()
+    }
+
+    #[test]
+    fn write_after_auth() {
+        let ops = check(
+            r#"pub fn set(env: Env, admin: Address, value: u32) {
+                admin.require_auth();
+                env.storage().instance().set(&0, &value);
+            }"#,
+        );
+        assert!(ops.is_empty());
+    }
+
+    #[test]
+    fn write_before_auth() {
+        let ops = check(
+            r#"pub fn set(env: Env, admin: Address, value: u32) {
+                env.storage().instance().set(&0, &value);
+                admin.require_auth();
+            }"#,
+        );
+        assert_eq!(ops.len(), 1);
+        assert_eq!(ops[0].0, "env.storage().instance().set(&0, &value)");
+    }
+
+    #[test]
+    fn functions_without_addresses_are_skipped() {
+        let ops = check(
+            r#"pub fn set(env: Env, value: u32) {
+                env.storage().instance().set(&0, &value);
+            }"#,
+        );
+        assert!(ops.is_empty());
+    }
+
+    #[test]
+    fn transfer_needs_auth_of_its_address() {
+        let func = r#"pub fn pay(env: Env, a: Address, b: Address, token: Address) {
+                a.require_auth();
+                let client = token::Client::new(&env, &token);
+                client.transfer(&a, &b, &1);
+                client.transfer(&b, &a, &1);
+            }"#;
+        let ops = check(func);
+        assert_eq!(ops.len(), 1);
+        assert_eq!(ops[0].0, "client.transfer(&b, &a, &1)");
+    }
+
+    #[test]
+    fn auth_in_one_branch() {
+        let ops = check(
+            r#"pub fn set(env: Env, admin: Address, value: u32) {
+                if value > 10 {
+                    admin.require_auth();
+                }
+                env.storage().instance().set(&0, &value);
+            }"#,
+        );
+        assert_eq!(ops.len(), 1);
+        assert_eq!(ops[0].1, ["`else` branch of `if value > 10`"]);
+    }
+
+    #[test]
+    fn auth_in_every_branch() {
+        let func = r#"pub fn set(env: Env, admin: Address, value: u32) {
+                match value {
+                    0 => admin.require_auth(),
+                    _ => {
+                        require_auth(&admin);
+                    }
+                }
+                env.storage().instance().set(&0, &value);
+            }"#;
+        assert!(check(func).is_empty());
+        assert_eq!(authorized(func), ["admin"]);
+    }
+
+    #[test]
+    fn auth_in_loop() {
+        let ops = check(
+            r#"pub fn set(env: Env, admin: Address, values: Vec<u32>) {
+                for value in values {
+                    admin.require_auth();
+                }
+                env.storage().instance().set(&0, &1);
+            }"#,
+        );
+        assert_eq!(ops.len(), 1);
+    }
+
+    #[test]
+    fn early_exits() {
+        let func = r#"pub fn set(env: Env, admin: Address, value: Option<u32>) -> Result<(), Error> {
+                let value = value.ok_or(Error::Missing)?;
+                if value == 0 {
+                    return Ok(());
+                }
+                admin.require_auth();
+                env.storage().instance().set(&0, &value);
+                Ok(())
+            }"#;
+        assert!(check(func).is_empty());
+        // Not on the paths leaving through the `?` and the `return`.
+        assert!(authorized(func).is_empty());
+    }
+
+    #[test]
+    fn panics_end_paths() {
+        let ops = check(
+            r#"pub fn set(env: Env, admin: Address, value: u32) {
+                if value == 0 {
+                    panic!("zero");
+                } else {
+                    admin.require_auth();
+                }
+                env.storage().instance().set(&0, &value);
+            }"#,
+        );
+        assert!(ops.is_empty());
+    }
+}
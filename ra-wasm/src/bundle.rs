@@ -0,0 +1,186 @@
+//! Loading the library crates of a bundle emitted by `rust-pack` into the
+//! crate graph.
+use std::sync::Arc;
+
+use cfg::CfgOptions;
+use ide::{Change, CrateGraph, Edition, FileId, SourceRoot, TextSize};
+use ide_db::base_db::{CrateDisplayName, CrateName, Dependency, Env};
+use serde::Deserialize;
+
+use crate::{bundle_header, create_source_root, proc_macros};
+
+#[derive(Deserialize)]
+struct Bundle {
+    crates: Vec<BundleCrate>,
+}
+
+/// The crates of a `bundle.bin` emitted by `rust-pack`.
+pub(crate) fn decode(bytes: &[u8]) -> Result<Vec<BundleCrate>, String> {
+    let json = bundle_header::inflate(bytes)?;
+    let bundle: Bundle =
+        serde_json::from_slice(&json).map_err(|e| format!("invalid bundle: {}", e))?;
+    Ok(bundle.crates)
+}
+
+#[derive(Deserialize)]
+pub struct BundleCrate {
+    id: String,
+    name: String,
+    edition: String,
+    /// Target cfg atoms, `unix` or `target_os="linux"`.
+    cfg: Vec<String>,
+    features: Vec<String>,
+    deps: Vec<BundleDep>,
+    #[serde(default)]
+    proc_macro: bool,
+    /// Whether the user's code depends on the crate directly.
+    #[serde(default)]
+    root: bool,
+    #[serde(default)]
+    source_map: Option<SourceMap>,
+    /// The paths the crate's items can be imported from.
+    #[serde(default)]
+    exports: Vec<Export>,
+    source: String,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct Export {
+    /// Relative to the crate root.
+    pub(crate) path: String,
+    pub(crate) kind: String,
+    /// The same for every path of an item in the bundle.
+    pub(crate) item: u32,
+}
+
+#[derive(Deserialize)]
+struct BundleDep {
+    #[serde(rename = "crate")]
+    krate: String,
+    name: String,
+}
+
+/// Maps offsets of a packed crate back to the files it was packed from.
+#[derive(Deserialize)]
+pub(crate) struct SourceMap {
+    files: Vec<String>,
+    /// Sorted by offset.
+    segments: Vec<Segment>,
+}
+
+/// From `offset` until the next segment, the packed text is the one of
+/// `files[file]` starting at `line`.
+#[derive(Deserialize)]
+struct Segment {
+    offset: u32,
+    file: u32,
+    line: u32,
+}
+
+impl SourceMap {
+    /// The original path and 0-based line of `offset` in `text`, the packed
+    /// source.
+    pub(crate) fn locate(&self, text: &str, offset: TextSize) -> Option<(&str, u32)> {
+        let offset = u32::from(offset);
+        let idx = self.segments.partition_point(|it| it.offset <= offset);
+        let segment = self.segments.get(idx.checked_sub(1)?)?;
+        let lines = text.get(segment.offset as usize..offset as usize)?.matches('\n').count();
+        let path = self.files.get(segment.file as usize)?;
+        Some((path, segment.line + lines as u32))
+    }
+}
+
+impl BundleCrate {
+    /// The target cfg, without the crate's features.
+    pub(crate) fn target_cfg(&self) -> CfgOptions {
+        let mut cfg = CfgOptions::default();
+        for atom in &self.cfg {
+            match atom.split_once('=') {
+                Some((key, value)) => {
+                    cfg.insert_key_value(key.into(), value.trim_matches('"').into())
+                }
+                None => cfg.insert_atom(atom.as_str().into()),
+            }
+        }
+        cfg
+    }
+
+    /// The exports of a crate the user's code depends on, with its extern
+    /// name.
+    pub(crate) fn root_exports(&self) -> Option<(&str, &[Export])> {
+        if self.root {
+            Some((&self.name, &self.exports))
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn into_source_map(self) -> Option<SourceMap> {
+        self.source_map
+    }
+}
+
+/// Adds `crates` to the crate graph, their root files numbered from
+/// `first_file`, and returns the dependencies of the user's crate.
+pub(crate) fn load(
+    crates: &[BundleCrate],
+    first_file: u32,
+    change: &mut Change,
+    crate_graph: &mut CrateGraph,
+    roots: &mut Vec<SourceRoot>,
+) -> Vec<Dependency> {
+    let mut ids = Vec::new();
+    for (idx, krate) in crates.iter().enumerate() {
+        let file_id = FileId(first_file + idx as u32);
+        let macro_crate =
+            if krate.proc_macro { proc_macros::macro_crate(&krate.name) } else { None };
+        let source = match macro_crate {
+            Some(it) if krate.source.is_empty() => it.root_source(),
+            _ => krate.source.clone(),
+        };
+        roots.push(create_source_root(&krate.id, file_id));
+        change.change_file(file_id, Some(Arc::new(source)));
+
+        let mut cfg = krate.target_cfg();
+        for feature in &krate.features {
+            cfg.insert_key_value("feature".into(), feature.as_str().into());
+        }
+        // Editions this version of rust-analyzer doesn't know are closest to
+        // the latest one it does.
+        let edition = krate.edition.parse().unwrap_or(Edition::Edition2021);
+        ids.push(crate_graph.add_crate_root(
+            file_id,
+            edition,
+            Some(CrateDisplayName::from_canonical_name(krate.name.clone())),
+            None,
+            cfg,
+            Default::default(),
+            Env::default(),
+            macro_crate.map(|it| it.proc_macros()).unwrap_or_default(),
+        ));
+    }
+
+    let dependency = |krate: &str, name: &str| {
+        let idx = crates.iter().position(|it| it.id == krate)?;
+        Some(Dependency::new(CrateName::normalize_dashes(name), ids[idx]))
+    };
+    for (krate, &from) in crates.iter().zip(&ids) {
+        for dep in &krate.deps {
+            let dep = match dependency(&dep.krate, &dep.name) {
+                Some(it) => it,
+                None => {
+                    log::warn!(
+                        "`{}` depends on `{}`, which isn't in the bundle",
+                        krate.id,
+                        dep.krate
+                    );
+                    continue;
+                }
+            };
+            if let Err(e) = crate_graph.add_dep(from, dep) {
+                log::warn!("{:?}", e);
+            }
+        }
+    }
+    crates.iter().filter(|it| it.root).filter_map(|it| dependency(&it.id, &it.name)).collect()
+}
@@ -0,0 +1,33 @@
+//! The header of `bundle.bin`: `RPAK`, the format version, and the CRC-32 and
+//! length of the JSON manifest that follows compressed with deflate.
+//!
+//! It doesn't use rust-analyzer so that rust-pack's tests can check their
+//! bundles against it.
+
+const MAGIC: &[u8; 4] = b"RPAK";
+const HEADER_LEN: usize = 16;
+/// The version of `rust-pack`'s format this build can read.
+pub(crate) const FORMAT_VERSION: u32 = 2;
+
+/// The JSON manifest of a `bundle.bin`.
+pub(crate) fn inflate(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+        return Err("not a rust-pack bundle".to_string());
+    }
+    let word = |idx: usize| {
+        u32::from_le_bytes([bytes[idx], bytes[idx + 1], bytes[idx + 2], bytes[idx + 3]])
+    };
+    let (version, checksum, len) = (word(4), word(8), word(12) as usize);
+    if version != FORMAT_VERSION {
+        return Err(format!(
+            "bundle format version {}, expected {}: pack it again with a matching rust-pack",
+            version, FORMAT_VERSION
+        ));
+    }
+    let json = miniz_oxide::inflate::decompress_to_vec_with_limit(&bytes[HEADER_LEN..], len)
+        .map_err(|e| format!("corrupted bundle: {:?}", e))?;
+    if json.len() != len || crc32fast::hash(&json) != checksum {
+        return Err("corrupted bundle: checksum mismatch".to_string());
+    }
+    Ok(json)
+}
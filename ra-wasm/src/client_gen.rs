@@ -0,0 +1,253 @@
+//! Synthesized `<Contract>Client` types.
+//!
+//! `#[contractimpl]` generates the client for invoking the contract from tests,
+//! but its expansion only sees the one block it is on. Instead the clients are
+//! written into a hidden module of the user crate, with the methods of all the
+//! blocks of a contract, declared by [`CLIENT_MOD_DECL`] which gets appended to
+//! the user's code.
+use syntax::{
+    ast::{self, HasName, HasVisibility},
+    AstNode, SourceFile,
+};
+
+use crate::soroban::{self, EntryPoint};
+
+pub(crate) const CLIENT_FILE_PATH: &str = "/my_crate/__soroban_client.rs";
+
+pub(crate) const CLIENT_MOD_NAME: &str = "__soroban_client";
+
+pub(crate) const CLIENT_MOD_DECL: &str =
+    "\n#[doc(hidden)]\nmod __soroban_client;\n#[doc(hidden)]\npub use __soroban_client::*;\n";
+
+/// Generates the client module for the top-level `#[contractimpl]` blocks of
+/// `file`, or an empty string if there are none.
+pub(crate) fn generate(file: &SourceFile) -> String {
+    let entries: Vec<_> = soroban::entry_points(file)
+        .into_iter()
+        .filter(|entry| {
+            entry.impl_.syntax().parent().map_or(false, |it| SourceFile::can_cast(it.kind()))
+        })
+        .filter(|entry| entry.is_trait_impl() || entry.func.visibility().is_some())
+        .collect();
+    if entries.is_empty() {
+        return String::new();
+    }
+
+    let mut contracts: Vec<String> = Vec::new();
+    for entry in &entries {
+        let name = contract_name(entry);
+        if !contracts.contains(&name) {
+            contracts.push(name);
+        }
+    }
+
+    let mut res = String::from("#![allow(unused)]\nuse super::*;\n");
+    for contract in &contracts {
+        client_struct(&format!("{}Client", contract), &mut res);
+        for entry in entries.iter().filter(|it| &contract_name(it) == contract) {
+            client_fns(&entry.func, &mut res);
+        }
+        res.push_str("}\n");
+    }
+    res
+}
+
+/// Writes a client struct called `name` and opens its `impl` block, which the
+/// caller fills with [`client_fns`] and closes.
+pub(crate) fn client_struct(name: &str, acc: &mut String) {
+    acc.push_str(&format!(
+        "
+pub struct {0}<'a> {{
+    pub env: soroban_sdk::Env,
+    pub address: soroban_sdk::Address,
+    _phantom: core::marker::PhantomData<&'a ()>,
+}}
+
+impl<'a> {0}<'a> {{
+    pub fn new(env: &soroban_sdk::Env, address: &soroban_sdk::Address) -> Self {{
+        loop {{}}
+    }}
+",
+        name
+    ));
+}
+
+fn contract_name(entry: &EntryPoint) -> String {
+    let self_ty =
+        entry.impl_.self_ty().map(|it| it.syntax().text().to_string()).unwrap_or_default();
+    self_ty.rsplit("::").next().unwrap_or_default().to_string()
+}
+
+/// Writes the plain and `try_` client methods invoking `func`.
+pub(crate) fn client_fns(func: &ast::Fn, acc: &mut String) {
+    let name = match func.name() {
+        Some(name) if name.text() != "__constructor" => name.text().to_string(),
+        _ => return,
+    };
+    let params: Vec<_> = func
+        .param_list()
+        .into_iter()
+        .flat_map(|list| list.params())
+        .filter_map(|param| {
+            let ty = param.ty()?.syntax().text().to_string();
+            if soroban::is_env_type(&ty) {
+                return None;
+            }
+            let pat = match param.pat()? {
+                ast::Pat::IdentPat(pat) => pat.name()?.text().to_string(),
+                _ => "_".to_string(),
+            };
+            let ty = if ty.starts_with('&') { ty } else { format!("&{}", ty) };
+            Some(format!("{}: {}", pat, ty))
+        })
+        .collect();
+    let params = params.iter().map(|it| format!(", {}", it)).collect::<String>();
+
+    let (ok, err) = match func.ret_type().and_then(|it| it.ty()) {
+        Some(ty) => match result_args(&ty) {
+            Some((ok, err)) => (ok, err),
+            None => (ty.syntax().text().to_string(), "soroban_sdk::Error".to_string()),
+        },
+        None => ("()".to_string(), "soroban_sdk::Error".to_string()),
+    };
+
+    acc.push_str(&format!(
+        "
+    pub fn {0}(&self{1}) -> {2} {{
+        loop {{}}
+    }}
+
+    pub fn try_{0}(
+        &self{1}
+    ) -> Result<Result<{2}, soroban_sdk::ConversionError>, Result<{3}, soroban_sdk::InvokeError>> {{
+        loop {{}}
+    }}
+",
+        name, params, ok, err
+    ));
+}
+
+/// Splits `Result<T, E>` into `T` and `E`.
+fn result_args(ty: &ast::Type) -> Option<(String, String)> {
+    let segment = match ty {
+        ast::Type::PathType(it) => it.path()?.segment()?,
+        _ => return None,
+    };
+    if segment.name_ref()?.text() != "Result" {
+        return None;
+    }
+    let mut args = segment.generic_arg_list()?.generic_args();
+    let ok = args.next()?.syntax().text().to_string();
+    let err = args.next()?.syntax().text().to_string();
+    Some((ok, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use syntax::{
+        ast::{self, HasName},
+        AstNode, SourceFile,
+    };
+
+    fn generate(text: &str) -> String {
+        super::generate(&SourceFile::parse(text).tree())
+    }
+
+    #[test]
+    fn inherent_impl() {
+        let client = generate(
+            r#"
+#[contractimpl]
+impl Counter {
+    pub fn __constructor(env: Env, admin: Address) {}
+    pub fn add(env: Env, to: Address, value: u32) -> Result<u32, Error> {}
+    pub fn reset(env: &Env, values: &Vec<u32>) {}
+    fn helper(env: Env) {}
+}
+"#,
+        );
+        assert_eq!(
+            client,
+            "#![allow(unused)]
+use super::*;
+
+pub struct CounterClient<'a> {
+    pub env: soroban_sdk::Env,
+    pub address: soroban_sdk::Address,
+    _phantom: core::marker::PhantomData<&'a ()>,
+}
+
+impl<'a> CounterClient<'a> {
+    pub fn new(env: &soroban_sdk::Env, address: &soroban_sdk::Address) -> Self {
+        loop {}
+    }
+
+    pub fn add(&self, to: &Address, value: &u32) -> u32 {
+        loop {}
+    }
+
+    pub fn try_add(
+        &self, to: &Address, value: &u32
+    ) -> Result<Result<u32, soroban_sdk::ConversionError>, Result<Error, soroban_sdk::InvokeError>> {
+        loop {}
+    }
+
+    pub fn reset(&self, values: &Vec<u32>) -> () {
+        loop {}
+    }
+
+    pub fn try_reset(
+        &self, values: &Vec<u32>
+    ) -> Result<Result<(), soroban_sdk::ConversionError>, Result<soroban_sdk::Error, soroban_sdk::InvokeError>> {
+        loop {}
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn blocks_of_a_contract() {
+        let client = generate(
+            r#"
+#[contractimpl]
+impl token::Interface for crate::Token {
+    fn balance(env: Env, id: Address) -> i128 {}
+}
+
+#[contractimpl]
+impl Token {
+    pub fn mint(env: Env, to: Address, amount: i128) {}
+}
+
+impl Token {
+    pub fn helper() {}
+}
+
+mod test {
+    #[contractimpl]
+    impl Mock {
+        pub fn call() {}
+    }
+}
+"#,
+        );
+        let file = SourceFile::parse(&client);
+        assert!(file.errors().is_empty(), "{:?}\n{}", file.errors(), client);
+        let fns: Vec<_> = file
+            .tree()
+            .syntax()
+            .descendants()
+            .filter_map(ast::Fn::cast)
+            .map(|it| it.name().unwrap().text().to_string())
+            .collect();
+        assert_eq!(fns, ["new", "balance", "try_balance", "mint", "try_mint"]);
+        assert!(client.contains("pub struct TokenClient<'a> {"));
+        assert!(!client.contains("MockClient"));
+    }
+
+    #[test]
+    fn no_contract() {
+        assert_eq!(generate("impl Counter { pub fn get() {} }"), "");
+    }
+}
@@ -0,0 +1,314 @@
+//! Extracts the public interface of a contract from the `soroban_sdk`
+//! attributes in the user crate, without compiling it.
+use syntax::{
+    ast::{self, HasName, HasVisibility},
+    AstNode, NodeOrToken, SourceFile, SyntaxKind,
+};
+
+use crate::{
+    return_types::{
+        ContractSpec, SpecCase, SpecError, SpecEvent, SpecEventField, SpecField, SpecFunction,
+        SpecType,
+    },
+    soroban,
+};
+
+pub(crate) fn contract_spec(file: &SourceFile) -> ContractSpec {
+    let mut spec = ContractSpec::default();
+    for item in file.syntax().descendants().filter_map(ast::Item::cast) {
+        if soroban::is_test_code(item.syntax()) {
+            continue;
+        }
+        match item {
+            ast::Item::Struct(it) => {
+                if soroban::has_attr(&it, "contract") {
+                    spec.contracts.push(name(&it));
+                }
+                if soroban::has_attr(&it, "contracttype") {
+                    spec.types.push(SpecType::Struct {
+                        name: name(&it),
+                        doc: soroban::docs(&it),
+                        fields: fields(it.field_list()),
+                    });
+                }
+                if let Some(attr) = soroban::find_attr(&it, "contractevent") {
+                    spec.events.push(event(&it, &attr));
+                }
+            }
+            ast::Item::Enum(it) => {
+                if soroban::has_attr(&it, "contracttype") {
+                    spec.types.push(SpecType::Enum {
+                        name: name(&it),
+                        doc: soroban::docs(&it),
+                        cases: cases(&it),
+                    });
+                }
+                if soroban::has_attr(&it, "contracterror") {
+                    spec.errors.push(SpecError {
+                        name: name(&it),
+                        doc: soroban::docs(&it),
+                        cases: cases(&it),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    spec.functions = soroban::entry_points(file)
+        .into_iter()
+        .filter(|entry| entry.is_trait_impl() || entry.func.visibility().is_some())
+        .filter(|entry| !soroban::is_test_code(entry.func.syntax()))
+        .map(|entry| function(&entry.func))
+        .collect();
+    spec
+}
+
+fn name(item: &impl HasName) -> String {
+    item.name().map(|it| it.text().to_string()).unwrap_or_default()
+}
+
+fn text(node: Option<impl AstNode>) -> String {
+    node.map(|it| it.syntax().text().to_string()).unwrap_or_default()
+}
+
+fn function(func: &ast::Fn) -> SpecFunction {
+    let inputs = func
+        .param_list()
+        .into_iter()
+        .flat_map(|list| list.params())
+        .filter_map(|param| {
+            let ty = text(param.ty());
+            if soroban::is_env_type(&ty) {
+                return None;
+            }
+            Some(SpecField { name: text(param.pat()), doc: String::new(), ty })
+        })
+        .collect();
+    SpecFunction {
+        name: name(func),
+        doc: soroban::docs(func),
+        inputs,
+        output: func.ret_type().and_then(|it| it.ty()).map(|it| it.syntax().text().to_string()),
+    }
+}
+
+fn fields(field_list: Option<ast::FieldList>) -> Vec<SpecField> {
+    match field_list {
+        Some(ast::FieldList::RecordFieldList(list)) => list
+            .fields()
+            .map(|field| SpecField {
+                name: name(&field),
+                doc: soroban::docs(&field),
+                ty: text(field.ty()),
+            })
+            .collect(),
+        Some(ast::FieldList::TupleFieldList(list)) => list
+            .fields()
+            .enumerate()
+            .map(|(idx, field)| SpecField {
+                name: idx.to_string(),
+                doc: soroban::docs(&field),
+                ty: text(field.ty()),
+            })
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+fn cases(enum_: &ast::Enum) -> Vec<SpecCase> {
+    enum_
+        .variant_list()
+        .into_iter()
+        .flat_map(|list| list.variants())
+        .map(|variant| SpecCase {
+            name: name(&variant),
+            doc: soroban::docs(&variant),
+            value: variant.expr().map(|it| it.syntax().text().to_string()),
+            types: fields(variant.field_list()).into_iter().map(|it| it.ty).collect(),
+        })
+        .collect()
+}
+
+fn event(strukt: &ast::Struct, attr: &ast::Attr) -> SpecEvent {
+    let fields = match strukt.field_list() {
+        Some(ast::FieldList::RecordFieldList(list)) => list
+            .fields()
+            .map(|field| SpecEventField {
+                name: name(&field),
+                ty: text(field.ty()),
+                topic: soroban::has_attr(&field, "topic"),
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    SpecEvent {
+        name: name(strukt),
+        doc: soroban::docs(strukt),
+        prefix_topics: prefix_topics(attr).unwrap_or_else(|| vec![to_snake_case(&name(strukt))]),
+        fields,
+    }
+}
+
+/// Reads `topics = ["a", "b"]` from `#[contractevent(..)]`.
+fn prefix_topics(attr: &ast::Attr) -> Option<Vec<String>> {
+    let tokens: Vec<_> = attr
+        .token_tree()?
+        .syntax()
+        .descendants_with_tokens()
+        .filter_map(NodeOrToken::into_token)
+        .filter(|it| !it.kind().is_trivia())
+        .collect();
+    let start =
+        tokens.iter().position(|it| it.kind() == SyntaxKind::IDENT && it.text() == "topics")?;
+    let topics = tokens[start + 1..]
+        .iter()
+        .take_while(|it| it.kind() != SyntaxKind::R_BRACK)
+        .filter(|it| it.kind() == SyntaxKind::STRING)
+        .map(|it| it.text().trim_matches('"').to_string())
+        .collect();
+    Some(topics)
+}
+
+fn to_snake_case(name: &str) -> String {
+    let mut res = String::new();
+    for (idx, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if idx > 0 {
+                res.push('_');
+            }
+            res.extend(c.to_lowercase());
+        } else {
+            res.push(c);
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use syntax::SourceFile;
+
+    #[test]
+    fn spec_json() {
+        let text = r#"
+#![no_std]
+use soroban_sdk::{contract, contracterror, contractevent, contractimpl, contracttype, Address, Env};
+
+#[contract]
+pub struct Token;
+
+/// A balance.
+#[contracttype]
+pub struct Balance {
+    /// The owner.
+    pub owner: Address,
+    pub amount: i128,
+}
+
+#[contracttype]
+pub enum DataKey {
+    Admin,
+    Balance(Address),
+}
+
+#[contracterror]
+pub enum Error {
+    /// Not enough tokens.
+    Insufficient = 1,
+}
+
+#[contractevent(topics = ["moved"])]
+pub struct Transfer {
+    #[topic]
+    pub from: Address,
+    pub amount: i128,
+}
+
+#[contractevent]
+pub struct AdminChanged {
+    pub admin: Address,
+}
+
+#[contractimpl]
+impl Token {
+    /// Moves tokens.
+    pub fn transfer(env: Env, from: Address, amount: i128) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn helper(env: &Env) {}
+}
+
+#[cfg(test)]
+mod test {
+    #[contracttype]
+    pub struct Fixture;
+}
+"#;
+        let spec = super::contract_spec(&SourceFile::parse(text).tree());
+        let expected = json!({
+            "contracts": ["Token"],
+            "functions": [{
+                "name": "transfer",
+                "doc": "Moves tokens.",
+                "inputs": [
+                    { "name": "from", "doc": "", "type": "Address" },
+                    { "name": "amount", "doc": "", "type": "i128" },
+                ],
+                "output": "Result<(), Error>",
+            }],
+            "types": [
+                {
+                    "kind": "struct",
+                    "name": "Balance",
+                    "doc": "A balance.",
+                    "fields": [
+                        { "name": "owner", "doc": "The owner.", "type": "Address" },
+                        { "name": "amount", "doc": "", "type": "i128" },
+                    ],
+                },
+                {
+                    "kind": "enum",
+                    "name": "DataKey",
+                    "doc": "",
+                    "cases": [
+                        { "name": "Admin", "doc": "", "value": null, "types": [] },
+                        { "name": "Balance", "doc": "", "value": null, "types": ["Address"] },
+                    ],
+                },
+            ],
+            "errors": [{
+                "name": "Error",
+                "doc": "",
+                "cases": [
+                    { "name": "Insufficient", "doc": "Not enough tokens.", "value": "1", "types": [] },
+                ],
+            }],
+            "events": [
+                {
+                    "name": "Transfer",
+                    "doc": "",
+                    "prefix_topics": ["moved"],
+                    "fields": [
+                        { "name": "from", "type": "Address", "topic": true },
+                        { "name": "amount", "type": "i128", "topic": false },
+                    ],
+                },
+                {
+                    "name": "AdminChanged",
+                    "doc": "",
+                    "prefix_topics": ["admin_changed"],
+                    "fields": [{ "name": "admin", "type": "Address", "topic": false }],
+                },
+            ],
+        });
+        assert_eq!(serde_json::to_value(&spec).unwrap(), expected);
+    }
+
+    #[test]
+    fn snake_case() {
+        assert_eq!(super::to_snake_case("AdminChanged"), "admin_changed");
+        assert_eq!(super::to_snake_case("Mint"), "mint");
+    }
+}
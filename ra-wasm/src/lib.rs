@@ -1,24 +1,36 @@
 #![cfg(target_arch = "wasm32")]
 #![allow(non_snake_case)]
 
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
-use cfg::CfgOptions;
+use hir::db::DefDatabase;
 use ide::{
-    Analysis, AnalysisHost, Change, CompletionConfig, CrateGraph, CrateId, DiagnosticsConfig,
-    Edition, FileId, FilePosition, HoverConfig, HoverDocFormat, Indel, InlayHintsConfig, InlayKind,
-    SourceRoot, TextSize,
+    Analysis, AnalysisHost, Change, CompletionConfig, CrateGraph, DiagnosticsConfig, Edition,
+    FileId, FilePosition, HoverConfig, HoverDocFormat, Indel, InlayHintsConfig, InlayKind, LineCol,
+    LineIndex, NavigationTarget, RangeInfo, SourceRoot, TextRange, TextSize,
 };
 use ide_db::{
-    base_db::{CrateName, Dependency, Env, FileSet, VfsPath},
+    base_db::{Env, FileSet, VfsPath},
     helpers::{
         insert_use::{ImportGranularity, InsertUseConfig, PrefixKind},
         SnippetCap,
     },
     search::SearchScope,
 };
+use syntax::{ast::HasModuleItem, AstNode, SourceFile};
 use wasm_bindgen::prelude::*;
 
+mod auth;
+mod bundle;
+mod bundle_header;
+mod client_gen;
+mod contract_spec;
+mod library_index;
+mod lints;
+mod prime_caches;
+mod proc_macros;
+mod soroban;
+mod soroban_macros;
 mod to_proto;
 
 mod return_types;
@@ -32,10 +44,27 @@ pub fn start() {
     log::info!("worker initialized")
 }
 
+/// Hidden module of the user crate holding the synthesized contract clients.
+const CLIENT_FILE_ID: FileId = FileId(1);
+/// Root files of the bundle crates are numbered from there, in order.
+const FIRST_LIBRARY_FILE: u32 = 2;
+
 #[wasm_bindgen]
 pub struct WorldState {
     host: AnalysisHost,
     file_id: FileId,
+    /// Length of the user's code; the rest of `file_id` is the appended
+    /// declaration of the client module.
+    code_len: TextSize,
+    client: String,
+    /// Source maps of the packed library files.
+    source_maps: HashMap<FileId, bundle::SourceMap>,
+    /// Importable library items, when the bundle has an index.
+    library_index: Option<library_index::LibraryIndex>,
+    /// Started by [`WorldState::init`], `None` before.
+    priming: Option<prime_caches::Priming>,
+    /// Code of the last update, while priming jobs hold the previous revision.
+    pending: Option<String>,
 }
 
 pub fn create_source_root(name: &str, f: FileId) -> SourceRoot {
@@ -44,74 +73,40 @@ pub fn create_source_root(name: &str, f: FileId) -> SourceRoot {
     SourceRoot::new_library(file_set)
 }
 
-pub fn create_crate(crate_graph: &mut CrateGraph, f: FileId) -> CrateId {
-    let mut cfg = CfgOptions::default();
-    cfg.insert_atom("unix".into());
-    cfg.insert_key_value("target_arch".into(), "x86_64".into());
-    cfg.insert_key_value("target_pointer_width".into(), "64".into());
-    crate_graph.add_crate_root(
-        f,
-        Edition::Edition2018,
-        None,
-        None,
-        cfg,
-        Default::default(),
-        Env::default(),
-        Vec::new(),
-    )
-}
-
-pub fn from_single_file(
-    text: String,
-    fake_std: String,
-    fake_core: String,
-    fake_alloc: String,
-    fake_soroban_sdk: String,
-) -> (AnalysisHost, FileId) {
+pub fn from_single_file(text: String, crates: &[bundle::BundleCrate]) -> (AnalysisHost, FileId) {
     let mut host = AnalysisHost::default();
+    host.raw_database_mut().set_enable_proc_attr_macros(true);
     let file_id = FileId(0);
-    let std_id = FileId(1);
-    let core_id = FileId(2);
-    let alloc_id = FileId(3);
-    let soroban_sdk_id = FileId(4);
 
     let mut file_set = FileSet::default();
     file_set.insert(file_id, VfsPath::new_virtual_path("/my_crate/main.rs".to_string()));
-    let source_root = SourceRoot::new_local(file_set);
+    file_set.insert(
+        CLIENT_FILE_ID,
+        VfsPath::new_virtual_path(client_gen::CLIENT_FILE_PATH.to_string()),
+    );
 
     let mut change = Change::new();
-    change.set_roots(vec![
-        source_root,
-        create_source_root("std", std_id),
-        create_source_root("core", core_id),
-        create_source_root("alloc", alloc_id),
-        create_source_root("soroban_sdk", soroban_sdk_id),
-    ]);
+    let mut roots = vec![SourceRoot::new_local(file_set)];
     let mut crate_graph = CrateGraph::default();
-    let my_crate = create_crate(&mut crate_graph, file_id);
-    let std_crate = create_crate(&mut crate_graph, std_id);
-    let core_crate = create_crate(&mut crate_graph, core_id);
-    let alloc_crate = create_crate(&mut crate_graph, alloc_id);
-    let soroban_sdk_crate = create_crate(&mut crate_graph, soroban_sdk_id);
-    let core_dep = Dependency::new(CrateName::new("core").unwrap(), core_crate);
-    let alloc_dep = Dependency::new(CrateName::new("alloc").unwrap(), alloc_crate);
-    let std_dep = Dependency::new(CrateName::new("std").unwrap(), std_crate);
-    let soroban_sdk_dep = Dependency::new(CrateName::new("soroban_sdk").unwrap(), soroban_sdk_crate);
-
-    crate_graph.add_dep(std_crate, core_dep.clone()).unwrap();
-    crate_graph.add_dep(std_crate, alloc_dep.clone()).unwrap();
-    crate_graph.add_dep(alloc_crate, core_dep.clone()).unwrap();
-
-    crate_graph.add_dep(my_crate, core_dep).unwrap();
-    crate_graph.add_dep(my_crate, alloc_dep).unwrap();
-    crate_graph.add_dep(my_crate, std_dep).unwrap();
-    crate_graph.add_dep(my_crate, soroban_sdk_dep).unwrap();
+    // All crates of a bundle are packed for the same target.
+    let cfg = crates.first().map(|it| it.target_cfg()).unwrap_or_default();
+    let my_crate = crate_graph.add_crate_root(
+        file_id,
+        Edition::Edition2018,
+        None,
+        None,
+        cfg,
+        Default::default(),
+        Env::default(),
+        Vec::new(),
+    );
+    for dep in bundle::load(crates, FIRST_LIBRARY_FILE, &mut change, &mut crate_graph, &mut roots) {
+        crate_graph.add_dep(my_crate, dep).unwrap();
+    }
 
+    change.set_roots(roots);
     change.change_file(file_id, Some(Arc::new(text)));
-    change.change_file(std_id, Some(Arc::new(fake_std)));
-    change.change_file(core_id, Some(Arc::new(fake_core)));
-    change.change_file(alloc_id, Some(Arc::new(fake_alloc)));
-    change.change_file(soroban_sdk_id, Some(Arc::new(fake_soroban_sdk)));
+    change.change_file(CLIENT_FILE_ID, Some(Arc::new(String::new())));
     change.set_crate_graph(crate_graph);
     host.apply_change(change);
     (host, file_id)
@@ -121,29 +116,213 @@ impl WorldState {
     fn analysis(&self) -> Analysis {
         self.host.analysis()
     }
+
+    /// Sets the user's code, regenerating the client module whenever the
+    /// `#[contractimpl]` blocks it is derived from change. Returns `false`,
+    /// keeping the code pending, if priming jobs still hold the previous
+    /// revision.
+    fn set_code(&mut self, code: String) -> bool {
+        let code_len = TextSize::of(code.as_str());
+        let mut text = code;
+        text.push_str(client_gen::CLIENT_MOD_DECL);
+        let file = SourceFile::parse(&text).tree();
+        let client = client_gen::generate(&file);
+        // An unclosed block or comment at the end of the code swallows the
+        // declaration, which is left out until the code is complete again.
+        let declared = file.items().filter(|it| it.syntax().text_range().start() >= code_len);
+        if client.is_empty() || declared.count() != 2 {
+            text.truncate(code_len.into());
+        }
+        // Not to stop priming for nothing.
+        if self.analysis().file_text(self.file_id).map_or(false, |it| *it == text) {
+            // Back to the applied code after a pending update.
+            if self.pending.take().is_some() {
+                if let Some(priming) = &mut self.priming {
+                    priming.resume(&self.host);
+                }
+            }
+            return true;
+        }
+        if let Some(priming) = self.priming.as_ref().filter(|it| !it.is_idle()) {
+            priming.stop();
+            text.truncate(code_len.into());
+            self.pending = Some(text);
+            return false;
+        }
+        self.pending = None;
+        let mut change = Change::new();
+        if client != self.client {
+            change.change_file(CLIENT_FILE_ID, Some(Arc::new(client.clone())));
+            self.client = client;
+        }
+        self.code_len = code_len;
+        change.change_file(self.file_id, Some(Arc::new(text)));
+        self.host.apply_change(change);
+        if let Some(priming) = &mut self.priming {
+            priming.resume(&self.host);
+        }
+        true
+    }
+
+    /// Whether the last update is pending, the positions of requests being
+    /// in code that isn't applied yet.
+    fn is_stale(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Whether `range` starts in the user's code rather than the appended
+    /// client module declaration.
+    fn in_code(&self, range: TextRange) -> bool {
+        range.start() < self.code_len
+    }
+
+    /// Moves ranges pointing into the client module declaration to the end of
+    /// the user's code, e.g. for syntax errors caused by an incomplete item.
+    fn clamp_to_code(&self, range: TextRange) -> TextRange {
+        let start = range.start().min(self.code_len);
+        TextRange::new(start, range.end().min(self.code_len).max(start))
+    }
+
+    /// Location links to `nav_info`'s targets in the user's code, and in
+    /// library files that have a source map.
+    fn location_links(
+        &self,
+        nav_info: RangeInfo<Vec<NavigationTarget>>,
+        line_index: &LineIndex,
+    ) -> Vec<LocationLink> {
+        let selection = to_proto::text_range(nav_info.range, line_index);
+        nav_info
+            .info
+            .iter()
+            .filter_map(|nav| {
+                if nav.file_id == self.file_id {
+                    Some(to_proto::location_link(selection, nav, line_index))
+                } else {
+                    self.library_location_link(selection, nav)
+                }
+            })
+            .collect()
+    }
+
+    /// Link to a target in a library file, at the line of the original file
+    /// it was packed from. The packed text of the target is sent along for the
+    /// editor to show at that line.
+    fn library_location_link(
+        &self,
+        selection: Range,
+        nav: &NavigationTarget,
+    ) -> Option<LocationLink> {
+        let source_map = self.source_maps.get(&nav.file_id)?;
+        let text = self.analysis().file_text(nav.file_id).ok()?;
+        let line_index = self.analysis().file_line_index(nav.file_id).ok()?;
+        let (path, line) = source_map.locate(&text, nav.full_range.start())?;
+
+        let first_line = line_index.line_col(nav.full_range.start()).line;
+        let source_start = line_index.offset(LineCol { line: first_line, col: 0 });
+        let move_range = |mut range: Range| {
+            range.startLineNumber = range.startLineNumber - first_line + line;
+            range.endLineNumber = range.endLineNumber - first_line + line;
+            range
+        };
+        let mut link = to_proto::location_link(selection, nav, &line_index);
+        link.range = move_range(link.range);
+        link.targetSelectionRange = move_range(link.targetSelectionRange);
+        link.path = Some(path.to_string());
+        link.source = Some(text[TextRange::new(source_start, nav.full_range.end())].to_string());
+        Some(link)
+    }
+
+    /// Original file and line of the library definition at `pos`.
+    fn definition_origin(&self, pos: FilePosition) -> Option<(String, u32)> {
+        let nav_info = self.analysis().goto_definition(pos).ok()??;
+        nav_info.info.iter().find_map(|nav| {
+            let source_map = self.source_maps.get(&nav.file_id)?;
+            let text = self.analysis().file_text(nav.file_id).ok()?;
+            let (path, line) = source_map.locate(&text, nav.focus_or_full_range().start())?;
+            Some((path.to_string(), line))
+        })
+    }
+
+    fn load(&mut self, code: String, crates: Vec<bundle::BundleCrate>) {
+        if let Some(priming) = self.priming.take() {
+            priming.stop();
+        }
+        self.pending = None;
+        let (host, file_id) = from_single_file(String::new(), &crates);
+        self.host = host;
+        self.file_id = file_id;
+        self.library_index = library_index::LibraryIndex::new(&crates);
+        self.source_maps = crates
+            .into_iter()
+            .enumerate()
+            .filter_map(|(idx, it)| {
+                Some((FileId(FIRST_LIBRARY_FILE + idx as u32), it.into_source_map()?))
+            })
+            .collect();
+        self.code_len = TextSize::from(0);
+        self.client = String::new();
+        self.set_code(code);
+    }
+
+    fn prime_caches(&mut self) {
+        let import_maps = self.library_index.is_none();
+        self.priming = Some(prime_caches::Priming::start(&self.host, import_maps));
+    }
 }
 
 #[wasm_bindgen]
 impl WorldState {
     #[wasm_bindgen(constructor)]
     pub fn new() -> Self {
-        let (host, file_id) =
-            from_single_file("".to_owned(), "".to_owned(), "".to_owned(), "".to_owned(), "".to_owned());
-        Self { host, file_id }
+        let (host, file_id) = from_single_file("".to_owned(), &[]);
+        Self {
+            host,
+            file_id,
+            code_len: TextSize::from(0),
+            client: String::new(),
+            source_maps: HashMap::new(),
+            library_index: None,
+            priming: None,
+            pending: None,
+        }
     }
 
-    pub fn init(&mut self, code: String, fake_std: String, fake_core: String, fake_alloc: String, fake_soroban_sdk: String) {
-        let (host, file_id) = from_single_file(code, fake_std, fake_core, fake_alloc, fake_soroban_sdk);
-        self.host = host;
-        self.file_id = file_id;
+    /// Loads `code` as the user's crate, depending on the root crates of
+    /// `crates`, a list of bundle crates emitted by `rust-pack`, and starts
+    /// priming the caches, see [`WorldState::prime_progress`].
+    pub fn init(&mut self, code: String, crates: JsValue) {
+        let crates: Vec<bundle::BundleCrate> = serde_wasm_bindgen::from_value(crates).unwrap();
+        self.load(code, crates);
+        self.prime_caches();
     }
 
+    /// Like [`WorldState::init`], with the crates of `bundle`, the compressed
+    /// `bundle.bin` emitted by `rust-pack`.
+    pub fn init_bundle(&mut self, code: String, bundle: &[u8]) -> Result<(), JsValue> {
+        let crates = bundle::decode(bundle).map_err(|e| JsValue::from_str(&e))?;
+        self.load(code, crates);
+        self.prime_caches();
+        Ok(())
+    }
+
+    /// The `PrimeCachesProgress` of the priming started by the last
+    /// [`WorldState::init`], `null` before. Doesn't wait for it.
+    pub fn prime_progress(&self) -> JsValue {
+        match &self.priming {
+            Some(priming) => serde_wasm_bindgen::to_value(&priming.progress()).unwrap(),
+            None => JsValue::NULL,
+        }
+    }
+
+    /// The `UpdateResult` of setting the user's code to `code`, or `null` if
+    /// it can't be applied yet because of the crates being primed. Then the
+    /// other requests return `null` too, until an update goes through.
     pub fn update(&mut self, code: String) -> JsValue {
         log::warn!("update");
         let file_id = FileId(0);
-        let mut change = Change::new();
-        change.change_file(file_id, Some(Arc::new(code)));
-        self.host.apply_change(change);
+        if !self.set_code(code) {
+            return JsValue::NULL;
+        }
 
         let line_index = self.analysis().file_line_index(self.file_id).unwrap();
 
@@ -152,6 +331,7 @@ impl WorldState {
             .highlight(file_id)
             .unwrap()
             .into_iter()
+            .filter(|hl| self.in_code(hl.range))
             .map(|hl| Highlight {
                 tag: Some(hl.highlight.tag.to_string()),
                 range: to_proto::text_range(hl.range, &line_index),
@@ -160,16 +340,17 @@ impl WorldState {
 
         let config = DiagnosticsConfig::default();
 
-        let diagnostics: Vec<_> = self
+        let mut diagnostics: Vec<_> = self
             .analysis()
             .diagnostics(&config, ide::AssistResolveStrategy::All, file_id)
             .unwrap()
             .into_iter()
             .map(|d| {
                 let Range { startLineNumber, startColumn, endLineNumber, endColumn } =
-                    to_proto::text_range(d.range, &line_index);
+                    to_proto::text_range(self.clamp_to_code(d.range), &line_index);
                 Diagnostic {
                     message: d.message,
+                    code: Some(d.code.as_str().to_string()),
                     severity: to_proto::severity(d.severity),
                     startLineNumber,
                     startColumn,
@@ -179,10 +360,21 @@ impl WorldState {
             })
             .collect();
 
+        let source_file = self.analysis().parse(file_id).unwrap();
+        diagnostics.extend(
+            lints::run(&source_file)
+                .into_iter()
+                .filter(|d| self.in_code(d.range))
+                .map(|d| to_proto::lint_diagnostic(d, &line_index)),
+        );
+
         serde_wasm_bindgen::to_value(&UpdateResult { diagnostics, highlights }).unwrap()
     }
 
     pub fn inlay_hints(&self) -> JsValue {
+        if self.is_stale() {
+            return JsValue::NULL;
+        }
         let line_index = self.analysis().file_line_index(self.file_id).unwrap();
         let results: Vec<_> = self
             .analysis()
@@ -210,20 +402,25 @@ impl WorldState {
     }
 
     pub fn completions(&self, line_number: u32, column: u32) -> JsValue {
-        const COMPLETION_CONFIG: CompletionConfig = CompletionConfig {
+        if self.is_stale() {
+            return JsValue::NULL;
+        }
+        const INSERT_USE_CONFIG: InsertUseConfig = InsertUseConfig {
+            granularity: ImportGranularity::Module,
+            enforce_granularity: false,
+            prefix_kind: PrefixKind::Plain,
+            group: true,
+            skip_glob_imports: false,
+        };
+        let completion_config = CompletionConfig {
             enable_postfix_completions: true,
-            enable_imports_on_the_fly: true,
+            // Library items are imported from the index instead.
+            enable_imports_on_the_fly: self.library_index.is_none(),
             enable_self_on_the_fly: true,
             add_call_parenthesis: true,
             add_call_argument_snippets: true,
             snippet_cap: SnippetCap::new(true),
-            insert_use: InsertUseConfig {
-                granularity: ImportGranularity::Module,
-                enforce_granularity: false,
-                prefix_kind: PrefixKind::Plain,
-                group: true,
-                skip_glob_imports: false,
-            },
+            insert_use: INSERT_USE_CONFIG,
             snippets: Vec::new(),
         };
 
@@ -231,17 +428,27 @@ impl WorldState {
         let line_index = self.analysis().file_line_index(self.file_id).unwrap();
 
         let pos = file_position(line_number, column, &line_index, self.file_id);
-        let res = match self.analysis().completions(&COMPLETION_CONFIG, pos).unwrap() {
+        let res = match self.analysis().completions(&completion_config, pos).unwrap() {
             Some(items) => items,
             None => return JsValue::NULL,
         };
 
-        let items: Vec<_> =
-            res.into_iter().map(|item| to_proto::completion_item(item, &line_index)).collect();
+        let mut items: Vec<_> = res
+            .into_iter()
+            .filter(|item| item.label() != client_gen::CLIENT_MOD_NAME)
+            .map(|item| to_proto::completion_item(item, &line_index))
+            .collect();
+        if let Some(index) = &self.library_index {
+            let db = self.host.raw_database();
+            items.extend(index.completions(db, pos, &INSERT_USE_CONFIG, &line_index, &items));
+        }
         serde_wasm_bindgen::to_value(&items).unwrap()
     }
 
     pub fn hover(&self, line_number: u32, column: u32) -> JsValue {
+        if self.is_stale() {
+            return JsValue::NULL;
+        }
         log::warn!("hover");
         let line_index = self.analysis().file_line_index(self.file_id).unwrap();
 
@@ -261,7 +468,11 @@ impl WorldState {
             _ => return JsValue::NULL,
         };
 
-        let value = info.info.markup.to_string();
+        let mut value = info.info.markup.to_string();
+        let pos = file_position(line_number, column, &line_index, self.file_id);
+        if let Some((path, line)) = self.definition_origin(pos) {
+            value.push_str(&format!("\n\n---\n\nDefined in `{}:{}`", path, line + 1));
+        }
         let hover = Hover {
             contents: vec![MarkdownString { value }],
             range: to_proto::text_range(info.range, &line_index),
@@ -271,10 +482,13 @@ impl WorldState {
     }
 
     pub fn code_lenses(&self) -> JsValue {
+        if self.is_stale() {
+            return JsValue::NULL;
+        }
         log::warn!("code_lenses");
         let line_index = self.analysis().file_line_index(self.file_id).unwrap();
 
-        let results: Vec<_> = self
+        let mut results: Vec<_> = self
             .analysis()
             .file_structure(self.file_id)
             .unwrap()
@@ -288,6 +502,7 @@ impl WorldState {
                 ),
                 ide::StructureNodeKind::Region => true,
             })
+            .filter(|it| self.in_code(it.node_range))
             .filter_map(|it| {
                 let position =
                     FilePosition { file_id: self.file_id, offset: it.node_range.start() };
@@ -317,10 +532,21 @@ impl WorldState {
             })
             .collect();
 
+        if let Ok(source_file) = self.analysis().parse(self.file_id) {
+            results.extend(
+                auth::analyze(&source_file)
+                    .into_iter()
+                    .map(|it| to_proto::auth_code_lens(it, &line_index)),
+            );
+        }
+
         serde_wasm_bindgen::to_value(&results).unwrap()
     }
 
     pub fn references(&self, line_number: u32, column: u32, include_declaration: bool) -> JsValue {
+        if self.is_stale() {
+            return JsValue::NULL;
+        }
         log::warn!("references");
         let line_index = self.analysis().file_line_index(self.file_id).unwrap();
 
@@ -351,6 +577,9 @@ impl WorldState {
     }
 
     pub fn prepare_rename(&self, line_number: u32, column: u32) -> JsValue {
+        if self.is_stale() {
+            return JsValue::NULL;
+        }
         log::warn!("prepare_rename");
         let line_index = self.analysis().file_line_index(self.file_id).unwrap();
 
@@ -368,6 +597,9 @@ impl WorldState {
     }
 
     pub fn rename(&self, line_number: u32, column: u32, new_name: &str) -> JsValue {
+        if self.is_stale() {
+            return JsValue::NULL;
+        }
         log::warn!("rename");
         let line_index = self.analysis().file_line_index(self.file_id).unwrap();
 
@@ -380,6 +612,7 @@ impl WorldState {
         let result: Vec<_> = change
             .source_file_edits
             .iter()
+            .filter(|(file_id, _)| **file_id == self.file_id)
             .flat_map(|(_, edit)| edit.iter())
             .map(|atom: &Indel| to_proto::text_edit(atom, &line_index))
             .collect();
@@ -388,6 +621,9 @@ impl WorldState {
     }
 
     pub fn signature_help(&self, line_number: u32, column: u32) -> JsValue {
+        if self.is_stale() {
+            return JsValue::NULL;
+        }
         log::warn!("signature_help");
         let line_index = self.analysis().file_line_index(self.file_id).unwrap();
 
@@ -409,6 +645,9 @@ impl WorldState {
     }
 
     pub fn definition(&self, line_number: u32, column: u32) -> JsValue {
+        if self.is_stale() {
+            return JsValue::NULL;
+        }
         log::warn!("definition");
         let line_index = self.analysis().file_line_index(self.file_id).unwrap();
 
@@ -418,11 +657,14 @@ impl WorldState {
             _ => return JsValue::NULL,
         };
 
-        let res = to_proto::location_links(nav_info, &line_index);
+        let res = self.location_links(nav_info, &line_index);
         serde_wasm_bindgen::to_value(&res).unwrap()
     }
 
     pub fn type_definition(&self, line_number: u32, column: u32) -> JsValue {
+        if self.is_stale() {
+            return JsValue::NULL;
+        }
         log::warn!("type_definition");
         let line_index = self.analysis().file_line_index(self.file_id).unwrap();
 
@@ -432,11 +674,14 @@ impl WorldState {
             _ => return JsValue::NULL,
         };
 
-        let res = to_proto::location_links(nav_info, &line_index);
+        let res = self.location_links(nav_info, &line_index);
         serde_wasm_bindgen::to_value(&res).unwrap()
     }
 
     pub fn document_symbols(&self) -> JsValue {
+        if self.is_stale() {
+            return JsValue::NULL;
+        }
         log::warn!("document_symbols");
         let line_index = self.analysis().file_line_index(self.file_id).unwrap();
 
@@ -446,7 +691,9 @@ impl WorldState {
         };
         let mut parents: Vec<(DocumentSymbol, Option<usize>)> = Vec::new();
 
-        for symbol in struct_nodes {
+        // The client module declaration comes last, so skipping it keeps the
+        // parent indices valid.
+        for symbol in struct_nodes.into_iter().filter(|it| self.in_code(it.node_range)) {
             let doc_symbol = DocumentSymbol {
                 name: symbol.label.clone(),
                 detail: symbol.detail.unwrap_or(symbol.label),
@@ -477,6 +724,9 @@ impl WorldState {
     }
 
     pub fn type_formatting(&self, line_number: u32, column: u32, ch: char) -> JsValue {
+        if self.is_stale() {
+            return JsValue::NULL;
+        }
         log::warn!("type_formatting");
         let line_index = self.analysis().file_line_index(self.file_id).unwrap();
 
@@ -495,18 +745,82 @@ impl WorldState {
     }
 
     pub fn folding_ranges(&self) -> JsValue {
+        if self.is_stale() {
+            return JsValue::NULL;
+        }
         log::warn!("folding_ranges");
         let line_index = self.analysis().file_line_index(self.file_id).unwrap();
         if let Ok(folds) = self.analysis().folding_ranges(self.file_id) {
-            let res: Vec<_> =
-                folds.into_iter().map(|fold| to_proto::folding_range(fold, &line_index)).collect();
+            let res: Vec<_> = folds
+                .into_iter()
+                .filter(|fold| self.in_code(fold.range))
+                .map(|fold| to_proto::folding_range(fold, &line_index))
+                .collect();
             serde_wasm_bindgen::to_value(&res).unwrap()
         } else {
             JsValue::NULL
         }
     }
 
+    pub fn code_actions(
+        &self,
+        start_line_number: u32,
+        start_column: u32,
+        end_line_number: u32,
+        end_column: u32,
+    ) -> JsValue {
+        if self.is_stale() {
+            return JsValue::NULL;
+        }
+        log::warn!("code_actions");
+        let line_index = self.analysis().file_line_index(self.file_id).unwrap();
+
+        let range = file_range(
+            start_line_number,
+            start_column,
+            end_line_number,
+            end_column,
+            &line_index,
+            self.file_id,
+        )
+        .range;
+        let source_file = match self.analysis().parse(self.file_id) {
+            Ok(it) => it,
+            _ => return JsValue::NULL,
+        };
+
+        let actions: Vec<_> = lints::run(&source_file)
+            .into_iter()
+            .filter(|d| d.range.intersect(range).is_some())
+            .filter_map(|d| {
+                let fix = d.fix?;
+                Some(CodeAction {
+                    title: fix.label,
+                    code: d.code.to_string(),
+                    edits: to_proto::text_edits(fix.edit, &line_index),
+                })
+            })
+            .collect();
+        serde_wasm_bindgen::to_value(&actions).unwrap()
+    }
+
+    pub fn contract_spec(&self) -> JsValue {
+        if self.is_stale() {
+            return JsValue::NULL;
+        }
+        log::warn!("contract_spec");
+        let source_file = match self.analysis().parse(self.file_id) {
+            Ok(it) => it,
+            _ => return JsValue::NULL,
+        };
+        let spec = contract_spec::contract_spec(&source_file);
+        serde_wasm_bindgen::to_value(&spec).unwrap()
+    }
+
     pub fn goto_implementation(&self, line_number: u32, column: u32) -> JsValue {
+        if self.is_stale() {
+            return JsValue::NULL;
+        }
         log::warn!("goto_implementation");
         let line_index = self.analysis().file_line_index(self.file_id).unwrap();
 
@@ -515,7 +829,7 @@ impl WorldState {
             Ok(Some(it)) => it,
             _ => return JsValue::NULL,
         };
-        let res = to_proto::location_links(nav_info, &line_index);
+        let res = self.location_links(nav_info, &line_index);
         serde_wasm_bindgen::to_value(&res).unwrap()
     }
 }
@@ -555,3 +869,132 @@ fn file_range(
         ),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    const CODE: &str = r#"#![no_std]
+use soroban_sdk::{contract, contractimpl, Env};
+
+#[contract]
+pub struct Counter;
+
+#[contractimpl]
+impl Counter {
+    pub fn increment(env: Env, by: u32) -> u32 {
+        by
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test() {
+        let env = Env::default();
+        let id = env.register(Counter, ());
+        let client = CounterClient::new(&env, &id);
+        client.increment(&1);
+    }
+}
+"#;
+
+    fn fake_crate(name: &str, source: &str, deps: &[&str]) -> bundle::BundleCrate {
+        let deps: Vec<_> = deps.iter().map(|it| json!({ "crate": it, "name": it })).collect();
+        let proc_macro = name.ends_with("_macros");
+        serde_json::from_value(json!({
+            "id": name,
+            "name": name,
+            "edition": "2018",
+            "cfg": ["unix"],
+            "features": [],
+            "deps": deps,
+            "proc_macro": proc_macro,
+            "root": !proc_macro,
+            "source": source,
+        }))
+        .unwrap()
+    }
+
+    /// The user's code loaded with the fake libraries of the editor.
+    fn world_state() -> WorldState {
+        let crates = vec![
+            fake_crate("core", "pub mod marker { pub struct PhantomData<T: ?Sized>; }", &[]),
+            fake_crate("alloc", include_str!("../../www/fake_alloc.rs"), &["core"]),
+            fake_crate("std", include_str!("../../www/fake_std.rs"), &["core", "alloc"]),
+            fake_crate("soroban_sdk_macros", "", &[]),
+            fake_crate(
+                "soroban_sdk",
+                include_str!("../../www/fake_soroban_sdk.rs"),
+                &["core", "alloc", "soroban_sdk_macros"],
+            ),
+        ];
+        let mut state = WorldState::new();
+        state.load(CODE.to_string(), crates);
+        state
+    }
+
+    /// 1-based line and column right after `prefix` in `CODE`.
+    fn position_after(prefix: &str) -> (u32, u32) {
+        let offset = CODE.find(prefix).unwrap() + prefix.len();
+        let line_start = CODE[..offset].rfind('\n').map_or(0, |it| it + 1);
+        (CODE[..offset].matches('\n').count() as u32 + 1, (offset - line_start) as u32 + 1)
+    }
+
+    fn to_json(value: JsValue) -> Value {
+        serde_wasm_bindgen::from_value(value).unwrap()
+    }
+
+    #[wasm_bindgen_test]
+    fn client_methods_complete() {
+        let state = world_state();
+        let (line, column) = position_after("client.");
+        let items = to_json(state.completions(line, column));
+        let labels: Vec<_> = items
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|it| Some(it["label"].as_str()?.trim_end_matches("(…)")))
+            .collect();
+        assert!(labels.contains(&"increment"), "{:?}", labels);
+        assert!(labels.contains(&"try_increment"), "{:?}", labels);
+        assert!(!labels.contains(&client_gen::CLIENT_MOD_NAME), "{:?}", labels);
+    }
+
+    #[wasm_bindgen_test]
+    fn client_methods_hover() {
+        let state = world_state();
+        let (line, column) = position_after("client.incr");
+        let hover = to_json(state.hover(line, column));
+        let value = hover["contents"][0]["value"].as_str().unwrap();
+        assert!(value.contains("pub fn increment(&self, by: &u32) -> u32"), "{}", value);
+
+        let (line, column) = position_after("CounterClient::n");
+        let hover = to_json(state.hover(line, column));
+        let value = hover["contents"][0]["value"].as_str().unwrap();
+        assert!(value.contains("CounterClient"), "{}", value);
+    }
+
+    #[wasm_bindgen_test]
+    fn client_module_is_hidden() {
+        let mut state = world_state();
+        let text = state.analysis().file_text(state.file_id).unwrap();
+        assert_eq!(&text[..CODE.len()], CODE);
+        assert!(text.ends_with(client_gen::CLIENT_MOD_DECL));
+        let symbols = to_json(state.document_symbols());
+        let names: Vec<_> =
+            symbols.as_array().unwrap().iter().filter_map(|it| it["name"].as_str()).collect();
+        assert_eq!(names.len(), 3);
+        assert!(!names.contains(&client_gen::CLIENT_MOD_NAME), "{:?}", names);
+
+        // Until the block is closed, the declaration would end up inside it.
+        let code = format!("{}\nfn unclosed() {{\n", CODE);
+        state.update(code.clone());
+        assert_eq!(*state.analysis().file_text(state.file_id).unwrap(), code);
+    }
+}
@@ -0,0 +1,164 @@
+//! Completion of library items with an import, from the index `rust-pack`
+//! puts in bundles.
+//!
+//! rust-analyzer's own import completion builds the import map of every
+//! library crate on the first completion, which takes seconds for `std`. With
+//! an index, it is turned off and only the user's crate is indexed.
+use std::collections::{HashMap, HashSet};
+
+use hir::Semantics;
+use ide::{FilePosition, LineIndex, TextEdit};
+use ide_db::{
+    helpers::insert_use::{insert_use, ImportScope, InsertUseConfig},
+    RootDatabase,
+};
+use syntax::{algo, ast, AstNode, SyntaxKind};
+
+use crate::{bundle::BundleCrate, return_types, to_proto};
+
+/// More matches than that aren't worth computing imports for.
+const MAX_COMPLETIONS: usize = 40;
+
+pub(crate) struct LibraryIndex {
+    /// Sorted by lowercased name.
+    entries: Vec<Entry>,
+}
+
+struct Entry {
+    name: String,
+    lowercase_name: String,
+    /// Full path, starting with the extern name of the crate.
+    path: String,
+    kind: String,
+}
+
+impl LibraryIndex {
+    /// The index of the crates the user's code depends on, `None` if the
+    /// bundle has none.
+    pub(crate) fn new(crates: &[BundleCrate]) -> Option<LibraryIndex> {
+        // The best path of each item, preferring `std` over the crates it
+        // re-exports.
+        let mut best: HashMap<u32, (u8, usize, String, &str)> = HashMap::new();
+        for (krate, exports) in crates.iter().filter_map(BundleCrate::root_exports) {
+            let rank = match krate {
+                "std" => 0,
+                "alloc" => 2,
+                "core" => 3,
+                _ => 1,
+            };
+            for export in exports {
+                let path = format!("{}::{}", krate, export.path);
+                let key = (rank, path.matches("::").count(), path, export.kind.as_str());
+                match best.get(&export.item) {
+                    Some(it) if (it.0, it.1, &it.2) <= (key.0, key.1, &key.2) => {}
+                    _ => {
+                        best.insert(export.item, key);
+                    }
+                }
+            }
+        }
+        if best.is_empty() {
+            return None;
+        }
+        let mut entries: Vec<_> = best
+            .into_iter()
+            .map(|(_, (_, _, path, kind))| {
+                let name = path.rsplit("::").next().unwrap_or_default().to_string();
+                Entry { lowercase_name: name.to_lowercase(), name, path, kind: kind.to_string() }
+            })
+            .collect();
+        entries.sort_by(|a, b| (&a.lowercase_name, &a.path).cmp(&(&b.lowercase_name, &b.path)));
+        Some(LibraryIndex { entries })
+    }
+
+    fn starting_with(&self, prefix: &str) -> impl Iterator<Item = &Entry> {
+        let prefix = prefix.to_lowercase();
+        let start = self.entries.partition_point(|it| it.lowercase_name < prefix);
+        self.entries[start..].iter().take_while(move |it| it.lowercase_name.starts_with(&prefix))
+    }
+
+    /// Items whose name starts with the identifier being typed at `pos`,
+    /// except the ones already in `completions`, with the edit importing them.
+    pub(crate) fn completions(
+        &self,
+        db: &RootDatabase,
+        pos: FilePosition,
+        config: &InsertUseConfig,
+        line_index: &LineIndex,
+        completions: &[return_types::CompletionItem],
+    ) -> Vec<return_types::CompletionItem> {
+        let sema = Semantics::new(db);
+        let file = sema.parse(pos.file_id);
+        let token = match file.syntax().token_at_offset(pos.offset).left_biased() {
+            Some(it) if it.kind() == SyntaxKind::IDENT => it,
+            _ => return Vec::new(),
+        };
+        // Only the first segment of paths, outside of imports.
+        let name_ref = match token.parent().and_then(ast::NameRef::cast) {
+            Some(it) => it,
+            None => return Vec::new(),
+        };
+        let path = match name_ref.syntax().ancestors().find_map(ast::Path::cast) {
+            Some(it) if it.qualifier().is_none() => it,
+            _ => return Vec::new(),
+        };
+        if path.syntax().ancestors().any(|it| ast::UseTree::can_cast(it.kind())) {
+            return Vec::new();
+        }
+        let scope = match ImportScope::find_insert_use_container(name_ref.syntax(), &sema) {
+            Some(it) => it,
+            None => return Vec::new(),
+        };
+        let prefix = &token.text()[..usize::from(pos.offset - token.text_range().start())];
+        if prefix.is_empty() {
+            return Vec::new();
+        }
+
+        let existing: HashSet<_> = completions.iter().map(|it| it.label.as_str()).collect();
+        let range = to_proto::text_range(token.text_range(), line_index);
+        self.starting_with(prefix)
+            .map(|entry| {
+                let label = if entry.kind == "macro" {
+                    format!("{}!", entry.name)
+                } else {
+                    entry.name.clone()
+                };
+                (entry, label)
+            })
+            .filter(|(_, label)| !existing.contains(label.as_str()))
+            .take(MAX_COMPLETIONS)
+            .map(|(entry, label)| {
+                let new_scope = scope.clone_for_update();
+                insert_use(&new_scope, ast::make::path_from_text(&entry.path), config);
+                let mut import = TextEdit::builder();
+                algo::diff(scope.as_syntax_node(), new_scope.as_syntax_node())
+                    .into_text_edit(&mut import);
+                return_types::CompletionItem {
+                    label: label.clone(),
+                    range,
+                    kind: completion_item_kind(&entry.kind),
+                    detail: Some(entry.path.clone()),
+                    insertText: label,
+                    insertTextRules: return_types::CompletionItemInsertTextRule::None,
+                    documentation: None,
+                    filterText: entry.name.clone(),
+                    additionalTextEdits: to_proto::text_edits(import.finish(), line_index),
+                }
+            })
+            .collect()
+    }
+}
+
+fn completion_item_kind(kind: &str) -> return_types::CompletionItemKind {
+    use return_types::CompletionItemKind::*;
+    match kind {
+        "fn" | "macro" => Function,
+        "struct" | "union" => Struct,
+        "enum" => Enum,
+        "trait" => Interface,
+        "const" => Constant,
+        "static" | "type" => Value,
+        "mod" => Module,
+        _ => User,
+    }
+}
@@ -0,0 +1,578 @@
+//! Soroban-specific lints, reported next to rust-analyzer's own diagnostics.
+//!
+//! Every lint is a [`Lint`] pass over the syntax tree of the user file; adding
+//! a new one only requires listing it in [`LINTS`].
+use ide::{TextEdit, TextRange, TextSize};
+use syntax::{
+    ast::{self, HasAttrs, HasModuleItem, HasName, HasVisibility},
+    AstNode, SourceFile,
+};
+
+use crate::{
+    auth::MissingAuth,
+    return_types::MarkerSeverity,
+    soroban::{self, EntryPoint},
+};
+
+pub(crate) struct LintDiagnostic {
+    pub(crate) code: &'static str,
+    pub(crate) message: String,
+    pub(crate) severity: MarkerSeverity,
+    pub(crate) range: TextRange,
+    pub(crate) fix: Option<LintFix>,
+}
+
+pub(crate) struct LintFix {
+    pub(crate) label: String,
+    pub(crate) edit: TextEdit,
+}
+
+pub(crate) trait Lint: Sync {
+    fn check(&self, file: &SourceFile, acc: &mut Vec<LintDiagnostic>);
+}
+
+static LINTS: &[&dyn Lint] = &[
+    &NonPubEntryPoint,
+    &MissingNoStd,
+    &PanicInEntryPoint,
+    &StorageWithoutTtl,
+    &StdUsage,
+    &MissingAuth,
+];
+
+pub(crate) fn run(file: &SourceFile) -> Vec<LintDiagnostic> {
+    let mut acc = Vec::new();
+    if !soroban::is_contract(file) {
+        return acc;
+    }
+    for lint in LINTS {
+        lint.check(file, &mut acc);
+    }
+    acc
+}
+
+/// `#[contractimpl]` only exports `pub` functions of inherent impls.
+struct NonPubEntryPoint;
+
+impl Lint for NonPubEntryPoint {
+    fn check(&self, file: &SourceFile, acc: &mut Vec<LintDiagnostic>) {
+        for entry in soroban::entry_points(file) {
+            if entry.is_trait_impl() || entry.func.visibility().is_some() {
+                continue;
+            }
+            let name = match entry.func.name() {
+                Some(name) => name,
+                None => continue,
+            };
+            let func = &entry.func;
+            let offset = match [
+                func.default_token(),
+                func.const_token(),
+                func.async_token(),
+                func.unsafe_token(),
+                func.fn_token(),
+            ]
+            .iter()
+            .flatten()
+            .map(|it| it.text_range().start())
+            .min()
+            {
+                Some(offset) => offset,
+                None => continue,
+            };
+            acc.push(LintDiagnostic {
+                code: "soroban-non-pub-entry-point",
+                message: format!(
+                    "`{}` is not `pub`, so `#[contractimpl]` won't export it from the contract",
+                    name
+                ),
+                severity: MarkerSeverity::Warning,
+                range: name.syntax().text_range(),
+                fix: Some(LintFix {
+                    label: format!("Make `{}` public", name),
+                    edit: TextEdit::insert(offset, "pub ".to_string()),
+                }),
+            });
+        }
+    }
+}
+
+/// Contracts are built for `wasm32-unknown-unknown` without the standard library.
+struct MissingNoStd;
+
+impl Lint for MissingNoStd {
+    fn check(&self, file: &SourceFile, acc: &mut Vec<LintDiagnostic>) {
+        let has_no_std = file.attrs().any(|attr| match attr.simple_name().as_deref() {
+            Some("no_std") => true,
+            Some("cfg_attr") => matches!(
+                attr.token_tree(),
+                Some(tt) if tt.syntax().text().to_string().contains("no_std")
+            ),
+            _ => false,
+        });
+        if has_no_std {
+            return;
+        }
+        let contract = file
+            .syntax()
+            .descendants()
+            .filter_map(ast::Struct::cast)
+            .find_map(|it| soroban::find_attr(&it, "contract"));
+        let range = match contract {
+            Some(attr) => attr.syntax().text_range(),
+            None => return,
+        };
+        let (offset, text) = after_inner_attrs(file, "#![no_std]");
+        acc.push(LintDiagnostic {
+            code: "soroban-missing-no-std",
+            message: "contract crates must be `#![no_std]`".to_string(),
+            severity: MarkerSeverity::Error,
+            range,
+            fix: Some(LintFix {
+                label: "Add `#![no_std]`".to_string(),
+                edit: TextEdit::insert(offset, text),
+            }),
+        });
+    }
+}
+
+/// Panicking aborts the invocation with an opaque error; entry points should
+/// return a `#[contracterror]` or use `panic_with_error!`.
+struct PanicInEntryPoint;
+
+impl Lint for PanicInEntryPoint {
+    fn check(&self, file: &SourceFile, acc: &mut Vec<LintDiagnostic>) {
+        for EntryPoint { func, .. } in soroban::entry_points(file) {
+            let (body, name) = match (func.body(), func.name()) {
+                (Some(body), Some(name)) => (body, name),
+                _ => continue,
+            };
+            for node in body.syntax().descendants() {
+                let (what, range) = if let Some(call) = ast::MethodCallExpr::cast(node.clone()) {
+                    match call.name_ref() {
+                        Some(it) if it.text() == "unwrap" || it.text() == "expect" => {
+                            (format!("`{}`", it.text()), it.syntax().text_range())
+                        }
+                        _ => continue,
+                    }
+                } else if let Some(mac) = ast::MacroCall::cast(node) {
+                    match mac.path().and_then(|it| it.segment()?.name_ref()) {
+                        Some(it)
+                            if ["panic", "unreachable", "todo", "unimplemented"]
+                                .contains(&it.text().as_str()) =>
+                        {
+                            (format!("`{}!`", it.text()), mac.syntax().text_range())
+                        }
+                        _ => continue,
+                    }
+                } else {
+                    continue;
+                };
+                acc.push(LintDiagnostic {
+                    code: "soroban-panic-in-entry-point",
+                    message: format!(
+                        "{} in entry point `{}` aborts the invocation without an error code; \
+                         return a `#[contracterror]` or use `panic_with_error!`",
+                        what, name
+                    ),
+                    severity: MarkerSeverity::Warning,
+                    range,
+                    fix: None,
+                });
+            }
+        }
+    }
+}
+
+/// Persistent, temporary and instance entries get archived or deleted once
+/// their TTL runs out, so writes should be paired with `extend_ttl`.
+struct StorageWithoutTtl;
+
+impl Lint for StorageWithoutTtl {
+    fn check(&self, file: &SourceFile, acc: &mut Vec<LintDiagnostic>) {
+        for EntryPoint { func, .. } in soroban::entry_points(file) {
+            let body = match func.body() {
+                Some(body) => body,
+                None => continue,
+            };
+            let calls: Vec<_> =
+                body.syntax().descendants().filter_map(ast::MethodCallExpr::cast).collect();
+            let is_extended = |kind| {
+                calls.iter().any(|call| {
+                    soroban::method_name(call).as_deref() == Some("extend_ttl")
+                        && soroban::storage_kind(call) == Some(kind)
+                })
+            };
+            for call in &calls {
+                if soroban::method_name(call).as_deref() != Some("set") {
+                    continue;
+                }
+                let kind = match soroban::storage_kind(call) {
+                    Some(kind) if !is_extended(kind) => kind,
+                    _ => continue,
+                };
+                acc.push(LintDiagnostic {
+                    code: "soroban-storage-without-ttl",
+                    message: format!(
+                        "write to {} storage without extending its TTL with `extend_ttl`",
+                        kind
+                    ),
+                    severity: MarkerSeverity::Warning,
+                    range: call.syntax().text_range(),
+                    fix: None,
+                });
+            }
+        }
+    }
+}
+
+/// Contracts are `no_std`; everything they need lives in `core`, `alloc` or
+/// `soroban_sdk`.
+struct StdUsage;
+
+impl Lint for StdUsage {
+    fn check(&self, file: &SourceFile, acc: &mut Vec<LintDiagnostic>) {
+        for node in file.syntax().descendants() {
+            if soroban::is_test_code(&node) {
+                continue;
+            }
+            let name_ref = if let Some(path) = ast::Path::cast(node.clone()) {
+                if path.qualifier().is_some() {
+                    continue;
+                }
+                match path.segment().and_then(|it| it.name_ref()) {
+                    Some(it) => it,
+                    None => continue,
+                }
+            } else if let Some(extern_crate) = ast::ExternCrate::cast(node) {
+                match extern_crate.name_ref() {
+                    Some(it) => it,
+                    None => continue,
+                }
+            } else {
+                continue;
+            };
+            if name_ref.text() != "std" {
+                continue;
+            }
+            let range = name_ref.syntax().text_range();
+            // The segments after `std::`, from the paths `std` qualifies,
+            // past the name's segment and path.
+            let rest: Vec<_> = name_ref
+                .syntax()
+                .ancestors()
+                .skip(3)
+                .map_while(ast::Path::cast)
+                .filter_map(|it| Some(it.segment()?.name_ref()?.text().to_string()))
+                .take(2)
+                .collect();
+            let fix = no_std_crate(&rest).map(|krate| {
+                let mut edit = TextEdit::builder();
+                if krate == "alloc" && !has_extern_crate(file, "alloc") {
+                    let (offset, text) = after_inner_attrs(file, "extern crate alloc;");
+                    edit.insert(offset, text);
+                }
+                edit.replace(range, krate.to_string());
+                LintFix { label: format!("Replace `std` with `{}`", krate), edit: edit.finish() }
+            });
+            acc.push(LintDiagnostic {
+                code: "soroban-std-usage",
+                message: "`std` is not available in contracts, use `core`, `alloc` or \
+                          `soroban_sdk`"
+                    .to_string(),
+                severity: MarkerSeverity::Error,
+                range,
+                fix,
+            });
+        }
+    }
+}
+
+/// Modules and macros of `std` that are `core`'s.
+const CORE_ITEMS: &[&str] = &[
+    "any",
+    "array",
+    "ascii",
+    "assert",
+    "assert_eq",
+    "assert_ne",
+    "cell",
+    "char",
+    "clone",
+    "cmp",
+    "convert",
+    "debug_assert",
+    "debug_assert_eq",
+    "debug_assert_ne",
+    "default",
+    "f32",
+    "f64",
+    "fmt",
+    "future",
+    "hash",
+    "hint",
+    "i8",
+    "i16",
+    "i32",
+    "i64",
+    "i128",
+    "isize",
+    "iter",
+    "marker",
+    "matches",
+    "mem",
+    "num",
+    "ops",
+    "option",
+    "pin",
+    "primitive",
+    "ptr",
+    "result",
+    "slice",
+    "str",
+    "task",
+    "todo",
+    "u8",
+    "u16",
+    "u32",
+    "u64",
+    "u128",
+    "unimplemented",
+    "unreachable",
+    "usize",
+    "write",
+    "writeln",
+];
+/// Modules and macros of `std` that are `alloc`'s.
+const ALLOC_ITEMS: &[&str] = &["boxed", "format", "rc", "string", "vec"];
+/// Items of `std::collections` that are `alloc`'s.
+const ALLOC_COLLECTIONS: &[&str] = &[
+    "BTreeMap",
+    "BTreeSet",
+    "BinaryHeap",
+    "LinkedList",
+    "VecDeque",
+    "TryReserveError",
+    "binary_heap",
+    "btree_map",
+    "btree_set",
+    "linked_list",
+    "vec_deque",
+];
+
+/// The crate of `no_std` code where what `std` has at `rest`, the segments
+/// after `std::`, is too, if any.
+fn no_std_crate(rest: &[String]) -> Option<&'static str> {
+    let (module, item) = match rest {
+        [module, item, ..] => (module.as_str(), Some(item.as_str())),
+        [module] => (module.as_str(), None),
+        [] => return None,
+    };
+    match (module, item) {
+        ("collections", Some(item)) if ALLOC_COLLECTIONS.contains(&item) => Some("alloc"),
+        ("sync", Some("Arc" | "Weak")) | ("borrow", Some("Cow" | "ToOwned")) => Some("alloc"),
+        ("fmt", Some("format")) | ("task", Some("Wake")) => Some("alloc"),
+        ("sync", Some("atomic")) | ("borrow", Some("Borrow" | "BorrowMut")) => Some("core"),
+        ("time", Some("Duration")) | ("panic", None) => Some("core"),
+        ("hash", Some("DefaultHasher" | "RandomState")) => None,
+        _ if CORE_ITEMS.contains(&module) => Some("core"),
+        _ if ALLOC_ITEMS.contains(&module) => Some("alloc"),
+        _ => None,
+    }
+}
+
+fn has_extern_crate(file: &SourceFile, name: &str) -> bool {
+    file.items().any(|item| match item {
+        ast::Item::ExternCrate(it) => it.name_ref().map_or(false, |it| it.text() == name),
+        _ => false,
+    })
+}
+
+/// Where to insert `item` at the top of `file`, after its inner attributes,
+/// and the text to insert.
+fn after_inner_attrs(file: &SourceFile, item: &str) -> (TextSize, String) {
+    let offset = file
+        .attrs()
+        .filter(|attr| attr.excl_token().is_some())
+        .last()
+        .map(|attr| attr.syntax().text_range().end())
+        .unwrap_or_else(|| TextSize::from(0));
+    let text =
+        if offset == TextSize::from(0) { format!("{}\n", item) } else { format!("\n{}", item) };
+    (offset, text)
+}
+
+#[cfg(test)]
+mod tests {
+    use syntax::SourceFile;
+
+    const CONTRACT: &str = r#"
+#![no_std]
+use soroban_sdk::{contract, contractimpl, Env, Symbol};
+
+#[contract]
+pub struct Counter;
+"#;
+
+    /// The text of the diagnostics with `code` in the contract file `items`.
+    fn check(items: &str, code: &str) -> Vec<String> {
+        let text = format!("{}{}", CONTRACT, items);
+        let file = SourceFile::parse(&text).tree();
+        super::run(&file)
+            .into_iter()
+            .filter(|it| it.code == code)
+            .map(|it| text[it.range].to_string())
+            .collect()
+    }
+
+    /// `text` with the fix of its first diagnostic with `code` applied.
+    fn fix(text: &str, code: &str) -> String {
+        let file = SourceFile::parse(text).tree();
+        let diagnostic = super::run(&file).into_iter().find(|it| it.code == code).unwrap();
+        let mut text = text.to_string();
+        diagnostic.fix.unwrap().edit.apply(&mut text);
+        text
+    }
+
+    #[test]
+    fn non_pub_entry_point() {
+        let items = r#"
+#[contractimpl]
+impl Counter {
+    fn hidden(env: Env) {}
+    pub fn shown(env: Env) {}
+}
+"#;
+        assert_eq!(check(items, "soroban-non-pub-entry-point"), ["hidden"]);
+        let fixed = fix(&format!("{}{}", CONTRACT, items), "soroban-non-pub-entry-point");
+        assert!(fixed.contains("pub fn hidden"));
+    }
+
+    #[test]
+    fn trait_impl_entry_points_are_exported() {
+        let items = r#"
+#[contractimpl]
+impl Token for Counter {
+    fn balance(env: Env) {}
+}
+"#;
+        assert!(check(items, "soroban-non-pub-entry-point").is_empty());
+    }
+
+    #[test]
+    fn missing_no_std() {
+        let text = "use soroban_sdk::contract;\n\n#[contract]\npub struct Counter;\n";
+        let file = SourceFile::parse(text).tree();
+        let codes: Vec<_> = super::run(&file).into_iter().map(|it| it.code).collect();
+        assert_eq!(codes, ["soroban-missing-no-std"]);
+        assert!(fix(text, "soroban-missing-no-std").starts_with("#![no_std]\n"));
+
+        let text = format!("#![cfg_attr(not(test), no_std)]\n{}", text);
+        let file = SourceFile::parse(&text).tree();
+        assert!(super::run(&file).is_empty());
+    }
+
+    #[test]
+    fn panic_in_entry_point() {
+        let items = r#"
+#[contractimpl]
+impl Counter {
+    pub fn get(env: Env) -> u32 {
+        let value: Option<u32> = env.storage().instance().get(&0);
+        if value.is_none() {
+            panic!("no value");
+        }
+        value.unwrap()
+    }
+}
+
+fn helper(value: Option<u32>) -> u32 {
+    value.unwrap()
+}
+"#;
+        let found = check(items, "soroban-panic-in-entry-point");
+        assert_eq!(found.len(), 2, "{:?}", found);
+        assert!(found[0].starts_with("panic!(\"no value\")"));
+        assert_eq!(found[1], "unwrap");
+    }
+
+    #[test]
+    fn storage_without_ttl() {
+        let items = r#"
+#[contractimpl]
+impl Counter {
+    pub fn set(env: Env, value: u32) {
+        env.storage().persistent().set(&0, &value);
+        env.storage().temporary().set(&1, &value);
+        env.storage().temporary().extend_ttl(&1, 100, 100);
+    }
+}
+"#;
+        assert_eq!(
+            check(items, "soroban-storage-without-ttl"),
+            ["env.storage().persistent().set(&0, &value)"]
+        );
+    }
+
+    #[test]
+    fn std_usage() {
+        let items = r#"
+use std::vec::Vec;
+
+#[cfg(feature = "testutils")]
+mod testutils {
+    use std::string::String;
+}
+
+#[cfg(test)]
+mod test {
+    use std::println;
+}
+
+#[cfg(all(test, feature = "testutils"))]
+mod both {
+    use std::println;
+}
+"#;
+        assert_eq!(check(items, "soroban-std-usage"), ["std", "std"]);
+    }
+
+    /// `path` in the contract file with the fix of `soroban-std-usage`
+    /// applied, if there is one.
+    fn fix_std(path: &str) -> Option<String> {
+        let mut text = format!("{}\nfn f() {{\n    {};\n}}\n", CONTRACT, path);
+        let file = SourceFile::parse(&text).tree();
+        let diagnostic =
+            super::run(&file).into_iter().find(|it| it.code == "soroban-std-usage").unwrap();
+        diagnostic.fix?.edit.apply(&mut text);
+        Some(text)
+    }
+
+    #[test]
+    fn std_usage_fixes() {
+        assert!(fix_std("std::mem::swap(&mut a, &mut b)").unwrap().contains("core::mem::swap"));
+        assert!(fix_std("std::sync::atomic::fence(o)").unwrap().contains("core::sync::atomic"));
+
+        let fixed = fix_std("std::vec::Vec::<u32>::new()").unwrap();
+        assert!(fixed.contains("alloc::vec::Vec::<u32>::new()"));
+        assert!(
+            fixed.starts_with("\n#![no_std]\nextern crate alloc;\nuse soroban_sdk"),
+            "{}",
+            fixed
+        );
+        let fixed = fix_std("std::collections::BTreeMap::<u32, u32>::new()").unwrap();
+        assert!(fixed.contains("alloc::collections::BTreeMap"));
+        let fixed = fix_std("std::string::String::new()").unwrap();
+        assert_eq!(fixed.matches("extern crate alloc;").count(), 1);
+
+        assert!(fix_std("std::collections::HashMap::<u32, u32>::new()").is_none());
+        assert!(fix_std("std::time::Instant::now()").is_none());
+        assert!(fix_std("std::process::exit(1)").is_none());
+    }
+
+    #[test]
+    fn only_contracts_are_linted() {
+        let file = SourceFile::parse("use std::vec::Vec;\nfn f() { None::<u32>.unwrap(); }\n");
+        assert!(super::run(&file.tree()).is_empty());
+    }
+}
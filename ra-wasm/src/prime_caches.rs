@@ -0,0 +1,113 @@
+//! Priming rust-analyzer's caches on the thread pool, in the background.
+//!
+//! The names of every crate are resolved, with the import maps of
+//! rust-analyzer's import completion when the bundle has no library index, so
+//! that the first requests don't pay for it. The worker keeps answering
+//! requests meanwhile and polls the progress.
+//!
+//! Applying a change cancels the queries running on other threads by
+//! unwinding them, which aborts under wasm, and waits for their snapshots of
+//! the database to be dropped. So changes are only applied once no job holds
+//! a snapshot: the jobs are stopped, those priming a crate finish against the
+//! previous revision while the change waits, and the crates left are primed
+//! again after it.
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+use hir::db::DefDatabase;
+use ide::AnalysisHost;
+use ide_db::base_db::{salsa::ParallelDatabase, CrateId, SourceDatabase};
+
+use crate::return_types::PrimeCachesProgress;
+
+pub(crate) struct Priming {
+    state: Arc<Mutex<State>>,
+    import_maps: bool,
+    total: usize,
+}
+
+#[derive(Default)]
+struct State {
+    primed: HashSet<CrateId>,
+    /// Display name of the crate primed last.
+    last: Option<String>,
+    /// Crates of the jobs holding a snapshot of the database, started or not.
+    queued: HashSet<CrateId>,
+    /// Jobs that haven't started yet return right away.
+    stopped: bool,
+}
+
+impl Priming {
+    /// Starts priming the crates of `host`.
+    pub(crate) fn start(host: &AnalysisHost, import_maps: bool) -> Priming {
+        let mut priming = Priming { state: Arc::default(), import_maps, total: 0 };
+        priming.resume(host);
+        priming
+    }
+
+    /// Stops the jobs that haven't started yet, without waiting for the others.
+    pub(crate) fn stop(&self) {
+        self.state.lock().unwrap().stopped = true;
+    }
+
+    /// Whether a change can be applied without waiting for a job.
+    pub(crate) fn is_idle(&self) -> bool {
+        self.state.lock().unwrap().queued.is_empty()
+    }
+
+    /// Primes the crates of `host` that aren't yet, after a [`Priming::stop`].
+    /// The jobs still queued go on if they haven't returned yet.
+    pub(crate) fn resume(&mut self, host: &AnalysisHost) {
+        let db = host.raw_database();
+        let crates = db.crate_graph().crates_in_topological_order();
+        self.total = crates.len();
+        let crates: Vec<_> = {
+            let mut state = self.state.lock().unwrap();
+            state.stopped = false;
+            let crates: Vec<_> = crates
+                .into_iter()
+                .filter(|it| !state.primed.contains(it) && !state.queued.contains(it))
+                .collect();
+            state.queued.extend(crates.iter().copied());
+            crates
+        };
+        // In dependency order, a crate waits on the ones it depends on being
+        // primed by other threads.
+        for krate in crates {
+            let db = db.snapshot();
+            let state = self.state.clone();
+            let import_maps = self.import_maps;
+            rayon::spawn(move || {
+                {
+                    let mut state = state.lock().unwrap();
+                    if state.stopped {
+                        drop(db);
+                        state.queued.remove(&krate);
+                        return;
+                    }
+                }
+                db.crate_def_map(krate);
+                if import_maps {
+                    db.import_map(krate);
+                }
+                let name = db.crate_graph()[krate].display_name.as_deref().map(String::from);
+                drop(db);
+                let mut state = state.lock().unwrap();
+                state.queued.remove(&krate);
+                state.primed.insert(krate);
+                state.last = name;
+            });
+        }
+    }
+
+    pub(crate) fn progress(&self) -> PrimeCachesProgress {
+        let state = self.state.lock().unwrap();
+        PrimeCachesProgress {
+            crateName: state.last.clone(),
+            done: state.primed.len() as u32,
+            total: self.total as u32,
+        }
+    }
+}
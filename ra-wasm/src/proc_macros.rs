@@ -0,0 +1,148 @@
+//! Proc-macro expanders implemented in Rust and compiled into the worker.
+//!
+//! Proc-macro dylibs can't be loaded in the browser, so the macros of the
+//! bundled crates are reimplemented here and registered per macro crate in
+//! [`MACRO_CRATES`]. rust-analyzer only uses an expander if the crate also
+//! declares a matching `#[proc_macro*]` function, so the root file of each
+//! macro crate is generated from the registry by [`MacroCrate::root_source`].
+use std::{fmt, sync::Arc};
+
+use ide_db::base_db::{Env, ProcMacro, ProcMacroExpander, ProcMacroExpansionError, ProcMacroKind};
+use syntax::{
+    ast::{self, HasModuleItem},
+    SourceFile,
+};
+use tt::{Leaf, Subtree, TokenId, TokenTree};
+
+use crate::soroban_macros;
+
+/// Expands the input of a macro call. For attribute macros the first argument
+/// is the annotated item, without the attribute, and the second one holds the
+/// attribute's arguments.
+pub(crate) type ExpandFn =
+    fn(&Subtree, Option<&Subtree>) -> Result<Subtree, ProcMacroExpansionError>;
+
+pub(crate) struct MacroCrate {
+    pub(crate) name: &'static str,
+    pub(crate) expanders: &'static [Expander],
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct Expander {
+    pub(crate) name: &'static str,
+    pub(crate) kind: ProcMacroKind,
+    pub(crate) expand: ExpandFn,
+}
+
+pub(crate) static MACRO_CRATES: &[MacroCrate] = &[soroban_macros::SOROBAN_SDK_MACROS];
+
+/// Looks up the registered expanders of the macro crate called `name`.
+pub(crate) fn macro_crate(name: &str) -> Option<&'static MacroCrate> {
+    MACRO_CRATES.iter().find(|it| it.name == name)
+}
+
+impl MacroCrate {
+    pub(crate) fn proc_macros(&self) -> Vec<ProcMacro> {
+        self.expanders
+            .iter()
+            .map(|it| ProcMacro { name: it.name.into(), kind: it.kind, expander: Arc::new(*it) })
+            .collect()
+    }
+
+    /// The crate root declaring every registered macro.
+    pub(crate) fn root_source(&self) -> String {
+        let mut res = String::new();
+        for expander in self.expanders {
+            let attr = match expander.kind {
+                ProcMacroKind::Attr => "proc_macro_attribute".to_string(),
+                ProcMacroKind::FuncLike => "proc_macro".to_string(),
+                ProcMacroKind::CustomDerive => format!("proc_macro_derive({})", expander.name),
+            };
+            res.push_str(&format!("#[{}]\npub fn {}() {{}}\n\n", attr, expander.name));
+        }
+        res
+    }
+}
+
+impl fmt::Debug for Expander {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Expander").field("name", &self.name).field("kind", &self.kind).finish()
+    }
+}
+
+impl ProcMacroExpander for Expander {
+    fn expand(
+        &self,
+        subtree: &Subtree,
+        attrs: Option<&Subtree>,
+        _env: &Env,
+    ) -> Result<Subtree, ProcMacroExpansionError> {
+        (self.expand)(subtree, attrs)
+    }
+}
+
+/// Attribute macro expansion that leaves the item unchanged.
+pub(crate) fn identity(
+    item: &Subtree,
+    _: Option<&Subtree>,
+) -> Result<Subtree, ProcMacroExpansionError> {
+    Ok(item.clone())
+}
+
+/// Parses the input of an attribute macro back into an item.
+pub(crate) fn parse_item(item: &Subtree) -> Option<ast::Item> {
+    SourceFile::parse(&item.to_string()).tree().items().next()
+}
+
+/// Parses generated code into tokens. The tokens get no ids, so they never map
+/// back into the macro input.
+pub(crate) fn parse_tokens(text: &str) -> Result<Subtree, ProcMacroExpansionError> {
+    let (mut subtree, _) = mbe::parse_to_token_tree(text).ok_or_else(|| {
+        ProcMacroExpansionError::Panic(format!("failed to parse generated code: {}", text))
+    })?;
+    clear_ids(&mut subtree);
+    subtree.delimiter = None;
+    Ok(subtree)
+}
+
+/// Returns `item`, followed by the items generated from `extra`.
+pub(crate) fn with_items(item: &Subtree, extra: &str) -> Result<Subtree, ProcMacroExpansionError> {
+    let mut res = item.clone();
+    res.delimiter = None;
+    res.token_trees.extend(parse_tokens(extra)?.token_trees);
+    Ok(res)
+}
+
+/// Finds the string value of `key = "value"` in macro arguments.
+pub(crate) fn string_arg(args: Option<&Subtree>, key: &str) -> Option<String> {
+    let args = &args?.token_trees;
+    args.windows(3).find_map(|it| {
+        let (ident, eq, lit) = match it {
+            [TokenTree::Leaf(ident), TokenTree::Leaf(eq), TokenTree::Leaf(lit)] => (ident, eq, lit),
+            _ => return None,
+        };
+        let (ident, eq, lit) = match (ident, eq, lit) {
+            (Leaf::Ident(ident), Leaf::Punct(eq), Leaf::Literal(lit)) => (ident, eq, lit),
+            _ => return None,
+        };
+        if ident.text == key && eq.char == '=' {
+            Some(lit.text.trim_matches('"').to_string())
+        } else {
+            None
+        }
+    })
+}
+
+fn clear_ids(subtree: &mut Subtree) {
+    if let Some(delimiter) = &mut subtree.delimiter {
+        delimiter.id = TokenId::unspecified();
+    }
+    for tt in &mut subtree.token_trees {
+        match tt {
+            TokenTree::Subtree(it) => clear_ids(it),
+            TokenTree::Leaf(Leaf::Ident(it)) => it.id = TokenId::unspecified(),
+            TokenTree::Leaf(Leaf::Punct(it)) => it.id = TokenId::unspecified(),
+            TokenTree::Leaf(Leaf::Literal(it)) => it.id = TokenId::unspecified(),
+        }
+    }
+}
@@ -59,6 +59,15 @@ pub struct TextEdit {
     pub text: String,
 }
 
+/// Sent each time the caches of a crate are primed.
+#[derive(Serialize)]
+pub struct PrimeCachesProgress {
+    /// `None` for the user's crate.
+    pub crateName: Option<String>,
+    pub done: u32,
+    pub total: u32,
+}
+
 #[derive(Serialize)]
 pub struct UpdateResult {
     pub diagnostics: Vec<Diagnostic>,
@@ -68,6 +77,7 @@ pub struct UpdateResult {
 #[derive(Serialize)]
 pub struct Diagnostic {
     pub message: String,
+    pub code: Option<String>,
     pub startLineNumber: u32,
     pub startColumn: u32,
     pub endLineNumber: u32,
@@ -76,7 +86,7 @@ pub struct Diagnostic {
 }
 
 #[allow(dead_code)]
-#[derive(Serialize_repr)]
+#[derive(Serialize_repr, Clone, Copy)]
 #[repr(u8)]
 pub enum MarkerSeverity {
     Hint = 1,
@@ -85,6 +95,13 @@ pub enum MarkerSeverity {
     Error = 8,
 }
 
+#[derive(Serialize)]
+pub struct CodeAction {
+    pub title: String,
+    pub code: String,
+    pub edits: Vec<TextEdit>,
+}
+
 #[derive(Serialize)]
 pub struct RenameLocation {
     pub range: Range,
@@ -178,6 +195,10 @@ pub struct LocationLink {
     pub originSelectionRange: Range,
     pub range: Range,
     pub targetSelectionRange: Range,
+    /// Original path of a library file, the ranges are in that file.
+    pub path: Option<String>,
+    /// Text of the target for library files, starting at the line of `range`.
+    pub source: Option<String>,
 }
 
 #[allow(dead_code)]
@@ -247,3 +268,66 @@ pub struct FoldingRange {
     pub end: u32,
     pub kind: Option<FoldingRangeKind>,
 }
+
+#[derive(Serialize, Default)]
+pub struct ContractSpec {
+    pub contracts: Vec<String>,
+    pub functions: Vec<SpecFunction>,
+    pub types: Vec<SpecType>,
+    pub errors: Vec<SpecError>,
+    pub events: Vec<SpecEvent>,
+}
+
+#[derive(Serialize)]
+pub struct SpecFunction {
+    pub name: String,
+    pub doc: String,
+    pub inputs: Vec<SpecField>,
+    pub output: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct SpecField {
+    pub name: String,
+    pub doc: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum SpecType {
+    Struct { name: String, doc: String, fields: Vec<SpecField> },
+    Enum { name: String, doc: String, cases: Vec<SpecCase> },
+}
+
+#[derive(Serialize)]
+pub struct SpecCase {
+    pub name: String,
+    pub doc: String,
+    pub value: Option<String>,
+    pub types: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct SpecError {
+    pub name: String,
+    pub doc: String,
+    pub cases: Vec<SpecCase>,
+}
+
+#[derive(Serialize)]
+pub struct SpecEvent {
+    pub name: String,
+    pub doc: String,
+    pub prefix_topics: Vec<String>,
+    pub fields: Vec<SpecEventField>,
+}
+
+#[derive(Serialize)]
+pub struct SpecEventField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub topic: bool,
+}
@@ -0,0 +1,135 @@
+//! Syntax-level helpers for recognizing Soroban contract items.
+//!
+//! The contract-specific analyses work on the syntax tree of the user crate as
+//! written, since the `soroban_sdk` attribute macros are only expanded into
+//! what name resolution needs, see [`crate::soroban_macros`].
+use cfg::{CfgAtom, CfgExpr};
+use syntax::{
+    ast::{self, HasAttrs, HasDocComments},
+    AstNode, SourceFile, SyntaxNode,
+};
+
+/// Finds an attribute by the last segment of its path, so that both
+/// `#[contractimpl]` and `#[soroban_sdk::contractimpl]` match.
+pub(crate) fn find_attr(owner: &impl HasAttrs, name: &str) -> Option<ast::Attr> {
+    owner.attrs().find(|attr| attr_name(attr).as_deref() == Some(name))
+}
+
+pub(crate) fn has_attr(owner: &impl HasAttrs, name: &str) -> bool {
+    find_attr(owner, name).is_some()
+}
+
+fn attr_name(attr: &ast::Attr) -> Option<String> {
+    Some(attr.path()?.segment()?.name_ref()?.text().to_string())
+}
+
+/// Joins the `///` doc comments of an item into a single string.
+pub(crate) fn docs(owner: &impl HasDocComments) -> String {
+    let lines: Vec<_> = owner
+        .doc_comments()
+        .filter_map(|comment| {
+            comment.doc_comment().map(|it| it.strip_prefix(' ').unwrap_or(it).to_string())
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/// Whether the file defines a contract at all; the lints only make sense then.
+pub(crate) fn is_contract(file: &SourceFile) -> bool {
+    file.syntax()
+        .descendants()
+        .filter_map(ast::Item::cast)
+        .any(|item| has_attr(&item, "contract") || has_attr(&item, "contractimpl"))
+}
+
+/// Whether `node` is only compiled for tests (`#[test]`, `#[cfg(test)]`).
+pub(crate) fn is_test_code(node: &SyntaxNode) -> bool {
+    node.ancestors().filter_map(ast::Item::cast).any(|item| {
+        item.attrs().any(|attr| match attr_name(&attr).as_deref() {
+            Some("test") => true,
+            Some("cfg") => attr.token_tree().map_or(false, |tt| {
+                let text = tt.syntax().text().to_string();
+                let predicate = text.get(1..text.len().saturating_sub(1)).unwrap_or_default();
+                match mbe::parse_to_token_tree(predicate) {
+                    Some((tt, _)) => requires_test(&CfgExpr::parse(&tt)),
+                    None => false,
+                }
+            }),
+            _ => false,
+        })
+    })
+}
+
+/// Whether `cfg` only holds with the `test` flag, so that
+/// `feature = "testutils"` or `any(test, feature = "testutils")` code still
+/// counts as contract code.
+fn requires_test(cfg: &CfgExpr) -> bool {
+    match cfg {
+        CfgExpr::Atom(CfgAtom::Flag(name)) => name.as_str() == "test",
+        CfgExpr::All(preds) => preds.iter().any(requires_test),
+        CfgExpr::Any(preds) => !preds.is_empty() && preds.iter().all(requires_test),
+        _ => false,
+    }
+}
+
+/// A function exported by a `#[contractimpl]` block.
+pub(crate) struct EntryPoint {
+    pub(crate) impl_: ast::Impl,
+    pub(crate) func: ast::Fn,
+}
+
+impl EntryPoint {
+    /// Trait impls export their functions without a `pub` modifier.
+    pub(crate) fn is_trait_impl(&self) -> bool {
+        self.impl_.trait_().is_some()
+    }
+}
+
+pub(crate) fn entry_points(file: &SourceFile) -> Vec<EntryPoint> {
+    file.syntax()
+        .descendants()
+        .filter_map(ast::Impl::cast)
+        .filter(|impl_| has_attr(impl_, "contractimpl"))
+        .flat_map(|impl_| {
+            let fns = impl_.assoc_item_list().into_iter().flat_map(|list| list.assoc_items());
+            fns.filter_map(|item| match item {
+                ast::AssocItem::Fn(func) => Some(func),
+                _ => None,
+            })
+            .map(move |func| EntryPoint { impl_: impl_.clone(), func })
+        })
+        .collect()
+}
+
+/// Whether `ty` is the contract environment, which the host passes implicitly.
+pub(crate) fn is_env_type(ty: &str) -> bool {
+    ty.trim_start_matches('&').rsplit("::").next() == Some("Env")
+}
+
+/// Returns the method name of `call`, e.g. `set` for `storage.set(..)`.
+pub(crate) fn method_name(call: &ast::MethodCallExpr) -> Option<String> {
+    Some(call.name_ref()?.text().to_string())
+}
+
+/// Storage kind (`persistent`, `temporary` or `instance`) a method call
+/// operates on, found by walking its receiver chain back to `.storage()`.
+pub(crate) fn storage_kind(call: &ast::MethodCallExpr) -> Option<&'static str> {
+    let mut receiver = call.receiver()?;
+    while let ast::Expr::MethodCallExpr(inner) = receiver {
+        let kind = match method_name(&inner).as_deref() {
+            Some("persistent") => Some("persistent"),
+            Some("temporary") => Some("temporary"),
+            Some("instance") => Some("instance"),
+            _ => None,
+        };
+        if let Some(kind) = kind {
+            let from_storage = matches!(
+                inner.receiver(),
+                Some(ast::Expr::MethodCallExpr(it)) if method_name(&it).as_deref() == Some("storage")
+            );
+            return if from_storage { Some(kind) } else { None };
+        }
+        receiver = inner.receiver()?;
+    }
+    None
+}
@@ -0,0 +1,277 @@
+//! Expanders for the `soroban_sdk` macros.
+//!
+//! These only produce what analysis needs: the trait impls and methods that
+//! user code relies on, with `loop {}` bodies. `#[contract]` and
+//! `#[contractimpl]` leave their items unchanged, since the contract clients
+//! they would generate come from [`crate::client_gen`], which needs to see all
+//! `#[contractimpl]` blocks of a contract at once.
+use ide_db::base_db::{ProcMacroExpansionError, ProcMacroKind};
+use syntax::ast::{self, HasName};
+use tt::Subtree;
+
+use crate::{
+    client_gen,
+    proc_macros::{self, Expander, MacroCrate},
+};
+
+pub(crate) const SOROBAN_SDK_MACROS: MacroCrate = MacroCrate {
+    name: "soroban_sdk_macros",
+    expanders: &[
+        Expander { name: "contract", kind: ProcMacroKind::Attr, expand: proc_macros::identity },
+        Expander { name: "contractimpl", kind: ProcMacroKind::Attr, expand: proc_macros::identity },
+        Expander {
+            name: "contractspecfn",
+            kind: ProcMacroKind::Attr,
+            expand: proc_macros::identity,
+        },
+        Expander { name: "contracttype", kind: ProcMacroKind::Attr, expand: contracttype },
+        Expander { name: "contracterror", kind: ProcMacroKind::Attr, expand: contracterror },
+        Expander { name: "contractevent", kind: ProcMacroKind::Attr, expand: contractevent },
+        Expander { name: "contractclient", kind: ProcMacroKind::Attr, expand: contractclient },
+        Expander { name: "contractimport", kind: ProcMacroKind::FuncLike, expand: contractimport },
+        Expander { name: "contractmeta", kind: ProcMacroKind::FuncLike, expand: contractmeta },
+        Expander { name: "symbol_short", kind: ProcMacroKind::FuncLike, expand: symbol_short },
+    ],
+};
+
+fn item_name(item: &Subtree) -> Result<String, ProcMacroExpansionError> {
+    let name = match proc_macros::parse_item(item) {
+        Some(ast::Item::Struct(it)) => it.name(),
+        Some(ast::Item::Enum(it)) => it.name(),
+        _ => None,
+    };
+    name.map(|it| it.text().to_string())
+        .ok_or_else(|| ProcMacroExpansionError::Panic("expected a struct or an enum".to_string()))
+}
+
+/// Conversions between `name` and `Val`.
+fn val_conversions(name: &str) -> String {
+    format!(
+        "
+impl soroban_sdk::TryFromVal<soroban_sdk::Env, soroban_sdk::Val> for {0} {{
+    type Error = soroban_sdk::ConversionError;
+    fn try_from_val(env: &soroban_sdk::Env, v: &soroban_sdk::Val) -> Result<Self, Self::Error> {{
+        loop {{}}
+    }}
+}}
+
+impl soroban_sdk::TryFromVal<soroban_sdk::Env, {0}> for soroban_sdk::Val {{
+    type Error = soroban_sdk::ConversionError;
+    fn try_from_val(env: &soroban_sdk::Env, v: &{0}) -> Result<Self, Self::Error> {{
+        loop {{}}
+    }}
+}}
+",
+        name
+    )
+}
+
+fn contracttype(item: &Subtree, _: Option<&Subtree>) -> Result<Subtree, ProcMacroExpansionError> {
+    let name = item_name(item)?;
+    proc_macros::with_items(item, &val_conversions(&name))
+}
+
+fn contracterror(item: &Subtree, _: Option<&Subtree>) -> Result<Subtree, ProcMacroExpansionError> {
+    let name = item_name(item)?;
+    let mut extra = val_conversions(&name);
+    for target in &["soroban_sdk::Error", "soroban_sdk::InvokeError"] {
+        extra.push_str(&format!(
+            "
+impl From<{0}> for {1} {{
+    fn from(val: {0}) -> Self {{
+        loop {{}}
+    }}
+}}
+
+impl From<&{0}> for {1} {{
+    fn from(val: &{0}) -> Self {{
+        loop {{}}
+    }}
+}}
+
+impl core::convert::TryFrom<{1}> for {0} {{
+    type Error = {1};
+    fn try_from(error: {1}) -> Result<Self, Self::Error> {{
+        loop {{}}
+    }}
+}}
+",
+            name, target
+        ));
+    }
+    proc_macros::with_items(item, &extra)
+}
+
+fn contractevent(item: &Subtree, _: Option<&Subtree>) -> Result<Subtree, ProcMacroExpansionError> {
+    let name = item_name(item)?;
+    let extra = format!(
+        "
+impl {} {{
+    pub fn publish(&self, env: &soroban_sdk::Env) {{
+        loop {{}}
+    }}
+}}
+",
+        name
+    );
+    proc_macros::with_items(item, &extra)
+}
+
+/// `#[contractclient(name = "Client")]` on a trait generates a client calling
+/// the trait's functions.
+fn contractclient(
+    item: &Subtree,
+    args: Option<&Subtree>,
+) -> Result<Subtree, ProcMacroExpansionError> {
+    let name = proc_macros::string_arg(args, "name").ok_or_else(|| {
+        ProcMacroExpansionError::Panic("expected `name = \"..\"` argument".to_string())
+    })?;
+    let trait_ = match proc_macros::parse_item(item) {
+        Some(ast::Item::Trait(it)) => it,
+        _ => return Err(ProcMacroExpansionError::Panic("expected a trait".to_string())),
+    };
+    let mut extra = String::new();
+    client_gen::client_struct(&name, &mut extra);
+    for item in trait_.assoc_item_list().into_iter().flat_map(|it| it.assoc_items()) {
+        if let ast::AssocItem::Fn(func) = item {
+            client_gen::client_fns(&func, &mut extra);
+        }
+    }
+    extra.push_str("}\n");
+    proc_macros::with_items(item, &extra)
+}
+
+/// `contractimport!(file = "..")` reads the spec of a Wasm file, which isn't
+/// available here, so only the always present items are generated.
+fn contractimport(_: &Subtree, _: Option<&Subtree>) -> Result<Subtree, ProcMacroExpansionError> {
+    let mut res = String::from("pub const WASM: &[u8] = &[];\n");
+    client_gen::client_struct("Client", &mut res);
+    res.push_str("}\n");
+    proc_macros::parse_tokens(&res)
+}
+
+fn contractmeta(_: &Subtree, _: Option<&Subtree>) -> Result<Subtree, ProcMacroExpansionError> {
+    Ok(Subtree::default())
+}
+
+fn symbol_short(input: &Subtree, _: Option<&Subtree>) -> Result<Subtree, ProcMacroExpansionError> {
+    proc_macros::parse_tokens(&format!(
+        "{{ const SYMBOL: soroban_sdk::Symbol = soroban_sdk::Symbol::short({}); SYMBOL }}",
+        input
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use syntax::{
+        ast::{self, HasModuleItem, HasName},
+        AstNode, SourceFile,
+    };
+
+    use super::SOROBAN_SDK_MACROS;
+
+    fn expand(name: &str, input: &str, args: Option<&str>) -> Option<String> {
+        let expander = SOROBAN_SDK_MACROS.expanders.iter().find(|it| it.name == name).unwrap();
+        let (input, _) = mbe::parse_to_token_tree(input).unwrap();
+        let args = args.map(|it| mbe::parse_to_token_tree(it).unwrap().0);
+        (expander.expand)(&input, args.as_ref()).ok().map(|it| it.to_string())
+    }
+
+    fn squeeze(node: Option<impl AstNode>) -> String {
+        let text = node.map(|it| it.syntax().text().to_string()).unwrap_or_default();
+        text.split_whitespace().collect()
+    }
+
+    /// The items of `text`, with the functions of the `impl` blocks.
+    fn items(text: &str) -> Vec<String> {
+        let file = SourceFile::parse(text);
+        assert!(file.errors().is_empty(), "{:?}\n{}", file.errors(), text);
+        file.tree()
+            .items()
+            .map(|item| match item {
+                ast::Item::Struct(it) => format!("struct {}", squeeze(it.name())),
+                ast::Item::Enum(it) => format!("enum {}", squeeze(it.name())),
+                ast::Item::Trait(it) => format!("trait {}", squeeze(it.name())),
+                ast::Item::Const(it) => format!("const {}", squeeze(it.name())),
+                ast::Item::Impl(it) => {
+                    let fns: Vec<_> = it
+                        .assoc_item_list()
+                        .into_iter()
+                        .flat_map(|it| it.assoc_items())
+                        .filter_map(|it| match it {
+                            ast::AssocItem::Fn(func) => Some(squeeze(func.name())),
+                            _ => None,
+                        })
+                        .collect();
+                    match it.trait_() {
+                        Some(trait_) => format!(
+                            "impl {} for {} {{ {} }}",
+                            squeeze(Some(trait_)),
+                            squeeze(it.self_ty()),
+                            fns.join(" ")
+                        ),
+                        None => format!("impl {} {{ {} }}", squeeze(it.self_ty()), fns.join(" ")),
+                    }
+                }
+                it => it.syntax().to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn contract_items_are_unchanged() {
+        let res = expand("contract", "pub struct Counter;", None).unwrap();
+        assert_eq!(items(&res), ["struct Counter"]);
+        let res =
+            expand("contractimpl", "impl Counter { pub fn get(env: Env) -> u32 { 0 } }", None)
+                .unwrap();
+        assert_eq!(items(&res), ["impl Counter { get }"]);
+    }
+
+    #[test]
+    fn contracttype_converts_to_val() {
+        let res = expand("contracttype", "pub enum Key { Admin }", None).unwrap();
+        assert_eq!(
+            items(&res),
+            [
+                "enum Key",
+                "impl soroban_sdk::TryFromVal<soroban_sdk::Env,soroban_sdk::Val> for Key \
+                 { try_from_val }",
+                "impl soroban_sdk::TryFromVal<soroban_sdk::Env,Key> for soroban_sdk::Val \
+                 { try_from_val }",
+            ]
+        );
+    }
+
+    #[test]
+    fn contracterror_converts_to_errors() {
+        let res = expand("contracterror", "pub enum Error { Missing = 1 }", None).unwrap();
+        let items = items(&res);
+        assert_eq!(items.len(), 9);
+        assert!(
+            items.contains(&"impl From<Error> for soroban_sdk::InvokeError { from }".to_string())
+        );
+    }
+
+    #[test]
+    fn contractclient_calls_the_trait() {
+        let trait_ = "pub trait Token { fn balance(env: Env, id: Address) -> i128; }";
+        let res = expand("contractclient", trait_, Some(r#"name = "TokenClient""#)).unwrap();
+        assert_eq!(
+            items(&res),
+            [
+                "trait Token",
+                "struct TokenClient",
+                "impl TokenClient<'a> { new balance try_balance }"
+            ]
+        );
+        assert!(expand("contractclient", trait_, None).is_none());
+    }
+
+    #[test]
+    fn symbol_short_is_a_const() {
+        let res = expand("symbol_short", r#""abc""#, None).unwrap();
+        let res: String = res.split_whitespace().collect();
+        assert!(res.contains(r#"soroban_sdk::Symbol::short("abc")"#), "{}", res);
+    }
+}
@@ -61,6 +61,46 @@ pub(crate) fn severity(s: ide::Severity) -> return_types::MarkerSeverity {
     }
 }
 
+pub(crate) fn lint_diagnostic(
+    d: crate::lints::LintDiagnostic,
+    line_index: &ide::LineIndex,
+) -> return_types::Diagnostic {
+    let return_types::Range { startLineNumber, startColumn, endLineNumber, endColumn } =
+        text_range(d.range, line_index);
+    return_types::Diagnostic {
+        message: d.message,
+        code: Some(d.code.to_string()),
+        severity: d.severity,
+        startLineNumber,
+        startColumn,
+        endLineNumber,
+        endColumn,
+    }
+}
+
+pub(crate) fn auth_code_lens(
+    auth: crate::auth::EntryPointAuth,
+    line_index: &ide::LineIndex,
+) -> return_types::CodeLensSymbol {
+    let title = if auth.authorized.is_empty() {
+        "authorizes no address on all paths".to_string()
+    } else {
+        let addresses: Vec<_> = auth.authorized.iter().map(|it| format!("`{}`", it)).collect();
+        format!("authorizes {}", addresses.join(", "))
+    };
+    let positions =
+        auth.auth_calls.iter().map(|(_, range)| text_range(*range, line_index)).collect();
+
+    return_types::CodeLensSymbol {
+        range: text_range(auth.range, line_index),
+        command: Some(return_types::Command {
+            id: "editor.action.showReferences".into(),
+            title,
+            positions,
+        }),
+    }
+}
+
 pub(crate) fn text_edit(indel: &ide::Indel, line_index: &ide::LineIndex) -> return_types::TextEdit {
     let text = indel.insert.clone();
     return_types::TextEdit { range: text_range(indel.delete, line_index), text }
@@ -132,27 +172,23 @@ pub(crate) fn signature_information(
     SignatureInformation { label, documentation, parameters }
 }
 
-pub(crate) fn location_links(
-    nav_info: ide::RangeInfo<Vec<ide::NavigationTarget>>,
+pub(crate) fn location_link(
+    selection: return_types::Range,
+    nav: &ide::NavigationTarget,
     line_index: &ide::LineIndex,
-) -> Vec<return_types::LocationLink> {
-    let selection = text_range(nav_info.range, line_index);
-    nav_info
-        .info
-        .into_iter()
-        .map(|nav| {
-            let range = text_range(nav.full_range, line_index);
-
-            let target_selection_range =
-                nav.focus_range.map(|it| text_range(it, line_index)).unwrap_or(range);
-
-            return_types::LocationLink {
-                originSelectionRange: selection,
-                range,
-                targetSelectionRange: target_selection_range,
-            }
-        })
-        .collect()
+) -> return_types::LocationLink {
+    let range = text_range(nav.full_range, line_index);
+
+    let target_selection_range =
+        nav.focus_range.map(|it| text_range(it, line_index)).unwrap_or(range);
+
+    return_types::LocationLink {
+        originSelectionRange: selection,
+        range,
+        targetSelectionRange: target_selection_range,
+        path: None,
+        source: None,
+    }
 }
 
 pub(crate) fn symbol_kind(kind: ide::StructureNodeKind) -> return_types::SymbolKind {
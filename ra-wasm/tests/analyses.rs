@@ -0,0 +1,20 @@
+//! The syntax-level analyses, built natively to run their tests. The crate
+//! itself only builds for wasm, with the bindings.
+#![allow(dead_code, non_snake_case)]
+
+#[path = "../src/auth.rs"]
+mod auth;
+#[path = "../src/client_gen.rs"]
+mod client_gen;
+#[path = "../src/contract_spec.rs"]
+mod contract_spec;
+#[path = "../src/lints.rs"]
+mod lints;
+#[path = "../src/proc_macros.rs"]
+mod proc_macros;
+#[path = "../src/return_types.rs"]
+mod return_types;
+#[path = "../src/soroban.rs"]
+mod soroban;
+#[path = "../src/soroban_macros.rs"]
+mod soroban_macros;
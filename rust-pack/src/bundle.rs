@@ -0,0 +1,164 @@
+//! The bundle manifest loaded by `WorldState::init` in ra-wasm: every packed
+//! crate with what's needed to add it to rust-analyzer's crate graph.
+//!
+//! It is written as `bundle.bin`, the JSON manifest compressed with deflate
+//! behind a header:
+//!
+//! | bytes  | content                                  |
+//! |--------|------------------------------------------|
+//! | 0..4   | `RPAK`                                   |
+//! | 4..8   | [`FORMAT_VERSION`], little endian        |
+//! | 8..12  | CRC-32 of the JSON manifest, same        |
+//! | 12..16 | length of the JSON manifest, same        |
+//! | 16..   | the JSON manifest, raw deflate           |
+//!
+//! ra-wasm decodes it in `WorldState::init_bundle`, so any change to the
+//! format or the manifest must be made there too and bump the version. The
+//! tests read the bundles back with ra-wasm's `bundle_header.rs`.
+use std::process::Command;
+
+use serde::Serialize;
+
+use crate::index::Export;
+use crate::source_map::SourceMap;
+
+#[derive(Serialize)]
+pub struct Bundle {
+    /// The target triple the crates were pruned for.
+    pub target: String,
+    pub crates: Vec<BundleCrate>,
+}
+
+pub const MAGIC: &[u8; 4] = b"RPAK";
+pub const FORMAT_VERSION: u32 = 2;
+
+impl Bundle {
+    /// The compressed `bundle.bin`.
+    pub fn to_binary(&self) -> Vec<u8> {
+        let json = serde_json::to_vec(self).unwrap();
+        let compressed = miniz_oxide::deflate::compress_to_vec(&json, 9);
+        let mut res = Vec::with_capacity(16 + compressed.len());
+        res.extend_from_slice(MAGIC);
+        res.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        res.extend_from_slice(&crc32fast::hash(&json).to_le_bytes());
+        res.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        res.extend_from_slice(&compressed);
+        res
+    }
+}
+
+#[derive(Serialize)]
+pub struct BundleCrate {
+    /// Unique in the bundle, referenced by [`BundleDep::krate`].
+    pub id: String,
+    pub name: String,
+    pub edition: String,
+    /// Target cfg atoms, `unix` or `target_os="linux"`.
+    pub cfg: Vec<String>,
+    pub features: Vec<String>,
+    pub deps: Vec<BundleDep>,
+    /// Proc-macro crates have no source; ra-wasm provides their expanders.
+    pub proc_macro: bool,
+    /// Whether the user's code depends on the crate directly.
+    pub root: bool,
+    /// Missing for crates packed from their expanded source.
+    pub source_map: Option<SourceMap>,
+    /// The paths its items can be imported from, see [`crate::index`].
+    pub exports: Vec<Export>,
+    pub source: String,
+}
+
+#[derive(Serialize)]
+pub struct BundleDep {
+    #[serde(rename = "crate")]
+    pub krate: String,
+    /// Extern name of the dependency.
+    pub name: String,
+}
+
+/// The cfg atoms of `target`, as printed by `rustc --print cfg`.
+pub fn target_cfg(target: &str) -> Result<Vec<String>, String> {
+    let output = rustc(&["--print", "cfg", "--target", target])?;
+    Ok(output.lines().map(String::from).collect())
+}
+
+/// The target triple of the host, from `rustc -vV`.
+pub fn host_target() -> Result<String, String> {
+    let output = rustc(&["-vV"])?;
+    output
+        .lines()
+        .find_map(|it| it.strip_prefix("host: "))
+        .map(String::from)
+        .ok_or_else(|| "rustc -vV didn't print the host".to_string())
+}
+
+/// The sysroot of the default toolchain.
+pub fn sysroot() -> Result<String, String> {
+    Ok(rustc(&["--print", "sysroot"])?.trim().to_string())
+}
+
+fn rustc(args: &[&str]) -> Result<String, String> {
+    let output = Command::new("rustc")
+        .args(args)
+        .output()
+        .map_err(|e| format!("failed to execute rustc: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "rustc {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    String::from_utf8(output.stdout).map_err(|_| "rustc output wasn't utf8".to_string())
+}
+
+#[cfg(test)]
+#[path = "../../ra-wasm/src/bundle_header.rs"]
+mod ra_wasm_header;
+
+#[cfg(test)]
+mod tests {
+    use super::{ra_wasm_header, Bundle, BundleCrate, BundleDep, FORMAT_VERSION};
+
+    fn krate(name: &str, deps: &[&str]) -> BundleCrate {
+        BundleCrate {
+            id: format!("{}-0.1.0", name),
+            name: name.to_string(),
+            edition: "2021".to_string(),
+            cfg: vec!["unix".to_string()],
+            features: Vec::new(),
+            deps: deps
+                .iter()
+                .map(|dep| BundleDep { krate: format!("{}-0.1.0", dep), name: dep.to_string() })
+                .collect(),
+            proc_macro: false,
+            root: true,
+            source_map: None,
+            exports: Vec::new(),
+            source: format!("pub struct S; // \"{}\" \u{e9}", name),
+        }
+    }
+
+    #[test]
+    fn ra_wasm_reads_the_bundle() {
+        assert_eq!(FORMAT_VERSION, ra_wasm_header::FORMAT_VERSION);
+        let bundle = Bundle {
+            target: "x86_64-unknown-linux-gnu".to_string(),
+            crates: vec![krate("a", &[]), krate("b", &["a"])],
+        };
+        let binary = bundle.to_binary();
+        let json = ra_wasm_header::inflate(&binary).unwrap();
+        assert_eq!(json, serde_json::to_vec(&bundle).unwrap());
+
+        let mut corrupted = binary.clone();
+        corrupted[8] ^= 1;
+        assert_eq!(
+            ra_wasm_header::inflate(&corrupted).unwrap_err(),
+            "corrupted bundle: checksum mismatch"
+        );
+        let mut other_version = binary;
+        other_version[4] += 1;
+        let expected = format!("bundle format version {}, ", FORMAT_VERSION + 1);
+        assert!(ra_wasm_header::inflate(&other_version).unwrap_err().starts_with(&expected));
+    }
+}
@@ -0,0 +1,122 @@
+//! Command line options.
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+usage: rust-pack [options] [<crate>...]
+
+Packs core, alloc and std, and the given crates with their dependencies, into a
+compressed bundle per target, <out>/<target>/bundle.bin. Without crates, only the
+sysroot crates are packed, into <out>/fake_<crate>.rs.
+
+options:
+    --sysroot <dir>         library sources, like <sysroot>/lib/rustlib/src/rust/library
+                            [default: from `rustc --print sysroot`]
+    --source <dir>          crate sources, a `cargo vendor` output or a registry source
+                            dir, required to pack crates
+    -o, --out <dir>         output directory [default: ../www]
+    --target <triple>       target to pack for, can be repeated [default: the host]
+    --expand                pack crates from their `-Zunpretty=expanded` output
+    --toolchain <name>      toolchain used by --expand [default: nightly]
+    --max-root-modules <n>  modules inlined from the root file of a crate [default: 4000]
+    --max-modules <n>       modules inlined from each other file [default: 10000]
+    --no-verify             don't check that the packed crates parse
+    --json                  also write the uncompressed <out>/<target>/bundle.json
+    -n, --dry-run           pack without writing anything
+    -v, --verbose           print every inlined module
+    -h, --help              print this help
+";
+
+pub struct Options {
+    pub sysroot: Option<PathBuf>,
+    pub source: Option<PathBuf>,
+    pub out: PathBuf,
+    pub targets: Vec<String>,
+    pub expand: bool,
+    pub toolchain: String,
+    pub max_root_modules: i32,
+    pub max_modules: i32,
+    pub verify: bool,
+    pub json: bool,
+    pub dry_run: bool,
+    pub verbose: bool,
+    pub help: bool,
+    pub crates: Vec<String>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            sysroot: None,
+            source: None,
+            out: PathBuf::from("../www"),
+            targets: Vec::new(),
+            expand: false,
+            toolchain: "nightly".to_string(),
+            max_root_modules: 4000,
+            max_modules: 10000,
+            verify: true,
+            json: false,
+            dry_run: false,
+            verbose: false,
+            help: false,
+            crates: Vec::new(),
+        }
+    }
+}
+
+impl Options {
+    pub fn parse(args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut args = args;
+        while let Some(arg) = args.next() {
+            if !arg.starts_with('-') {
+                options.crates.push(arg);
+                continue;
+            }
+            // Both `--out dir` and `--out=dir`.
+            let (flag, mut inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => {
+                    (flag.to_string(), Some(value.to_string()))
+                }
+                _ => (arg.clone(), None),
+            };
+            let mut value = || {
+                inline_value
+                    .take()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("missing value for {}", flag))
+            };
+            match flag.as_str() {
+                "--sysroot" => options.sysroot = Some(value()?.into()),
+                "--source" => options.source = Some(value()?.into()),
+                "-o" | "--out" => options.out = value()?.into(),
+                "--target" => options.targets.push(value()?),
+                "--expand" => options.expand = true,
+                "--toolchain" => options.toolchain = value()?,
+                "--max-root-modules" => options.max_root_modules = count(&flag, &value()?)?,
+                "--max-modules" => options.max_modules = count(&flag, &value()?)?,
+                "--no-verify" => options.verify = false,
+                "--json" => options.json = true,
+                "-n" | "--dry-run" => options.dry_run = true,
+                "-v" | "--verbose" => options.verbose = true,
+                "-h" | "--help" => options.help = true,
+                _ => return Err(format!("unknown option {}", arg)),
+            }
+            if inline_value.is_some() {
+                return Err(format!("{} takes no value", flag));
+            }
+        }
+        if !options.help && !options.crates.is_empty() && options.source.is_none() {
+            return Err("--source is required to pack crates".to_string());
+        }
+        Ok(options)
+    }
+}
+
+fn count(flag: &str, value: &str) -> Result<i32, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|it| *it >= 0)
+        .ok_or_else(|| format!("invalid value for {}: {}", flag, value))
+}
@@ -0,0 +1,475 @@
+//! Finding crates in an offline source directory and resolving the dependency
+//! graph between them.
+//!
+//! The source directory is either a `cargo vendor` output or a registry source
+//! dir like `~/.cargo/registry/src`, where crates are one level deeper, inside
+//! a directory per registry index.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::fs::{self, read_to_string};
+use std::path::{Path, PathBuf};
+
+use semver::{Version, VersionReq};
+use toml::Value;
+
+#[derive(Debug)]
+pub enum ResolveError {
+    Io(PathBuf, std::io::Error),
+    Manifest(PathBuf, String),
+    NotFound { name: String, req: String, required_by: Option<String> },
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResolveError::Io(path, e) => write!(f, "can't read {}: {}", path.display(), e),
+            ResolveError::Manifest(path, e) => {
+                write!(f, "invalid manifest {}: {}", path.display(), e)
+            }
+            ResolveError::NotFound { name, req, required_by } => {
+                write!(f, "no version of `{}` matching `{}` in the source directory", name, req)?;
+                if let Some(parent) = required_by {
+                    write!(f, " (required by `{}`)", parent)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+pub struct Manifest {
+    pub name: String,
+    pub version: Version,
+    pub edition: String,
+    /// Root of the library target, relative to the crate directory.
+    pub lib_path: PathBuf,
+    pub proc_macro: bool,
+    pub dependencies: Vec<DepSpec>,
+    pub features: BTreeMap<String, Vec<String>>,
+}
+
+pub struct DepSpec {
+    /// Name of the dependency as seen by the dependent crate.
+    pub name: String,
+    pub package: String,
+    pub req: VersionReq,
+    pub path: Option<PathBuf>,
+    pub optional: bool,
+    pub default_features: bool,
+    pub features: Vec<String>,
+}
+
+impl Manifest {
+    pub fn load(dir: &Path) -> Result<Manifest, ResolveError> {
+        let path = dir.join("Cargo.toml");
+        let text = read_to_string(&path).map_err(|e| ResolveError::Io(path.clone(), e))?;
+        let toml: Value = text
+            .parse()
+            .map_err(|e: toml::de::Error| ResolveError::Manifest(path.clone(), e.to_string()))?;
+        let err = |msg: &str| ResolveError::Manifest(path.clone(), msg.to_string());
+
+        let package = toml.get("package").ok_or_else(|| err("missing [package]"))?;
+        let name = package
+            .get("name")
+            .and_then(Value::as_str)
+            .ok_or_else(|| err("missing package name"))?
+            .to_string();
+        // Unpublished workspace members may inherit the version, which doesn't
+        // matter for path dependencies.
+        let version = package
+            .get("version")
+            .and_then(Value::as_str)
+            .map_or(Ok(Version::new(0, 0, 0)), Version::parse)
+            .map_err(|e| err(&e.to_string()))?;
+        let edition = package.get("edition").and_then(Value::as_str).unwrap_or("2015").to_string();
+
+        let lib = toml.get("lib");
+        let lib_path = lib
+            .and_then(|it| it.get("path"))
+            .and_then(Value::as_str)
+            .map_or_else(|| PathBuf::from("src/lib.rs"), PathBuf::from);
+        let proc_macro = lib
+            .and_then(|it| it.get("proc-macro").or_else(|| it.get("proc_macro")))
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+
+        let mut dependencies = Vec::new();
+        dep_table(toml.get("dependencies"), dir, &mut dependencies).map_err(|e| err(&e))?;
+        // Target specific dependencies are all followed, whatever the target.
+        if let Some(targets) = toml.get("target").and_then(Value::as_table) {
+            for target in targets.values() {
+                dep_table(target.get("dependencies"), dir, &mut dependencies)
+                    .map_err(|e| err(&e))?;
+            }
+        }
+
+        let features = toml
+            .get("features")
+            .and_then(Value::as_table)
+            .map(|table| {
+                table
+                    .iter()
+                    .map(|(name, enables)| {
+                        let enables = enables.as_array().map_or(&[][..], |it| it.as_slice());
+                        let enables = enables.iter().filter_map(Value::as_str).map(String::from);
+                        (name.clone(), enables.collect())
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(Manifest { name, version, edition, lib_path, proc_macro, dependencies, features })
+    }
+}
+
+fn dep_table(table: Option<&Value>, dir: &Path, acc: &mut Vec<DepSpec>) -> Result<(), String> {
+    let table = match table.and_then(Value::as_table) {
+        Some(it) => it,
+        None => return Ok(()),
+    };
+    for (name, spec) in table {
+        let str_field = |field: &str| spec.get(field).and_then(Value::as_str);
+        let bool_field = |field: &str| spec.get(field).and_then(Value::as_bool);
+        let req = spec.as_str().or_else(|| str_field("version")).unwrap_or("*");
+        let req = VersionReq::parse(req).map_err(|e| format!("dependency `{}`: {}", name, e))?;
+        let features =
+            spec.get("features").and_then(Value::as_array).map_or(&[][..], |it| it.as_slice());
+        acc.push(DepSpec {
+            name: name.replace('-', "_"),
+            package: str_field("package").unwrap_or(name).to_string(),
+            req,
+            path: str_field("path").map(|it| dir.join(it)),
+            optional: bool_field("optional").unwrap_or(false),
+            default_features: bool_field("default-features")
+                .or_else(|| bool_field("default_features"))
+                .unwrap_or(true),
+            features: features.iter().filter_map(Value::as_str).map(String::from).collect(),
+        });
+    }
+    Ok(())
+}
+
+pub struct SourceDir {
+    /// `(package name, version, crate dir)`
+    crates: Vec<(String, Version, PathBuf)>,
+}
+
+impl SourceDir {
+    pub fn open(root: &Path) -> Result<SourceDir, ResolveError> {
+        let mut crates = Vec::new();
+        for dir in sub_dirs(root)? {
+            if dir.join("Cargo.toml").exists() {
+                crates.extend(crate_dir(dir));
+            } else {
+                for dir in sub_dirs(&dir)? {
+                    crates.extend(crate_dir(dir));
+                }
+            }
+        }
+        Ok(SourceDir { crates })
+    }
+
+    /// Finds the highest version of `name` matching `req`.
+    pub fn find(&self, name: &str, req: &VersionReq) -> Option<&Path> {
+        self.crates
+            .iter()
+            .filter(|(n, version, _)| n == name && req.matches(version))
+            .max_by(|a, b| a.1.cmp(&b.1))
+            .map(|(_, _, dir)| dir.as_path())
+    }
+}
+
+fn sub_dirs(dir: &Path) -> Result<Vec<PathBuf>, ResolveError> {
+    let entries = fs::read_dir(dir).map_err(|e| ResolveError::Io(dir.to_owned(), e))?;
+    Ok(entries.filter_map(|it| Some(it.ok()?.path())).filter(|it| it.is_dir()).collect())
+}
+
+/// Reads the name and version of the crate in `dir`. Vendored crates of
+/// which only one version exists are in a directory without the version.
+fn crate_dir(dir: PathBuf) -> Option<(String, Version, PathBuf)> {
+    let manifest = Manifest::load(&dir).ok()?;
+    Some((manifest.name, manifest.version, dir))
+}
+
+pub struct CrateNode {
+    /// `name-version`, unique in the graph.
+    pub id: String,
+    pub dir: PathBuf,
+    pub manifest: Manifest,
+    pub features: BTreeSet<String>,
+    /// `(extern name, index of the dependency)`
+    pub deps: Vec<(String, usize)>,
+}
+
+impl CrateNode {
+    /// Name of the crate as used in Rust code.
+    pub fn crate_name(&self) -> String {
+        self.manifest.name.replace('-', "_")
+    }
+}
+
+/// Resolves `roots` and everything they depend on with the default features
+/// enabled. Dependencies of proc-macro crates only run at compile time and
+/// aren't followed.
+pub fn resolve(source: &SourceDir, roots: &[String]) -> Result<Vec<CrateNode>, ResolveError> {
+    let mut graph = Graph { source, nodes: Vec::new(), by_id: HashMap::new(), queue: Vec::new() };
+    for root in roots {
+        let dir = source.find(root, &VersionReq::STAR).ok_or_else(|| ResolveError::NotFound {
+            name: root.clone(),
+            req: "*".to_string(),
+            required_by: None,
+        })?;
+        let node = graph.add_node(dir.to_owned())?;
+        graph.queue.push((node, "default".to_string()));
+    }
+    graph.run()?;
+    Ok(graph.nodes)
+}
+
+struct Graph<'a> {
+    source: &'a SourceDir,
+    nodes: Vec<CrateNode>,
+    by_id: HashMap<String, usize>,
+    /// Features left to enable.
+    queue: Vec<(usize, String)>,
+}
+
+impl Graph<'_> {
+    fn run(&mut self) -> Result<(), ResolveError> {
+        // `dep?/feature` only applies if `dep` gets enabled by something else,
+        // so those are retried until nothing changes.
+        let mut weak = Vec::new();
+        loop {
+            while let Some((node, feature)) = self.queue.pop() {
+                self.enable_feature(node, feature, &mut weak)?;
+            }
+            let mut changed = false;
+            for (node, dep, feature) in std::mem::take(&mut weak) {
+                match self.active_dep(node, &dep) {
+                    Some(dep) => {
+                        self.queue.push((dep, feature));
+                        changed = true;
+                    }
+                    None => weak.push((node, dep, feature)),
+                }
+            }
+            if !changed {
+                return Ok(());
+            }
+        }
+    }
+
+    fn add_node(&mut self, dir: PathBuf) -> Result<usize, ResolveError> {
+        let manifest = Manifest::load(&dir)?;
+        let id = format!("{}-{}", manifest.name.replace('-', "_"), manifest.version);
+        if let Some(&idx) = self.by_id.get(&id) {
+            return Ok(idx);
+        }
+        let idx = self.nodes.len();
+        self.by_id.insert(id.clone(), idx);
+        let required: Vec<_> = if manifest.proc_macro {
+            Vec::new()
+        } else {
+            manifest
+                .dependencies
+                .iter()
+                .filter(|it| !it.optional)
+                .map(|it| it.name.clone())
+                .collect()
+        };
+        self.nodes.push(CrateNode {
+            id,
+            dir,
+            manifest,
+            features: BTreeSet::new(),
+            deps: Vec::new(),
+        });
+        for dep in required {
+            self.activate_dep(idx, &dep)?;
+        }
+        Ok(idx)
+    }
+
+    fn enable_feature(
+        &mut self,
+        node: usize,
+        feature: String,
+        weak: &mut Vec<(usize, String, String)>,
+    ) -> Result<(), ResolveError> {
+        if self.nodes[node].manifest.proc_macro
+            || !self.nodes[node].features.insert(feature.clone())
+        {
+            return Ok(());
+        }
+        let enables = match self.nodes[node].manifest.features.get(&feature) {
+            Some(it) => it.clone(),
+            // Optional dependencies are implicit features.
+            None => {
+                self.activate_dep(node, &feature)?;
+                return Ok(());
+            }
+        };
+        for item in enables {
+            if let Some(dep) = item.strip_prefix("dep:") {
+                self.activate_dep(node, dep)?;
+            } else if let Some((dep, feature)) = item.split_once('/') {
+                match dep.strip_suffix('?') {
+                    Some(dep) => weak.push((node, dep.to_string(), feature.to_string())),
+                    None => {
+                        let dep = self.activate_dep(node, dep)?;
+                        self.queue.extend(dep.map(|it| (it, feature.to_string())));
+                    }
+                }
+            } else {
+                self.queue.push((node, item));
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the dependency `name` of `node` to the graph, returning its index,
+    /// or `None` if `node` has no such dependency.
+    fn activate_dep(&mut self, node: usize, name: &str) -> Result<Option<usize>, ResolveError> {
+        let name = name.replace('-', "_");
+        if let Some(dep) = self.active_dep(node, &name) {
+            return Ok(Some(dep));
+        }
+        // The same dependency may be listed for several targets.
+        let spec = self.nodes[node].manifest.dependencies.iter().find(|it| it.name == name);
+        let (package, req, path, default_features, features) = match spec {
+            Some(it) => (
+                it.package.clone(),
+                it.req.clone(),
+                it.path.clone(),
+                it.default_features,
+                it.features.clone(),
+            ),
+            None => return Ok(None),
+        };
+        let dir = match path {
+            Some(path) => path,
+            None => self
+                .source
+                .find(&package, &req)
+                .ok_or_else(|| ResolveError::NotFound {
+                    name: package.clone(),
+                    req: req.to_string(),
+                    required_by: Some(self.nodes[node].id.clone()),
+                })?
+                .to_owned(),
+        };
+        let dep = self.add_node(dir)?;
+        self.nodes[node].deps.push((name, dep));
+        if default_features {
+            self.queue.push((dep, "default".to_string()));
+        }
+        self.queue.extend(features.into_iter().map(|it| (dep, it)));
+        Ok(Some(dep))
+    }
+
+    fn active_dep(&self, node: usize, name: &str) -> Option<usize> {
+        self.nodes[node].deps.iter().find(|(it, _)| it == name).map(|&(_, dep)| dep)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{resolve, CrateNode, SourceDir};
+
+    /// A source directory with the crates of `manifests`, `(dir, Cargo.toml)`.
+    fn source_dir(manifests: &[(&str, &str)]) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, manifest) in manifests {
+            let crate_dir = dir.path().join(name);
+            fs::create_dir_all(&crate_dir).unwrap();
+            fs::write(crate_dir.join("Cargo.toml"), manifest).unwrap();
+        }
+        dir
+    }
+
+    fn package(name: &str, version: &str, rest: &str) -> String {
+        format!("[package]\nname = \"{}\"\nversion = \"{}\"\n{}", name, version, rest)
+    }
+
+    fn node<'a>(nodes: &'a [CrateNode], id: &str) -> &'a CrateNode {
+        nodes.iter().find(|it| it.id == id).unwrap()
+    }
+
+    #[test]
+    fn highest_matching_version() {
+        let app = package("app", "0.1.0", "[dependencies]\nlog = \"0.4.2\"\n");
+        let dir = source_dir(&[
+            ("app", &app),
+            ("log-0.4.1", &package("log", "0.4.1", "")),
+            // Registry source dirs have one more level.
+            ("index.crates.io-6f17d22bba15001f/log-0.4.17", &package("log", "0.4.17", "")),
+            ("log-0.5.0", &package("log", "0.5.0", "")),
+        ]);
+        let source = SourceDir::open(dir.path()).unwrap();
+        let nodes = resolve(&source, &["app".to_string()]).unwrap();
+        let ids: Vec<_> = nodes.iter().map(|it| it.id.as_str()).collect();
+        assert_eq!(ids, ["app-0.1.0", "log-0.4.17"]);
+        assert_eq!(nodes[0].deps, [("log".to_string(), 1)]);
+
+        let app = package("app", "0.1.0", "[dependencies]\nlog = \"0.6\"\n");
+        let dir = source_dir(&[("app", &app), ("log", &package("log", "0.4.17", ""))]);
+        let source = SourceDir::open(dir.path()).unwrap();
+        let err = resolve(&source, &["app".to_string()]).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "no version of `log` matching `^0.6` in the source directory (required by `app-0.1.0`)"
+        );
+    }
+
+    fn resolve_features(default: &str) -> Vec<CrateNode> {
+        let app = package(
+            "app",
+            "0.1.0",
+            &format!(
+                r#"
+[dependencies]
+serde = {{ version = "1", default-features = false, features = ["derive"] }}
+json = {{ version = "1", optional = true, package = "serde-json" }}
+time = {{ version = "0.3", optional = true }}
+
+[features]
+default = [{}]
+std = ["dep:json", "serde/std", "time?/std"]
+"#,
+                default
+            ),
+        );
+        let serde =
+            package("serde", "1.0.100", "[features]\ndefault = [\"std\"]\nstd = []\nderive = []\n");
+        let time = package("time", "0.3.0", "[features]\ndefault = []\nstd = []\n");
+        let dir = source_dir(&[
+            ("app", &app),
+            ("serde", &serde),
+            ("serde-json", &package("serde-json", "1.0.0", "")),
+            ("time", &time),
+        ]);
+        let source = SourceDir::open(dir.path()).unwrap();
+        resolve(&source, &["app".to_string()]).unwrap()
+    }
+
+    fn features(node: &CrateNode) -> Vec<&str> {
+        node.features.iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn feature_resolution() {
+        let nodes = resolve_features(r#""std""#);
+        let ids: Vec<_> = nodes.iter().map(|it| it.id.as_str()).collect();
+        assert_eq!(ids, ["app-0.1.0", "serde-1.0.100", "serde_json-1.0.0"]);
+        assert_eq!(features(node(&nodes, "app-0.1.0")), ["default", "std"]);
+        // Without its default features, but with the ones enabled by `app`.
+        assert_eq!(features(node(&nodes, "serde-1.0.100")), ["derive", "std"]);
+        assert_eq!(nodes[0].deps, [("serde".to_string(), 1), ("json".to_string(), 2)]);
+
+        // `time?/std` only applies once `time` is enabled.
+        let nodes = resolve_features(r#""std", "time""#);
+        assert_eq!(features(node(&nodes, "time-0.3.0")), ["default", "std"]);
+    }
+}
@@ -0,0 +1,104 @@
+//! Packing crates from the output of `-Zunpretty=expanded`, with the code
+//! generated by their proc macros, which ra-wasm can't expand.
+//!
+//! The crate is built as the dependency of a generated package, so that cargo
+//! compiles its dependencies and proc macros with the resolved features, and
+//! only the crate itself is printed. This needs a nightly toolchain and
+//! access to the registry for the dependencies.
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+
+use toml::Value;
+
+use crate::crates::CrateNode;
+
+#[derive(Debug)]
+pub enum ExpandError {
+    Io(PathBuf, io::Error),
+    Cargo { krate: String, stderr: String },
+}
+
+impl fmt::Display for ExpandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExpandError::Io(path, e) => write!(f, "can't write {}: {}", path.display(), e),
+            ExpandError::Cargo { krate, stderr } => {
+                write!(f, "expanding `{}` failed:\n{}", krate, stderr)
+            }
+        }
+    }
+}
+
+/// The expanded source of `node` built for `target` with `toolchain`.
+pub fn expand_crate(
+    node: &CrateNode,
+    target: &str,
+    toolchain: &str,
+) -> Result<String, ExpandError> {
+    let dir = std::env::temp_dir().join("rust-pack-expand").join(&node.id);
+    let io_err = |path: &PathBuf| {
+        let path = path.clone();
+        move |e| ExpandError::Io(path, e)
+    };
+    fs::create_dir_all(&dir).map_err(io_err(&dir))?;
+    let lib = dir.join("lib.rs");
+    fs::write(&lib, "").map_err(io_err(&lib))?;
+    let manifest = dir.join("Cargo.toml");
+    fs::write(&manifest, expand_manifest(node)).map_err(io_err(&manifest))?;
+
+    let spec = format!("{}@{}", node.manifest.name, node.manifest.version);
+    let output = Command::new("cargo")
+        .arg(format!("+{}", toolchain))
+        .args(&["rustc", "--quiet", "--lib", "-p", &spec, "--target", target, "--manifest-path"])
+        .arg(&manifest)
+        .args(&["--", "-Zunpretty=expanded"])
+        .output()
+        .map_err(io_err(&manifest))?;
+    if !output.status.success() {
+        return Err(ExpandError::Cargo {
+            krate: node.id.clone(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// A package depending on `node` with its resolved features.
+fn expand_manifest(node: &CrateNode) -> String {
+    let manifest = &node.manifest;
+    // Features implied by the enabled ones are left to cargo, except for the
+    // optional dependencies a dependent may have enabled directly.
+    let features: Vec<_> = node
+        .features
+        .iter()
+        .filter(|feature| {
+            manifest.features.contains_key(*feature)
+                || manifest
+                    .dependencies
+                    .iter()
+                    .any(|dep| dep.optional && dep.name == feature.replace('-', "_"))
+        })
+        .map(|feature| Value::String(feature.clone()).to_string())
+        .collect();
+    format!(
+        "[package]\n\
+         name = \"rust-pack-expand\"\n\
+         version = \"0.0.0\"\n\
+         edition = \"2018\"\n\
+         \n\
+         [lib]\n\
+         path = \"lib.rs\"\n\
+         \n\
+         [dependencies]\n\
+         {} = {{ package = {}, path = {}, default-features = false, features = [{}] }}\n\
+         \n\
+         [workspace]\n",
+        node.crate_name(),
+        Value::String(manifest.name.clone()),
+        Value::String(node.dir.to_string_lossy().into_owned()),
+        features.join(", "),
+    )
+}
@@ -0,0 +1,252 @@
+//! Inlining the files of `include!` and `include_str!`.
+//!
+//! The path is relative to the file containing the call, like for rustc.
+//! Calls whose file can't be found, like the ones with a path built with
+//! `concat!(env!("OUT_DIR"), ..)`, are replaced with a stub and a warning: an
+//! empty string for `include_str!`, nothing for items and `loop {}` for
+//! expressions.
+use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
+
+use cfg::CfgOptions;
+use syntax::{ast, AstNode, NodeOrToken, SourceFile, SyntaxKind, TextRange, T};
+
+use crate::prune::prune;
+use crate::source_map::Mapped;
+
+/// Files including each other are given up on after this many levels.
+const MAX_DEPTH: usize = 16;
+
+pub fn inline_includes(
+    text: Mapped,
+    path: &Path,
+    cfg: &CfgOptions,
+    files: &mut Vec<PathBuf>,
+) -> Mapped {
+    inline_includes_at(text, path, cfg, files, 0)
+}
+
+fn inline_includes_at(
+    mut text: Mapped,
+    path: &Path,
+    cfg: &CfgOptions,
+    files: &mut Vec<PathBuf>,
+    depth: usize,
+) -> Mapped {
+    let file = SourceFile::parse(&text.text).tree();
+    let mut edits = Vec::new();
+    for call in include_calls(&file) {
+        let included = include_path(&call.args)
+            .map(|it| path.parent().unwrap_or_else(|| Path::new("")).join(it))
+            .filter(|_| depth < MAX_DEPTH)
+            .and_then(|it| Some((read_to_string(&it).ok()?.replace("\r\n", "\n"), it)));
+        // A token tree may be the body of a macro, with `$path` arguments
+        // resolving only once expanded.
+        if call.in_token_tree && included.is_none() {
+            continue;
+        }
+        let (name, is_item) = (call.name, call.is_item);
+        let replacement = match (name.as_str(), included) {
+            ("include_str", Some((content, _))) => Mapped::plain(raw_string(&content)),
+            ("include", Some((content, included))) => {
+                files.push(included.clone());
+                let content = prune(Mapped::file(content, files.len() as u32 - 1), cfg);
+                let content = inline_includes_at(content, &included, cfg, files, depth + 1);
+                if is_item {
+                    content
+                } else {
+                    let mut block = Mapped::plain("{\n".to_string());
+                    block.push(content);
+                    block.push(Mapped::plain("\n}".to_string()));
+                    block
+                }
+            }
+            (_, None) => {
+                eprintln!(
+                    "warning: {}: can't resolve `{}`, replaced with a stub",
+                    path.display(),
+                    &text.text[call.range]
+                );
+                let stub = match name.as_str() {
+                    "include_str" => "\"\"",
+                    _ if is_item => "",
+                    _ => "loop {}",
+                };
+                Mapped::plain(stub.to_string())
+            }
+            _ => continue,
+        };
+        edits.push((call.range, replacement));
+    }
+    // Calls nested in the arguments of another one are replaced along with it.
+    edits.sort_by_key(|(range, _)| range.start());
+    edits.dedup_by(|next, prev| prev.0.contains_range(next.0));
+    text.edit(edits);
+    text
+}
+
+struct IncludeCall {
+    name: String,
+    args: ast::TokenTree,
+    range: TextRange,
+    is_item: bool,
+    /// In the arguments of another macro, like `concat!("..", include_str!(..))`.
+    in_token_tree: bool,
+}
+
+fn include_calls(file: &SourceFile) -> Vec<IncludeCall> {
+    let mut calls = Vec::new();
+    for node in file.syntax().descendants() {
+        if let Some(call) = ast::MacroCall::cast(node.clone()) {
+            let name = match call.path().and_then(|it| it.segment()).and_then(|it| it.name_ref()) {
+                Some(it) => it.text().to_string(),
+                None => continue,
+            };
+            let args = match call.token_tree() {
+                Some(it) if name == "include" || name == "include_str" => it,
+                _ => continue,
+            };
+            let is_item = node.parent().map_or(false, |it| {
+                matches!(
+                    it.kind(),
+                    SyntaxKind::SOURCE_FILE | SyntaxKind::ITEM_LIST | SyntaxKind::MACRO_ITEMS
+                )
+            });
+            calls.push(IncludeCall {
+                name,
+                args,
+                range: node.text_range(),
+                is_item,
+                in_token_tree: false,
+            });
+        } else if ast::TokenTree::can_cast(node.kind()) {
+            // Only `include_str!`, an `include!` there isn't known to be an
+            // expression.
+            let children: Vec<_> =
+                node.children_with_tokens().filter(|it| !it.kind().is_trivia()).collect();
+            for window in children.windows(3) {
+                if let [NodeOrToken::Token(name), NodeOrToken::Token(bang), NodeOrToken::Node(args)] =
+                    window
+                {
+                    if name.text() == "include_str" && bang.kind() == T![!] {
+                        calls.push(IncludeCall {
+                            name: name.text().to_string(),
+                            args: ast::TokenTree::cast(args.clone()).unwrap(),
+                            range: TextRange::new(
+                                name.text_range().start(),
+                                args.text_range().end(),
+                            ),
+                            is_item: false,
+                            in_token_tree: true,
+                        });
+                    }
+                }
+            }
+        }
+    }
+    calls
+}
+
+/// The path of an include call with a plain string literal argument.
+fn include_path(tt: &ast::TokenTree) -> Option<String> {
+    let tokens: Vec<_> = tt
+        .syntax()
+        .children_with_tokens()
+        .filter_map(NodeOrToken::into_token)
+        .filter(|it| !it.kind().is_trivia())
+        .collect();
+    match tokens.as_slice() {
+        [_, literal, _] | [_, literal, _, _] if literal.kind() == SyntaxKind::STRING => {
+            let text = literal.text();
+            if text.starts_with('r') {
+                Some(text.trim_start_matches('r').trim_matches('#').trim_matches('"').to_string())
+            } else {
+                Some(text.trim_matches('"').replace("\\\\", "\\"))
+            }
+        }
+        _ => None,
+    }
+}
+
+/// A raw string literal for `text`.
+fn raw_string(text: &str) -> String {
+    let mut hashes = 0;
+    while text.contains(&format!("\"{}", "#".repeat(hashes))) {
+        hashes += 1;
+    }
+    let hashes = "#".repeat(hashes);
+    format!("r{}\"{}\"{}", hashes, text, hashes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::inline_includes;
+    use crate::prune::cfg_options;
+    use crate::source_map::Mapped;
+
+    /// Inlines the includes of `lib.rs` in a directory with `files`, returning
+    /// the result and the paths of the included files, relative to it.
+    fn inline(files: &[(&str, &str)]) -> (String, Vec<String>) {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, text) in files {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        let lib = dir.path().join("lib.rs");
+        let text = fs::read_to_string(&lib).unwrap();
+        let mut included = vec![lib.clone()];
+        let cfg = cfg_options(&[], &[]);
+        let res = inline_includes(Mapped::file(text, 0), &lib, &cfg, &mut included);
+        let included = included[1..]
+            .iter()
+            .map(|it| it.strip_prefix(dir.path()).unwrap().display().to_string())
+            .collect();
+        (res.text, included)
+    }
+
+    #[test]
+    fn include_items_and_expressions() {
+        let (text, included) = inline(&[
+            ("lib.rs", "include!(\"gen/items.rs\");\nfn f() -> u32 { include!(\"expr.rs\") }\n"),
+            // Relative to the file including it.
+            ("gen/items.rs", "include!(\"more.rs\");\n#[cfg(feature = \"std\")]\nfn std() {}\n"),
+            ("gen/more.rs", "struct More;"),
+            ("expr.rs", "1 + 2"),
+        ]);
+        assert_eq!(text, "struct More;\n\n\nfn f() -> u32 { {\n1 + 2\n} }\n",);
+        assert_eq!(included, ["gen/items.rs", "gen/more.rs", "expr.rs"]);
+    }
+
+    #[test]
+    fn include_str() {
+        let (text, _) = inline(&[
+            ("lib.rs", "const A: &str = include_str!(\"a.txt\");\n"),
+            ("a.txt", "say \"hi\"\n"),
+        ]);
+        assert_eq!(text, "const A: &str = r#\"say \"hi\"\n\"#;\n");
+
+        // Kept in the arguments of other macros too.
+        let (text, _) = inline(&[
+            ("lib.rs", "const A: &str = concat!(include_str!(\"a.txt\"), \"!\");\n"),
+            ("a.txt", "a"),
+        ]);
+        assert_eq!(text, "const A: &str = concat!(r\"a\", \"!\");\n");
+    }
+
+    #[test]
+    fn missing_files_are_stubbed() {
+        let (text, included) = inline(&[(
+            "lib.rs",
+            concat!(
+                "include!(concat!(env!(\"OUT_DIR\"), \"/gen.rs\"));\n",
+                "const A: &str = include_str!(\"missing.txt\");\n",
+                "fn f() -> u32 { include!(\"missing.rs\") }\n",
+            ),
+        )]);
+        assert_eq!(text, "\nconst A: &str = \"\";\nfn f() -> u32 { loop {} }\n");
+        assert!(included.is_empty());
+    }
+}
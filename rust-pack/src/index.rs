@@ -0,0 +1,451 @@
+//! The paths the items of the packed crates can be imported from, following
+//! `pub use` re-exports across the bundle.
+//!
+//! ra-wasm offers to import library items from this index instead of building
+//! the import maps of every library crate on the first completion.
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+use syntax::ast::{self, HasAttrs, HasModuleItem, HasName, HasVisibility};
+use syntax::{AstNode, SourceFile};
+
+use crate::bundle::BundleCrate;
+
+/// Re-exports of re-exports are followed this many times.
+const MAX_ROUNDS: usize = 8;
+/// Longer paths are dropped, which also stops `pub use super::*` cycles.
+const MAX_SEGMENTS: usize = 6;
+
+#[derive(Serialize)]
+pub struct Export {
+    /// Relative to the crate root, `collections::HashMap`.
+    pub path: String,
+    /// `fn`, `struct`, `enum`, `union`, `trait`, `type`, `const`, `static`,
+    /// `macro` or `mod`.
+    pub kind: &'static str,
+    /// The same for every path of an item in the bundle.
+    pub item: u32,
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    kind: &'static str,
+    item: u32,
+    /// Whether the item itself is `pub`, a path is public when the items of
+    /// all its prefixes are.
+    is_pub: bool,
+}
+
+struct Reexport {
+    module: Vec<String>,
+    path: Vec<String>,
+    /// `None` for a glob.
+    name: Option<String>,
+}
+
+struct CrateTable {
+    /// Extern names of the dependencies, to their index in the bundle.
+    deps: HashMap<String, usize>,
+    /// By path, with a `!` after the names of macros, which live in their
+    /// own namespace.
+    entries: BTreeMap<String, Entry>,
+    reexports: Vec<Reexport>,
+}
+
+/// The exports of each crate of `crates`, in order.
+pub fn exports(crates: &[BundleCrate]) -> Vec<Vec<Export>> {
+    let ids: HashMap<_, _> = crates.iter().enumerate().map(|(idx, it)| (&it.id, idx)).collect();
+    let mut tables: Vec<_> = crates
+        .iter()
+        .map(|krate| CrateTable {
+            deps: krate
+                .deps
+                .iter()
+                .filter_map(|dep| Some((dep.name.replace('-', "_"), *ids.get(&dep.krate)?)))
+                .collect(),
+            entries: BTreeMap::new(),
+            reexports: Vec::new(),
+        })
+        .collect();
+    let mut next_item = 0;
+    for (krate, table) in crates.iter().zip(&mut tables) {
+        let file = SourceFile::parse(&krate.source).tree();
+        table.collect(file.items(), &[], &mut next_item);
+    }
+    for _ in 0..MAX_ROUNDS {
+        let mut changed = false;
+        for krate in 0..tables.len() {
+            for idx in 0..tables[krate].reexports.len() {
+                changed |= reexport(&mut tables, krate, idx);
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    tables.iter().map(CrateTable::exports).collect()
+}
+
+impl CrateTable {
+    fn collect(
+        &mut self,
+        items: impl Iterator<Item = ast::Item>,
+        module: &[String],
+        next_item: &mut u32,
+    ) {
+        for item in items {
+            let kind = match &item {
+                ast::Item::Fn(_) => "fn",
+                ast::Item::Struct(_) => "struct",
+                ast::Item::Enum(_) => "enum",
+                ast::Item::Union(_) => "union",
+                ast::Item::Trait(_) => "trait",
+                ast::Item::TypeAlias(_) => "type",
+                ast::Item::Const(_) => "const",
+                ast::Item::Static(_) => "static",
+                ast::Item::MacroDef(_) => "macro",
+                ast::Item::Module(_) => "mod",
+                ast::Item::MacroRules(it) => {
+                    if has_attr(it, "macro_export") {
+                        if let Some(name) = it.name() {
+                            let is_pub = !has_doc_hidden(it);
+                            let entry = Entry { kind: "macro", item: *next_item, is_pub };
+                            *next_item += 1;
+                            self.entries.entry(format!("{}!", name)).or_insert(entry);
+                        }
+                    }
+                    continue;
+                }
+                ast::Item::ExternCrate(it) if module.is_empty() => {
+                    let name = it.name_ref().map(|it| it.text().to_string());
+                    let alias =
+                        it.rename().and_then(|it| it.name()).map(|it| it.text().to_string());
+                    if let (Some(name), Some(alias)) = (name, alias) {
+                        if let Some(&dep) = self.deps.get(&name) {
+                            self.deps.insert(alias, dep);
+                        }
+                    }
+                    continue;
+                }
+                // Other imports can't make public paths.
+                ast::Item::Use(it) if is_pub(it) => {
+                    if let Some(tree) = it.use_tree() {
+                        flatten_use_tree(tree, &[], &mut |path, name| {
+                            self.reexports.push(Reexport { module: module.to_vec(), path, name })
+                        });
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            let name = match ast::AnyHasName::cast(item.syntax().clone()).and_then(|it| it.name()) {
+                Some(it) => it.text().to_string(),
+                None => continue,
+            };
+            let is_pub = ast::AnyHasVisibility::cast(item.syntax().clone())
+                .map_or(false, |it| is_pub(&it))
+                && !has_doc_hidden(&ast::AnyHasAttrs::new(item.clone()));
+            let path = match kind {
+                "macro" => join(module, &format!("{}!", name)),
+                _ => join(module, &name),
+            };
+            self.entries.entry(path).or_insert(Entry { kind, item: *next_item, is_pub });
+            *next_item += 1;
+            if let ast::Item::Module(it) = item {
+                if let Some(items) = it.item_list() {
+                    let mut module = module.to_vec();
+                    module.push(name);
+                    self.collect(items.items(), &module, next_item);
+                }
+            }
+        }
+    }
+
+    /// The shortest public path of each item defined or re-exported here.
+    fn exports(&self) -> Vec<Export> {
+        let mut best: HashMap<u32, (&str, &'static str)> = HashMap::new();
+        for (path, entry) in &self.entries {
+            if !self.is_public(path) {
+                continue;
+            }
+            let better = |(other, _): &(&str, _)| {
+                (segments(path), path.as_str()) < (segments(other), *other)
+            };
+            match best.get(&entry.item) {
+                Some(it) if !better(it) => {}
+                _ => {
+                    best.insert(entry.item, (path, entry.kind));
+                }
+            }
+        }
+        let mut res: Vec<_> = best
+            .into_iter()
+            .map(|(item, (path, kind))| Export {
+                path: path.trim_end_matches('!').to_string(),
+                kind,
+                item,
+            })
+            .collect();
+        res.sort_by(|a, b| a.path.cmp(&b.path));
+        res
+    }
+
+    fn is_public(&self, path: &str) -> bool {
+        let mut prefix_end = 0;
+        loop {
+            let end = path[prefix_end..].find("::").map_or(path.len(), |it| prefix_end + it);
+            match self.entries.get(&path[..end]) {
+                Some(entry) if entry.is_pub => {}
+                _ => return false,
+            }
+            if end == path.len() {
+                return true;
+            }
+            prefix_end = end + 2;
+        }
+    }
+
+    /// The path of the item a use path refers to, relative to the root of the
+    /// crate it is in.
+    fn resolve(
+        &self,
+        krate: usize,
+        module: &[String],
+        path: &[String],
+    ) -> Option<(usize, Vec<String>)> {
+        let (first, rest) = path.split_first()?;
+        let res = match first.as_str() {
+            // `::dep::path`
+            "" => (*self.deps.get(rest.first()?)?, rest[1..].to_vec()),
+            "crate" => (krate, rest.to_vec()),
+            "self" => (krate, [module, rest].concat()),
+            "super" => {
+                let supers = path.iter().take_while(|it| *it == "super").count();
+                let module = &module[..module.len().checked_sub(supers)?];
+                (krate, [module, &path[supers..]].concat())
+            }
+            // Items in scope shadow extern crates.
+            name if !self.entries.contains_key(&join(module, name))
+                && self.deps.contains_key(name) =>
+            {
+                (self.deps[name], rest.to_vec())
+            }
+            _ => (krate, [module, path].concat()),
+        };
+        Some(res)
+    }
+}
+
+/// Adds the paths the re-export `idx` of `krate` makes, returns whether there
+/// were new ones.
+fn reexport(tables: &mut [CrateTable], krate: usize, idx: usize) -> bool {
+    let table = &tables[krate];
+    let reexport = &table.reexports[idx];
+    let (from_crate, from) = match table.resolve(krate, &reexport.module, &reexport.path) {
+        Some(it) => it,
+        None => return false,
+    };
+    let from_table = &tables[from_crate];
+    let mut new = Vec::new();
+    match &reexport.name {
+        Some(name) => {
+            let (from, to) = (from.join("::"), join(&reexport.module, name));
+            subtree(from_table, &from, &to, &mut new);
+            subtree(from_table, &format!("{}!", from), &format!("{}!", to), &mut new);
+        }
+        None => {
+            let from = from.join("::");
+            let prefix = if from.is_empty() { String::new() } else { format!("{}::", from) };
+            let children: Vec<_> = from_table
+                .entries
+                .range(prefix.clone()..)
+                .map(|(path, _)| path)
+                .take_while(|path| path.starts_with(&prefix))
+                .filter(|path| !path[prefix.len()..].contains("::"))
+                .collect();
+            for child in children {
+                let to = join(&reexport.module, &child[prefix.len()..]);
+                subtree(from_table, child, &to, &mut new);
+            }
+        }
+    }
+    let entries = &mut tables[krate].entries;
+    let mut changed = false;
+    for (path, entry) in new {
+        if segments(&path) <= MAX_SEGMENTS && !entries.contains_key(&path) {
+            entries.insert(path, entry);
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// The entries of `from` and its children, moved to `to`.
+fn subtree(table: &CrateTable, from: &str, to: &str, res: &mut Vec<(String, Entry)>) {
+    let entry = match table.entries.get(from) {
+        Some(it) => it,
+        None => return,
+    };
+    res.push((to.to_string(), *entry));
+    if entry.kind != "mod" {
+        return;
+    }
+    let prefix = format!("{}::", from);
+    for (path, entry) in table.entries.range(prefix.clone()..) {
+        if !path.starts_with(&prefix) {
+            break;
+        }
+        res.push((format!("{}{}", to, &path[from.len()..]), *entry));
+    }
+}
+
+/// Calls `f` with the path and the name, `None` for globs, of every import of
+/// `tree`.
+fn flatten_use_tree(
+    tree: ast::UseTree,
+    prefix: &[String],
+    f: &mut dyn FnMut(Vec<String>, Option<String>),
+) {
+    let mut path = prefix.to_vec();
+    if let Some(it) = tree.path() {
+        let text: String =
+            it.syntax().text().to_string().chars().filter(|it| !it.is_whitespace()).collect();
+        path.extend(text.split("::").map(String::from));
+    }
+    if let Some(list) = tree.use_tree_list() {
+        for tree in list.use_trees() {
+            flatten_use_tree(tree, &path, f);
+        }
+    } else if tree.star_token().is_some() {
+        f(path, None);
+    } else {
+        // `use a::{self}`
+        if path.last().map_or(false, |it| it == "self") {
+            path.pop();
+        }
+        let name = match tree.rename() {
+            // `use a as _` can't be named.
+            Some(rename) => match rename.name() {
+                Some(it) => it.text().to_string(),
+                None => return,
+            },
+            None => match path.last() {
+                Some(it) => it.clone(),
+                None => return,
+            },
+        };
+        f(path, Some(name));
+    }
+}
+
+fn is_pub(it: &impl HasVisibility) -> bool {
+    it.visibility().map_or(false, |it| it.syntax().text() == "pub")
+}
+
+fn has_attr(it: &impl HasAttrs, name: &str) -> bool {
+    it.attrs().any(|attr| attr.simple_name().map_or(false, |it| it == name))
+}
+
+fn has_doc_hidden(it: &impl HasAttrs) -> bool {
+    it.attrs().any(|attr| {
+        attr.as_simple_call().map_or(false, |(name, tt)| {
+            name == "doc" && tt.syntax().text().to_string().contains("hidden")
+        })
+    })
+}
+
+fn join(module: &[String], name: &str) -> String {
+    if module.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}::{}", module.join("::"), name)
+    }
+}
+
+fn segments(path: &str) -> usize {
+    path.matches("::").count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::exports;
+    use crate::bundle::{BundleCrate, BundleDep};
+
+    fn krate(name: &str, deps: &[&str], source: &str) -> BundleCrate {
+        BundleCrate {
+            id: name.to_string(),
+            name: name.to_string(),
+            edition: "2021".to_string(),
+            cfg: Vec::new(),
+            features: Vec::new(),
+            deps: deps
+                .iter()
+                .map(|dep| BundleDep { krate: dep.to_string(), name: dep.to_string() })
+                .collect(),
+            proc_macro: false,
+            root: false,
+            source_map: None,
+            exports: Vec::new(),
+            source: source.to_string(),
+        }
+    }
+
+    /// `(path, kind, item)` of the exports of each crate.
+    fn check(crates: &[BundleCrate]) -> Vec<Vec<(String, &'static str, u32)>> {
+        let exports = exports(crates);
+        exports
+            .into_iter()
+            .map(|it| it.into_iter().map(|it| (it.path, it.kind, it.item)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn reexports() {
+        let dep = r#"
+pub mod inner {
+    pub struct Thing;
+    pub fn helper() {}
+    #[doc(hidden)]
+    pub struct Hidden;
+}
+mod private {
+    pub struct Secret;
+}
+pub use private::Secret as Public;
+#[macro_export]
+macro_rules! m {
+    () => {};
+}
+"#;
+        let app = r#"
+pub use dep::inner::*;
+pub mod prelude {
+    pub use dep::inner::Thing;
+    pub use dep::m;
+    pub use super::*;
+}
+"#;
+        let res = check(&[krate("dep", &[], dep), krate("app", &["dep"], app)]);
+        let own = |path: &str, kind, item| (path.to_string(), kind, item);
+        assert_eq!(
+            res[0],
+            [
+                own("Public", "struct", 5),
+                own("inner", "mod", 0),
+                own("inner::Thing", "struct", 1),
+                own("inner::helper", "fn", 2),
+                own("m", "macro", 6)
+            ]
+        );
+        // The shortest path of each item, with the ones of `dep`.
+        assert_eq!(
+            res[1],
+            [
+                own("Thing", "struct", 1),
+                own("helper", "fn", 2),
+                own("prelude", "mod", 7),
+                own("prelude::m", "macro", 6)
+            ]
+        );
+    }
+}
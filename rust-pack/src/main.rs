@@ -1,192 +1,301 @@
-#![allow(unused)]
-use core::panic;
+use std::fmt;
 use std::fs;
-use std::fs::read_to_string;
-use std::path::Path;
-use std::process::Command;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
 
-mod remove_function;
+use syntax::SourceFile;
 
-struct Mod<'a> {
-    pub_prefix: &'a str,
-    explicit_path: Option<&'a str>,
-    name: &'a str,
-}
+mod bundle;
+mod cli;
+mod crates;
+mod expand;
+mod includes;
+mod index;
+mod modules;
+mod prune;
+mod source_map;
+mod stub;
+
+use cli::Options;
+use modules::{put_module_in_string, Inliner, MyError};
+use source_map::{Mapped, SourceMap};
+use stub::stub_function_bodies;
 
-#[derive(Default, Debug)]
-struct ModState<'a> {
-    path_seen: Option<&'a str>,
-    in_attribute: bool,
+#[derive(Debug)]
+enum PackError {
+    Usage(String),
+    Rustc(String),
+    Resolve(crates::ResolveError),
+    Module {
+        krate: String,
+        error: MyError,
+    },
+    Io(PathBuf, io::Error),
+    /// The packed crate doesn't parse.
+    Verify {
+        krate: String,
+        errors: Vec<String>,
+    },
 }
 
-fn remove_comment(line: &str) -> &str {
-    if let Some((l, _)) = line.split_once("//") {
-        l.trim()
-    } else {
-        line
+impl fmt::Display for PackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PackError::Usage(e) | PackError::Rustc(e) => write!(f, "{}", e),
+            PackError::Resolve(e) => write!(f, "{}", e),
+            PackError::Module { krate, error } => write!(f, "packing {}: {}", krate, error),
+            PackError::Io(path, e) => write!(f, "can't write {}: {}", path.display(), e),
+            PackError::Verify { krate, errors } => {
+                write!(f, "packed {} doesn't parse:", krate)?;
+                for error in errors {
+                    write!(f, "\n  {}", error)?;
+                }
+                Ok(())
+            }
+        }
     }
 }
 
-fn clean_token(line: &str) -> &str {
-    if let Some(l) = line.strip_prefix("r#") {
-        l
-    } else {
-        line
-    }
+/// A packed crate, with the files its source map refers to.
+struct Packed {
+    source: Mapped,
+    files: Vec<PathBuf>,
 }
 
-fn is_external_mod<'a>(mod_state: &mut ModState<'a>, line: &'a str) -> Option<Mod<'a>> {
-    let line = remove_comment(line);
-    if line.is_empty() {
-        return None;
+fn library(options: &Options) -> Result<PathBuf, PackError> {
+    match &options.sysroot {
+        Some(it) => Ok(it.clone()),
+        None => {
+            let sysroot = bundle::sysroot().map_err(PackError::Rustc)?;
+            Ok(Path::new(&sysroot).join("lib/rustlib/src/rust/library"))
+        }
     }
-    if line.starts_with("#[path = ") {
-        mod_state.path_seen = Some(&line[10..line.len() - 2]);
-        return None;
+}
+
+/// Inlines the module tree of the crate rooted at `root`, pruned for `cfg`.
+fn pack_root(
+    name: &str,
+    root: &Path,
+    cfg: &cfg::CfgOptions,
+    options: &Options,
+) -> Result<Packed, PackError> {
+    let mut inliner =
+        Inliner { cfg, budget: options.max_modules, verbose: options.verbose, files: Vec::new() };
+    let mut source = Mapped::default();
+    put_module_in_string(&mut source, root, 0, options.max_root_modules, &mut inliner)
+        .map_err(|error| PackError::Module { krate: name.to_string(), error })?;
+    Ok(Packed { source, files: inliner.files })
+}
+
+/// Stubs the function bodies of a packed crate and checks it still parses.
+fn finish(name: &str, source: Mapped, options: &Options) -> Result<Mapped, PackError> {
+    let (source, report) = stub_function_bodies(source);
+    println!("{}: {}", name, report);
+    if options.verify {
+        verify(name, &source.text)?;
     }
-    if line.starts_with("#[") {
-        if !line.ends_with(']') {
-            mod_state.in_attribute = true;
-        }
-        return None;
+    Ok(source)
+}
+
+fn verify(name: &str, text: &str) -> Result<(), PackError> {
+    let errors = SourceFile::parse(text).errors().to_vec();
+    if errors.is_empty() {
+        return Ok(());
     }
-    if mod_state.in_attribute {
-        if line.ends_with(']') {
-            mod_state.in_attribute = false;
-        }
-        return None;
-    }
-    let current_mod_state = std::mem::take(mod_state);
-    if !line.ends_with(';') {
-        return None;
-    }
-    let line = &line[..line.len() - 1];
-    if let Some(line) = line.strip_prefix("mod ") {
-        Some(Mod {
-            explicit_path: current_mod_state.path_seen,
-            pub_prefix: "",
-            name: clean_token(line),
-        })
-    } else if let Some(line) = line.strip_prefix("pub mod ") {
-        Some(Mod {
-            explicit_path: current_mod_state.path_seen,
-            pub_prefix: "pub ",
-            name: clean_token(line),
-        })
-    } else if let Some(line) = line.strip_prefix("pub(crate) mod ") {
-        Some(Mod {
-            explicit_path: current_mod_state.path_seen,
-            pub_prefix: "pub(crate) ",
-            name: clean_token(line),
+    let mut messages: Vec<_> = errors
+        .iter()
+        .take(10)
+        .map(|e| {
+            let offset = usize::from(e.range().start());
+            format!("line {}: {}", text[..offset].matches('\n').count() + 1, e)
         })
-    } else if let Some(line) = line.strip_prefix("pub(self) mod ") {
-        Some(Mod {
-            explicit_path: current_mod_state.path_seen,
-            pub_prefix: "pub(self) ",
-            name: clean_token(line),
-        })
-    } else if let Some(line) = line.strip_prefix("pub(super) mod ") {
-        Some(Mod {
-            explicit_path: current_mod_state.path_seen,
-            pub_prefix: "pub(super) ",
-            name: clean_token(line),
-        })
-    } else if let Some(line) = line.strip_prefix("pub(in ") {
-        panic!("pub in not supported: {}", line);
-    } else {
-        None
+        .collect();
+    if errors.len() > messages.len() {
+        messages.push(format!("and {} more", errors.len() - messages.len()));
     }
+    Err(PackError::Verify { krate: name.to_string(), errors: messages })
 }
 
-trait MyStringMethods {
-    fn push_line(&mut self, line: &str);
+fn write(path: &Path, contents: impl AsRef<[u8]>, options: &Options) -> Result<(), PackError> {
+    if options.dry_run {
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| PackError::Io(dir.to_owned(), e))?;
+    }
+    fs::write(path, contents).map_err(|e| PackError::Io(path.to_owned(), e))
 }
 
-impl MyStringMethods for String {
-    fn push_line(&mut self, line: &str) {
-        self.push_str(line);
-        self.push('\n');
+/// Packs `std`, `alloc` and `core` into `<out>/fake_<crate>.rs`.
+fn pack_sysroot(target: &str, options: &Options) -> Result<(), PackError> {
+    let library = library(options)?;
+    let cfg = prune::cfg_options(&bundle::target_cfg(target).map_err(PackError::Rustc)?, &[]);
+    for what in &["std", "alloc", "core"] {
+        println!("packing {} for {}", what, target);
+        let packed = pack_root(what, &library.join(what).join("src/lib.rs"), &cfg, options)?;
+        let source = finish(what, packed.source, options)?;
+        write(&options.out.join(format!("fake_{}.rs", what)), &source.text, options)?;
     }
+    Ok(())
 }
 
-#[derive(Default, Debug)]
-struct MyError {
-    libstack: Vec<String>,
-    cause_module: String,
+/// Packs `std`, `alloc`, `core` and the crates of `options` with their
+/// dependencies into a bundle per target, `<out>/<target>/bundle.bin`.
+fn pack_crates(source_dir: &Path, targets: &[String], options: &Options) -> Result<(), PackError> {
+    let source = crates::SourceDir::open(source_dir).map_err(PackError::Resolve)?;
+    let graph = crates::resolve(&source, &options.crates).map_err(PackError::Resolve)?;
+    for target in targets {
+        pack_target(&graph, target, &options.out.join(target), options)?;
+    }
+    Ok(())
 }
 
-fn put_module_in_string(
-    output: &mut String,
-    path: &Path,
-    depth: usize,
-    mut expand_cnt: i32,
-) -> Result<(), MyError> {
-    let src = read_to_string(path).map_err(|_x| MyError {
-        libstack: vec![],
-        cause_module: path.to_string_lossy().to_string(),
-    })?;
-    let mut mod_state = ModState::default();
-    for line in src.lines() {
-        if let Some(m) = is_external_mod(&mut mod_state, line) {
-            if expand_cnt == 0 {
-                continue;
-            };
-            let rr = 10000;
-            expand_cnt -= 1;
-            println!("{} mod found: {}", ">".repeat(depth), line);
-            output.push_line(&format!("{}mod {} {{", m.pub_prefix, m.name));
-            let mut parent_path = path.parent().unwrap().to_owned();
-            let file_name =
-                path.file_name().unwrap().to_str().unwrap().strip_suffix(".rs").unwrap();
-            if file_name != "lib" && file_name != "mod" {
-                parent_path = parent_path.join(file_name);
-            }
-            let same_level_path = parent_path.join(format!("{}.rs", m.name));
-            let folder_path = parent_path.join(format!("{}/mod.rs", m.name));
-            let child_path = if let Some(ep) = m.explicit_path {
-                println!("explicit path found: {:?}", ep);
-                path.parent().unwrap().join(ep)
-            } else if same_level_path.exists() {
-                same_level_path
-            } else if folder_path.exists() {
-                folder_path
+/// Writes the bundle of `graph` pruned for `target`. Each crate is also
+/// written to its own file for inspection.
+fn pack_target(
+    graph: &[crates::CrateNode],
+    target: &str,
+    output_dir: &Path,
+    options: &Options,
+) -> Result<(), PackError> {
+    let cfg = bundle::target_cfg(target).map_err(PackError::Rustc)?;
+    let mut bundle = bundle::Bundle { target: target.to_string(), crates: Vec::new() };
+
+    let library = library(options)?;
+    // Paths like `library/core/src/option.rs`.
+    let library_base = library.parent().unwrap_or(&library);
+    let sysroot_deps: &[(&str, &[&str])] =
+        &[("core", &[]), ("alloc", &["core"]), ("std", &["core", "alloc"])];
+    for (name, deps) in sysroot_deps {
+        println!("packing {} for {}", name, target);
+        let dir = library.join(name);
+        let manifest = crates::Manifest::load(&dir).map_err(PackError::Resolve)?;
+        let crate_cfg = prune::cfg_options(&cfg, &[]);
+        let packed = pack_root(name, &dir.join(&manifest.lib_path), &crate_cfg, options)?;
+        let source = finish(name, packed.source, options)?;
+        write(&output_dir.join(format!("{}.rs", name)), &source.text, options)?;
+        bundle.crates.push(bundle::BundleCrate {
+            id: name.to_string(),
+            name: name.to_string(),
+            edition: manifest.edition,
+            cfg: cfg.clone(),
+            features: Vec::new(),
+            deps: deps
+                .iter()
+                .map(|it| bundle::BundleDep { krate: it.to_string(), name: it.to_string() })
+                .collect(),
+            proc_macro: false,
+            root: true,
+            source_map: Some(SourceMap::new(&packed.files, library_base, source.segments)),
+            exports: Vec::new(),
+            source: source.text,
+        });
+    }
+
+    for node in graph {
+        let features: Vec<_> = node.features.iter().cloned().collect();
+        let mut source = Mapped::default();
+        let mut files = Vec::new();
+        if !node.manifest.proc_macro {
+            println!("packing {} for {}", node.id, target);
+            let expanded = if options.expand {
+                expand::expand_crate(node, target, &options.toolchain)
+                    .map_err(|e| eprintln!("{}, packing the sources instead", e))
+                    .ok()
             } else {
-                println!(
-                    "same_level_path: {:?}\nfolder_path: {:?}\n",
-                    same_level_path, folder_path
-                );
-                return Err(MyError {
-                    libstack: vec![path.to_string_lossy().to_string()],
-                    cause_module: folder_path.to_string_lossy().to_string(),
-                });
+                None
             };
-            if let Err(mut e) = put_module_in_string(output, &child_path, depth + 1, rr) {
-                e.libstack.push(path.to_string_lossy().to_string());
-                return Err(e);
-            }
-            output.push_line("}");
+            let packed = match expanded {
+                // Already without the cfgs the target and features disable.
+                Some(expanded) => Packed { source: Mapped::plain(expanded), files: Vec::new() },
+                None => {
+                    let crate_cfg = prune::cfg_options(&cfg, &features);
+                    let root = node.dir.join(&node.manifest.lib_path);
+                    pack_root(&node.id, &root, &crate_cfg, options)?
+                }
+            };
+            source = finish(&node.id, packed.source, options)?;
+            files = packed.files;
+            write(&output_dir.join(format!("{}.rs", node.id)), &source.text, options)?;
+        }
+        // Paths like `serde-1.0.130/src/de/mod.rs`.
+        let base = node.dir.parent().unwrap_or(&node.dir);
+        let source_map = if files.is_empty() {
+            None
         } else {
-            output.push_line(line);
+            Some(SourceMap::new(&files, base, source.segments))
+        };
+        let mut deps: Vec<_> = node
+            .deps
+            .iter()
+            .map(|(name, dep)| bundle::BundleDep {
+                krate: graph[*dep].id.clone(),
+                name: name.clone(),
+            })
+            .collect();
+        for name in &["core", "alloc", "std"] {
+            deps.push(bundle::BundleDep { krate: name.to_string(), name: name.to_string() });
         }
+        bundle.crates.push(bundle::BundleCrate {
+            id: node.id.clone(),
+            name: node.crate_name(),
+            edition: node.manifest.edition.clone(),
+            cfg: cfg.clone(),
+            features,
+            deps,
+            proc_macro: node.manifest.proc_macro,
+            root: options.crates.iter().any(|it| it.replace('-', "_") == node.crate_name()),
+            source_map,
+            exports: Vec::new(),
+            source: source.text,
+        });
+    }
+    let exports = index::exports(&bundle.crates);
+    for (krate, exports) in bundle.crates.iter_mut().zip(exports) {
+        krate.exports = exports;
+    }
+    if options.json {
+        let json = serde_json::to_string(&bundle).unwrap();
+        write(&output_dir.join("bundle.json"), &json, options)?;
+    }
+    let binary = bundle.to_binary();
+    println!("{}: bundle of {} bytes", target, binary.len());
+    write(&output_dir.join("bundle.bin"), &binary, options)
+}
+
+fn run(options: &Options) -> Result<(), PackError> {
+    let targets = match options.targets.as_slice() {
+        [] => vec![bundle::host_target().map_err(PackError::Rustc)?],
+        targets => targets.to_vec(),
+    };
+    match (&options.source, targets.as_slice()) {
+        (Some(source_dir), _) if !options.crates.is_empty() => {
+            pack_crates(source_dir, &targets, options)
+        }
+        (_, [target]) => pack_sysroot(target, options),
+        _ => Err(PackError::Usage("packing only the sysroot takes a single target".to_string())),
     }
-    Ok(())
 }
 
 fn main() {
-    let rustc_result = Command::new("rustc")
-        .args(&["--print", "sysroot"])
-        .output()
-        .expect("Failed to execute rustc")
-        .stdout;
-    let sysroot = std::str::from_utf8(&rustc_result).expect("rustc output wasn't utf8");
-    for what in &["std", "alloc", "core"] {
-        let path_string =
-            &format!("{}/lib/rustlib/src/rust/library/{}/src/lib.rs", sysroot.trim(), what);
-        let path = Path::new(&path_string);
-        let output_path = format!("../www/fake_{}.rs", what);
-        let mut output = String::default();
-        put_module_in_string(&mut output, path, 0, 4000).unwrap();
-        //FIXME: add it when ready: output = remove_function_body(&output);
-        fs::write(output_path, output.clone()).unwrap();
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(it) => it,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, cli::USAGE);
+            process::exit(2);
+        }
+    };
+    if options.help {
+        print!("{}", cli::USAGE);
+        return;
+    }
+    if let Err(e) = run(&options) {
+        eprintln!("error: {}", e);
+        let code = match e {
+            PackError::Usage(_) => 2,
+            _ => 1,
+        };
+        process::exit(code);
     }
 }
@@ -0,0 +1,438 @@
+//! Inlining out-of-line modules into a single file.
+//!
+//! Module files are found following the rules of rustc: a file owns the
+//! directory it is in if it is a crate root, a `mod.rs` or was loaded through
+//! `#[path]`, otherwise it owns the directory named after it. Inline modules
+//! add their name to the directory of their children, and `#[path]` is
+//! relative to the directory of the file, unless it is inside an inline
+//! module.
+//!
+//! `mod` declarations inside macro calls, like the ones `cfg_if!` is used
+//! with, are inlined too. Each file is pruned for the target before its
+//! modules are looked for, so the files of other platforms aren't read, and
+//! the files it includes are inlined.
+use std::fmt;
+use std::fs::read_to_string;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use cfg::CfgOptions;
+use syntax::{
+    ast::{self, HasAttrs, HasModuleItem, HasName},
+    AstNode, NodeOrToken, SourceFile, SyntaxKind, SyntaxToken, TextRange, T,
+};
+
+use crate::includes::inline_includes;
+use crate::prune::prune;
+use crate::source_map::Mapped;
+
+#[derive(Debug)]
+pub enum ModuleError {
+    Read { path: PathBuf, error: io::Error },
+    NotFound { module: String, declared_in: PathBuf, candidates: Vec<PathBuf> },
+}
+
+impl fmt::Display for ModuleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModuleError::Read { path, error } => {
+                write!(f, "can't read {}: {}", path.display(), error)
+            }
+            ModuleError::NotFound { module, declared_in, candidates } => {
+                write!(
+                    f,
+                    "file for module `{}` declared in {} not found, tried",
+                    module,
+                    declared_in.display()
+                )?;
+                for candidate in candidates {
+                    write!(f, " {}", candidate.display())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MyError {
+    /// Files being inlined when the error happened, innermost first.
+    pub libstack: Vec<String>,
+    pub cause: ModuleError,
+}
+
+impl fmt::Display for MyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.cause)?;
+        for file in &self.libstack {
+            write!(f, "\n  while inlining {}", file)?;
+        }
+        Ok(())
+    }
+}
+
+/// How the module tree of a crate is inlined.
+pub struct Inliner<'a> {
+    pub cfg: &'a CfgOptions,
+    /// How many modules declared in each file other than the root are inlined.
+    pub budget: i32,
+    /// Print every inlined module.
+    pub verbose: bool,
+    /// The files read so far, which the segments of the output refer to.
+    pub files: Vec<PathBuf>,
+}
+
+/// Appends the module tree rooted at `path` to `output`. At most `expand_cnt`
+/// modules declared directly in `path` are inlined, the others are kept as
+/// declarations.
+pub fn put_module_in_string(
+    output: &mut Mapped,
+    path: &Path,
+    depth: usize,
+    expand_cnt: i32,
+    inliner: &mut Inliner,
+) -> Result<(), MyError> {
+    let mut expand_cnt = expand_cnt;
+    let text = inliner.inline_file(path, true, depth, &mut expand_cnt)?;
+    output.push(text);
+    Ok(())
+}
+
+impl Inliner<'_> {
+    /// Returns the text of `path` with its out-of-line modules inlined.
+    fn inline_file(
+        &mut self,
+        path: &Path,
+        owns_dir: bool,
+        depth: usize,
+        budget: &mut i32,
+    ) -> Result<Mapped, MyError> {
+        let text = read_to_string(path).map_err(|error| MyError {
+            libstack: Vec::new(),
+            cause: ModuleError::Read { path: path.to_owned(), error },
+        })?;
+        self.files.push(path.to_owned());
+        // Like rustc, which would accept a `\` before a `\r\n` in a string.
+        let text = text.replace("\r\n", "\n");
+        let text = prune(Mapped::file(text, self.files.len() as u32 - 1), self.cfg);
+        let text = inline_includes(text, path, self.cfg, &mut self.files);
+        let file_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let dir = if owns_dir {
+            file_dir.to_owned()
+        } else {
+            file_dir.join(path.file_stem().unwrap_or_default())
+        };
+
+        let mut decls = Vec::new();
+        let file = SourceFile::parse(&text.text).tree();
+        collect_items(file.items(), &dir, false, &mut decls);
+
+        let mut edits = Vec::new();
+        for decl in decls {
+            if *budget == 0 {
+                break;
+            }
+            *budget -= 1;
+            if self.verbose {
+                println!("{} mod found: {}", ">".repeat(depth), decl.name);
+            }
+            let (child, owns_dir) = decl.resolve(path).map_err(|cause| MyError {
+                libstack: vec![path.to_string_lossy().to_string()],
+                cause,
+            })?;
+            let mut child_budget = self.budget;
+            let content = self
+                .inline_file(&child, owns_dir, depth + 1, &mut child_budget)
+                .map_err(|mut e| {
+                    e.libstack.push(path.to_string_lossy().to_string());
+                    e
+                })?;
+            let mut replacement = Mapped::plain(" {\n".to_string());
+            replacement.push(content);
+            replacement.push(Mapped::plain("\n}".to_string()));
+            edits.push((decl.semicolon, replacement));
+        }
+
+        let mut res = text;
+        res.edit(edits);
+        Ok(res)
+    }
+}
+
+/// A `mod name;` declaration.
+struct ModDecl {
+    name: String,
+    /// Directory for the module file, from the directory of the declaring file
+    /// and the inline modules around the declaration.
+    dir: PathBuf,
+    in_inline_mod: bool,
+    paths: PathAttrs,
+    semicolon: TextRange,
+}
+
+#[derive(Default)]
+struct PathAttrs {
+    plain: Option<String>,
+    /// Values of `#[cfg_attr(.., path = ..)]` in macro calls, which pruning
+    /// doesn't see into. The cfgs aren't evaluated, the first path that exists
+    /// is used.
+    cfg_attr: Vec<String>,
+}
+
+impl ModDecl {
+    /// Finds the file of the module, and whether it owns its directory.
+    fn resolve(&self, file: &Path) -> Result<(PathBuf, bool), ModuleError> {
+        let file_dir = file.parent().unwrap_or_else(|| Path::new(""));
+        let base = if self.in_inline_mod { &self.dir } else { file_dir };
+        let candidates: Vec<_> = match &self.paths.plain {
+            Some(path) => vec![(base.join(path), true)],
+            None => self
+                .paths
+                .cfg_attr
+                .iter()
+                .map(|path| (base.join(path), true))
+                .chain(Some((self.dir.join(format!("{}.rs", self.name)), false)))
+                .chain(Some((self.dir.join(&self.name).join("mod.rs"), true)))
+                .collect(),
+        };
+        match candidates.iter().find(|(path, _)| path.exists()) {
+            Some(it) => Ok(it.clone()),
+            None => Err(ModuleError::NotFound {
+                module: self.name.clone(),
+                declared_in: file.to_owned(),
+                candidates: candidates.into_iter().map(|(path, _)| path).collect(),
+            }),
+        }
+    }
+}
+
+fn collect_items(
+    items: impl Iterator<Item = ast::Item>,
+    dir: &Path,
+    in_inline_mod: bool,
+    acc: &mut Vec<ModDecl>,
+) {
+    for item in items {
+        match item {
+            ast::Item::Module(module) => {
+                let name = match module.name() {
+                    Some(it) => it.text().trim_start_matches("r#").to_string(),
+                    None => continue,
+                };
+                let paths = path_attrs(module.attrs());
+                if let Some(item_list) = module.item_list() {
+                    let dir = match &paths.plain {
+                        Some(path) => dir.join(path),
+                        None => dir.join(&name),
+                    };
+                    collect_items(item_list.items(), &dir, true, acc);
+                } else if let Some(semicolon) = module.semicolon_token() {
+                    acc.push(ModDecl {
+                        name,
+                        dir: dir.to_owned(),
+                        in_inline_mod,
+                        paths,
+                        semicolon: semicolon.text_range(),
+                    });
+                }
+            }
+            ast::Item::MacroCall(call) => {
+                if let Some(tt) = call.token_tree() {
+                    collect_macro_decls(
+                        tt.syntax().descendants_with_tokens(),
+                        dir,
+                        in_inline_mod,
+                        acc,
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Finds `#[attrs] vis mod name;` in the tokens of a macro call.
+fn collect_macro_decls(
+    elements: impl Iterator<Item = syntax::SyntaxElement>,
+    dir: &Path,
+    in_inline_mod: bool,
+    acc: &mut Vec<ModDecl>,
+) {
+    let tokens: Vec<SyntaxToken> =
+        elements.filter_map(NodeOrToken::into_token).filter(|it| !it.kind().is_trivia()).collect();
+    for (idx, window) in tokens.windows(3).enumerate() {
+        let is_decl = window[0].kind() == T![mod]
+            && window[1].kind() == SyntaxKind::IDENT
+            && window[2].kind() == T![;];
+        if !is_decl {
+            continue;
+        }
+        acc.push(ModDecl {
+            name: window[1].text().trim_start_matches("r#").to_string(),
+            dir: dir.to_owned(),
+            in_inline_mod,
+            paths: macro_path_attrs(&tokens[..idx]),
+            semicolon: window[2].text_range(),
+        });
+    }
+}
+
+fn path_attrs(attrs: impl Iterator<Item = ast::Attr>) -> PathAttrs {
+    let mut res = PathAttrs::default();
+    for attr in attrs {
+        let tokens: Vec<_> = attr
+            .syntax()
+            .descendants_with_tokens()
+            .filter_map(NodeOrToken::into_token)
+            .filter(|it| !it.kind().is_trivia())
+            .collect();
+        match attr.simple_name().as_deref() {
+            Some("path") => res.plain = path_value(&tokens),
+            Some("cfg_attr") => res.cfg_attr.extend(path_value(&tokens)),
+            _ => {}
+        }
+    }
+    res
+}
+
+/// `#[path]` attributes right before the tokens of a `mod` declaration inside
+/// a macro call, skipping its visibility.
+fn macro_path_attrs(before: &[SyntaxToken]) -> PathAttrs {
+    let mut end = before.len();
+    if end > 0 && before[end - 1].kind() == T![')'] {
+        while end > 0 && before[end - 1].kind() != T!['('] {
+            end -= 1;
+        }
+        end = end.saturating_sub(1);
+    }
+    if end > 0 && before[end - 1].kind() == T![pub] {
+        end -= 1;
+    }
+    let mut res = PathAttrs::default();
+    while end > 1 && before[end - 1].kind() == T![']'] {
+        let start = match before[..end].iter().rposition(|it| it.kind() == T!['[']) {
+            Some(it) if it > 0 && before[it - 1].kind() == T![#] => it,
+            _ => break,
+        };
+        let attr = &before[start..end];
+        match attr.get(1).map(|it| it.text().to_string()).as_deref() {
+            Some("path") => res.plain = path_value(attr),
+            Some("cfg_attr") => res.cfg_attr.extend(path_value(attr)),
+            _ => {}
+        }
+        end = start - 1;
+    }
+    res
+}
+
+/// Finds `path = "value"` in the tokens of an attribute.
+fn path_value(tokens: &[SyntaxToken]) -> Option<String> {
+    tokens.windows(3).find_map(|it| {
+        let is_path = it[0].text() == "path" && it[1].kind() == T![=];
+        if !is_path {
+            return None;
+        }
+        match it[2].kind() {
+            SyntaxKind::STRING => Some(it[2].text().trim_matches('"').to_string()),
+            _ => None,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use cfg::CfgOptions;
+
+    use super::{put_module_in_string, Inliner};
+    use crate::source_map::Mapped;
+
+    /// Writes `files`, `(path, text)`, and inlines the crate rooted at the
+    /// first one.
+    fn inline(files: &[(&str, &str)]) -> String {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, text) in files {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, text).unwrap();
+        }
+        let cfg = CfgOptions::default();
+        let mut inliner = Inliner { cfg: &cfg, budget: 100, verbose: false, files: Vec::new() };
+        let mut res = Mapped::default();
+        put_module_in_string(&mut res, &dir.path().join(files[0].0), 0, 100, &mut inliner).unwrap();
+        assert_eq!(inliner.files.len(), files.len());
+        res.text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    #[test]
+    fn mod_rs_dirs() {
+        let res = inline(&[
+            ("src/lib.rs", "mod a; mod b;"),
+            ("src/a.rs", "mod c;"),
+            ("src/a/c.rs", "fn c() {}"),
+            ("src/b/mod.rs", "mod d;"),
+            ("src/b/d.rs", "fn d() {}"),
+        ]);
+        assert_eq!(res, "mod a { mod c { fn c() {} } } mod b { mod d { fn d() {} } }");
+    }
+
+    #[test]
+    fn path_in_inline_mod() {
+        let res = inline(&[
+            ("src/lib.rs", r#"mod outer { #[path = "x.rs"] mod b; mod c; }"#),
+            ("src/outer/x.rs", "fn b() {}"),
+            ("src/outer/c.rs", "fn c() {}"),
+        ]);
+        assert_eq!(
+            res,
+            r#"mod outer { #[path = "x.rs"] mod b { fn b() {} } mod c { fn c() {} } }"#
+        );
+    }
+
+    #[test]
+    fn path_in_non_mod_rs_file() {
+        // Relative to the directory of the file, unless in an inline module,
+        // which is relative to the directory the file owns.
+        let res = inline(&[
+            ("src/lib.rs", "mod a;"),
+            ("src/a.rs", r#"#[path = "other.rs"] mod b; mod inner { #[path = "y.rs"] mod c; }"#),
+            ("src/other.rs", "fn b() {}"),
+            ("src/a/inner/y.rs", "fn c() {}"),
+        ]);
+        assert_eq!(
+            res,
+            concat!(
+                r#"mod a { #[path = "other.rs"] mod b { fn b() {} } "#,
+                r#"mod inner { #[path = "y.rs"] mod c { fn c() {} } } }"#,
+            )
+        );
+    }
+
+    #[test]
+    fn mod_in_macro_call() {
+        let res =
+            inline(&[("src/lib.rs", "some_macro! { pub mod a; }"), ("src/a.rs", "fn a() {}")]);
+        assert_eq!(res, "some_macro! { pub mod a { fn a() {} } }");
+    }
+
+    #[test]
+    fn missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("lib.rs"), "mod missing;").unwrap();
+        let cfg = CfgOptions::default();
+        let mut inliner = Inliner { cfg: &cfg, budget: 100, verbose: false, files: Vec::new() };
+        let err = put_module_in_string(
+            &mut Mapped::default(),
+            &dir.path().join("lib.rs"),
+            0,
+            100,
+            &mut inliner,
+        )
+        .unwrap_err();
+        let message = err.to_string();
+        assert!(message.starts_with("file for module `missing` declared in"), "{}", message);
+        assert!(message.contains(&Path::new("missing").join("mod.rs").display().to_string()));
+    }
+}
@@ -0,0 +1,283 @@
+//! Dropping the code a target doesn't build.
+//!
+//! Items, fields, variants and statements with a `#[cfg]` that is false for
+//! the target are removed, `#[cfg_attr]` is replaced by its attributes or
+//! removed, and `cfg_if!` and `cfg_select!` calls by their enabled branch.
+//! Predicates that can't be evaluated are kept as they are. Of the inner
+//! `#![cfg]`s only the one of the file is looked at, so a disabled module file
+//! ends up empty.
+use cfg::{CfgExpr, CfgOptions};
+use syntax::{
+    ast, AstNode, NodeOrToken, SourceFile, SyntaxElement, SyntaxKind, SyntaxNode, TextRange, T,
+};
+
+use crate::source_map::Mapped;
+
+/// The cfg of a crate, from target atoms like `unix` or `target_os="linux"`
+/// and its enabled features.
+pub fn cfg_options(atoms: &[String], features: &[String]) -> CfgOptions {
+    let mut cfg = CfgOptions::default();
+    for atom in atoms {
+        match atom.split_once('=') {
+            Some((key, value)) => cfg.insert_key_value(key.into(), value.trim_matches('"').into()),
+            None => cfg.insert_atom(atom.as_str().into()),
+        }
+    }
+    for feature in features {
+        cfg.insert_key_value("feature".into(), feature.as_str().into());
+    }
+    cfg
+}
+
+pub fn prune(mut text: Mapped, cfg: &CfgOptions) -> Mapped {
+    // The branches of `cfg_if!` and the attributes of `#[cfg_attr]` are only
+    // parsed once they are spliced in, so go again until nothing changes.
+    loop {
+        let edits = prune_edits(&text.text, cfg);
+        if edits.is_empty() {
+            return text;
+        }
+        text.edit(edits.into_iter().map(|(range, it)| (range, Mapped::plain(it))).collect());
+    }
+}
+
+/// Non overlapping edits, in order.
+fn prune_edits(text: &str, cfg: &CfgOptions) -> Vec<(TextRange, String)> {
+    let file = SourceFile::parse(text).tree();
+    let mut edits: Vec<(TextRange, String)> = Vec::new();
+    for node in file.syntax().descendants() {
+        let range = node.text_range();
+        if edits.last().map_or(false, |(last, _)| last.contains_range(range)) {
+            continue;
+        }
+        if let Some(attr) = ast::Attr::cast(node.clone()) {
+            if let Some(replacement) = expand_cfg_attr(&attr, cfg) {
+                edits.push((range, replacement));
+            }
+        } else if is_disabled(&node, cfg) {
+            edits.push((removal_range(&node), String::new()));
+        } else if let Some(branch) =
+            ast::MacroCall::cast(node).and_then(|it| enabled_branch(&it, cfg))
+        {
+            edits.push((range, branch));
+        }
+    }
+    edits
+}
+
+fn is_disabled(node: &SyntaxNode, cfg: &CfgOptions) -> bool {
+    let is_file = node.kind() == SyntaxKind::SOURCE_FILE;
+    node.children()
+        .filter_map(ast::Attr::cast)
+        .filter(|attr| attr.excl_token().is_some() == is_file)
+        .filter_map(|attr| attr.as_simple_call())
+        .any(|(name, tt)| {
+            name == "cfg" && eval(&delimited(&tt.syntax().to_string()), cfg) == Some(false)
+        })
+}
+
+/// The range of `node` with the comma separating it from the next element of
+/// a list.
+fn removal_range(node: &SyntaxNode) -> TextRange {
+    let mut end = node.text_range().end();
+    let mut next = node.next_sibling_or_token();
+    while let Some(element) = next {
+        if element.kind().is_trivia() {
+            next = element.next_sibling_or_token();
+            continue;
+        }
+        if element.kind() == T![,] {
+            end = element.text_range().end();
+        }
+        break;
+    }
+    TextRange::new(node.text_range().start(), end)
+}
+
+fn expand_cfg_attr(attr: &ast::Attr, cfg: &CfgOptions) -> Option<String> {
+    let (name, tt) = attr.as_simple_call()?;
+    if name != "cfg_attr" {
+        return None;
+    }
+    let parts = split_commas(&tt);
+    let (predicate, attrs) = parts.split_first()?;
+    if !eval(predicate, cfg)? {
+        return Some(String::new());
+    }
+    let bang = if attr.excl_token().is_some() { "!" } else { "" };
+    let attrs: Vec<_> =
+        attrs.iter().filter(|it| !it.is_empty()).map(|it| format!("#{}[{}]", bang, it)).collect();
+    Some(attrs.join(" "))
+}
+
+/// The contents of the enabled branch of a `cfg_if!` or `cfg_select!` call.
+fn enabled_branch(call: &ast::MacroCall, cfg: &CfgOptions) -> Option<String> {
+    let name = call.path()?.segment()?.name_ref()?;
+    let elements: Vec<_> = call
+        .token_tree()?
+        .syntax()
+        .children_with_tokens()
+        .filter(|it| !it.kind().is_trivia())
+        .collect();
+    if elements.len() < 2 {
+        return None;
+    }
+    let rest = &elements[1..elements.len() - 1];
+    match name.text().as_str() {
+        "cfg_if" => cfg_if(rest, cfg),
+        "cfg_select" => cfg_select(rest, cfg),
+        _ => None,
+    }
+}
+
+/// `if #[cfg(a)] { .. } else if #[cfg(b)] { .. } else { .. }`
+fn cfg_if(mut rest: &[SyntaxElement], cfg: &CfgOptions) -> Option<String> {
+    while !rest.is_empty() {
+        if rest[0].kind() == T![else] {
+            rest = &rest[1..];
+        }
+        let mut enabled = true;
+        if rest.first()?.kind() == T![if] {
+            let attr = delimited(&rest.get(2)?.to_string());
+            let predicate = attr.trim().strip_prefix("cfg")?.trim_start();
+            enabled = eval(&delimited(predicate), cfg)?;
+            rest = &rest[3..];
+        }
+        let block = match rest.first()? {
+            NodeOrToken::Node(it) => it,
+            NodeOrToken::Token(_) => return None,
+        };
+        if enabled {
+            return Some(delimited(&block.to_string()));
+        }
+        rest = &rest[1..];
+    }
+    Some(String::new())
+}
+
+/// `a => { .. } b => { .. } _ => { .. }`
+fn cfg_select(mut rest: &[SyntaxElement], cfg: &CfgOptions) -> Option<String> {
+    while !rest.is_empty() {
+        // Token trees have `=>` as two tokens.
+        let arrow =
+            rest.windows(2).position(|it| it[0].kind() == T![=] && it[1].kind() == T![>])?;
+        let predicate: String = rest[..arrow].iter().map(|it| it.to_string()).collect();
+        let block = match rest.get(arrow + 2)? {
+            NodeOrToken::Node(it) => it,
+            NodeOrToken::Token(_) => return None,
+        };
+        let enabled = predicate == "_" || eval(&predicate, cfg)?;
+        if enabled {
+            return Some(delimited(&block.to_string()));
+        }
+        rest = &rest[arrow + 3..];
+        if rest.first().map(|it| it.kind()) == Some(T![,]) {
+            rest = &rest[1..];
+        }
+    }
+    Some(String::new())
+}
+
+/// Splits the contents of a token tree at its top level commas.
+fn split_commas(tt: &ast::TokenTree) -> Vec<String> {
+    let elements: Vec<_> = tt.syntax().children_with_tokens().collect();
+    if elements.len() < 2 {
+        return Vec::new();
+    }
+    let mut parts = vec![String::new()];
+    for element in &elements[1..elements.len() - 1] {
+        match element {
+            NodeOrToken::Token(token) if token.kind() == T![,] => parts.push(String::new()),
+            _ => parts.last_mut().unwrap().push_str(&element.to_string()),
+        }
+    }
+    parts.iter().map(|it| it.trim().to_string()).collect()
+}
+
+/// `text` without its delimiters.
+fn delimited(text: &str) -> String {
+    let mut chars = text.chars();
+    chars.next();
+    chars.next_back();
+    chars.as_str().to_string()
+}
+
+fn eval(predicate: &str, cfg: &CfgOptions) -> Option<bool> {
+    let (tt, _) = mbe::parse_to_token_tree(predicate)?;
+    cfg.check(&CfgExpr::parse(&tt))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cfg_options, prune};
+    use crate::source_map::Mapped;
+
+    /// The non-empty lines of `text` pruned for a Linux target with the `std`
+    /// feature, trimmed.
+    fn prune_lines(text: &str) -> Vec<String> {
+        let atoms = ["unix".to_string(), r#"target_os="linux""#.to_string()];
+        let cfg = cfg_options(&atoms, &["std".to_string()]);
+        let res = prune(Mapped::file(text.to_string(), 0), &cfg).text;
+        res.lines().map(str::trim).filter(|it| !it.is_empty()).map(String::from).collect()
+    }
+
+    #[test]
+    fn cfg() {
+        let text = r#"
+#[cfg(windows)]
+fn windows() {}
+#[cfg(all(unix, feature = "std"))]
+fn unix() {}
+struct S {
+    #[cfg(target_os = "macos")]
+    mac: u32,
+    linux: u32,
+}
+"#;
+        assert_eq!(
+            prune_lines(text),
+            [
+                r#"#[cfg(all(unix, feature = "std"))]"#,
+                "fn unix() {}",
+                "struct S {",
+                "linux: u32,",
+                "}"
+            ]
+        );
+        assert!(prune_lines("#![cfg(windows)]\nfn windows() {}\n").is_empty());
+    }
+
+    #[test]
+    fn cfg_attr() {
+        let text = r#"
+#[cfg_attr(unix, derive(Debug), repr(C))]
+struct A;
+#[cfg_attr(windows, derive(Clone))]
+struct B;
+"#;
+        assert_eq!(prune_lines(text), ["#[derive(Debug)] #[repr(C)]", "struct A;", "struct B;"]);
+    }
+
+    #[test]
+    fn cfg_if() {
+        let text = r#"
+cfg_if::cfg_if! {
+    if #[cfg(windows)] {
+        fn imp() -> u32 { 0 }
+    } else if #[cfg(unix)] {
+        #[cfg_attr(feature = "std", inline)]
+        fn imp() -> u32 { 1 }
+    } else {
+        fn imp() -> u32 { 2 }
+    }
+}
+"#;
+        assert_eq!(prune_lines(text), ["#[inline]", "fn imp() -> u32 { 1 }"]);
+    }
+
+    #[test]
+    fn cfg_select() {
+        let text = "cfg_select! { windows => { fn a() {} } _ => { fn b() {} } }";
+        assert_eq!(prune_lines(text), ["fn b() {}"]);
+    }
+}
@@ -0,0 +1,150 @@
+//! Mapping offsets in packed sources back to the files they come from.
+//!
+//! Every pass editing a crate's source keeps the map up to date: the text
+//! after an edit is mapped to where the replaced range ended, and text that
+//! replaced something is mapped to where it started, unless it comes with its
+//! own segments like an inlined module.
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use syntax::TextRange;
+
+#[derive(Serialize)]
+pub struct SourceMap {
+    /// Paths of the original files, relative to the directory the crate is in.
+    pub files: Vec<String>,
+    pub segments: Vec<Segment>,
+}
+
+/// From `offset` until the next segment, the packed text is the one of
+/// `files[file]` starting at `line`, counted from 0.
+#[derive(Serialize, Clone, Copy)]
+pub struct Segment {
+    pub offset: u32,
+    pub file: u32,
+    pub line: u32,
+}
+
+impl SourceMap {
+    pub fn new(files: &[PathBuf], base: &Path, segments: Vec<Segment>) -> SourceMap {
+        let files = files
+            .iter()
+            .map(|it| it.strip_prefix(base).unwrap_or(it).to_string_lossy().replace('\\', "/"))
+            .collect();
+        SourceMap { files, segments }
+    }
+}
+
+/// Text along with the segments mapping it to its original files.
+#[derive(Default)]
+pub struct Mapped {
+    pub text: String,
+    pub segments: Vec<Segment>,
+}
+
+impl Mapped {
+    /// The text of the file with index `file` in the source map.
+    pub fn file(text: String, file: u32) -> Mapped {
+        Mapped { text, segments: vec![Segment { offset: 0, file, line: 0 }] }
+    }
+
+    /// Generated text, mapped to wherever it is inserted.
+    pub fn plain(text: String) -> Mapped {
+        Mapped { text, segments: Vec::new() }
+    }
+
+    pub fn push(&mut self, other: Mapped) {
+        let shift = self.text.len() as u32;
+        for segment in other.segments {
+            let segment = Segment { offset: segment.offset + shift, ..segment };
+            // A later segment at the same offset overrides the previous one.
+            match self.segments.last_mut() {
+                Some(last) if last.offset == segment.offset => *last = segment,
+                _ => self.segments.push(segment),
+            }
+        }
+        self.text.push_str(&other.text);
+    }
+
+    /// Applies non overlapping edits.
+    pub fn edit(&mut self, mut edits: Vec<(TextRange, Mapped)>) {
+        edits.sort_by_key(|(range, _)| range.start());
+        let mut res = Mapped { text: String::with_capacity(self.text.len()), segments: Vec::new() };
+        let mut last = 0;
+        for (range, replacement) in edits {
+            let range = Range::<usize>::from(range);
+            res.push(self.slice(last..range.start));
+            res.push(replacement);
+            last = range.end;
+        }
+        res.push(self.slice(last..self.text.len()));
+        *self = res;
+    }
+
+    /// The text in `range` with the segments mapping it.
+    fn slice(&self, range: Range<usize>) -> Mapped {
+        let mut segments: Vec<_> = self.locate(range.start).into_iter().collect();
+        let first = self.segments.partition_point(|it| it.offset as usize <= range.start);
+        let end = self.segments.partition_point(|it| (it.offset as usize) < range.end);
+        if first < end {
+            segments.extend(
+                self.segments[first..end]
+                    .iter()
+                    .map(|it| Segment { offset: it.offset - range.start as u32, ..*it }),
+            );
+        }
+        Mapped { text: self.text[range].to_string(), segments }
+    }
+
+    /// The first segment of a slice starting at `offset`.
+    fn locate(&self, offset: usize) -> Option<Segment> {
+        let idx = self.segments.partition_point(|it| it.offset as usize <= offset);
+        let segment = self.segments.get(idx.checked_sub(1)?)?;
+        let lines = self.text[segment.offset as usize..offset].matches('\n').count() as u32;
+        Some(Segment { offset: 0, file: segment.file, line: segment.line + lines })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use syntax::TextRange;
+
+    use super::{Mapped, SourceMap};
+
+    fn segments(text: &Mapped) -> Vec<(u32, u32, u32)> {
+        text.segments.iter().map(|it| (it.offset, it.file, it.line)).collect()
+    }
+
+    #[test]
+    fn edit() {
+        let mut text = Mapped::file("a\nb\nc\nd\n".to_string(), 0);
+        // Like an inlined module replacing `b\nc`.
+        let mut module = Mapped::plain("{\n".to_string());
+        module.push(Mapped::file("x\ny\n".to_string(), 1));
+        module.push(Mapped::plain("}".to_string()));
+        text.edit(vec![(TextRange::new(2.into(), 5.into()), module)]);
+        assert_eq!(text.text, "a\n{\nx\ny\n}\nd\n");
+        // The braces stay on the line of the replaced text, the rest of the
+        // file continues at the line the replaced text ended on.
+        assert_eq!(segments(&text), [(0, 0, 0), (4, 1, 0), (9, 0, 2)]);
+    }
+
+    #[test]
+    fn push_at_the_same_offset() {
+        let mut text = Mapped::default();
+        text.push(Mapped::file(String::new(), 0));
+        text.push(Mapped::file("fn f() {}\n".to_string(), 1));
+        assert_eq!(segments(&text), [(0, 1, 0)]);
+    }
+
+    #[test]
+    fn relative_paths() {
+        let files =
+            [PathBuf::from("/src/foo-1.0.0/src/lib.rs"), PathBuf::from("/elsewhere/gen.rs")];
+        let map = SourceMap::new(&files, Path::new("/src/foo-1.0.0"), Vec::new());
+        assert_eq!(map.files, ["src/lib.rs", "/elsewhere/gen.rs"]);
+    }
+}
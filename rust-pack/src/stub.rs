@@ -0,0 +1,153 @@
+//! Replacing function bodies with `loop {}`.
+//!
+//! Analysis of code using a library only needs its signatures, so the bodies
+//! are most of the size of a packed crate for nothing. `const fn` bodies are
+//! kept since they can be evaluated in types, like array lengths, and so are
+//! functions inside macro calls, which aren't parsed. The items declared in a
+//! body are kept too, since their impls apply to the whole crate, with their
+//! own functions stubbed.
+use std::collections::BTreeMap;
+use std::fmt;
+
+use syntax::{ast, AstNode, SourceFile, SyntaxNode, TextRange, TextSize, WalkEvent};
+
+use crate::source_map::Mapped;
+
+const STUB: &str = "{ loop {} }";
+
+pub struct StubReport {
+    pub functions: usize,
+    pub size_before: usize,
+    pub size_after: usize,
+}
+
+impl fmt::Display for StubReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let saved = self.size_before - self.size_after;
+        write!(
+            f,
+            "stubbed {} function bodies, {} -> {} bytes ({:.1}% saved)",
+            self.functions,
+            self.size_before,
+            self.size_after,
+            saved as f64 * 100.0 / self.size_before.max(1) as f64
+        )
+    }
+}
+
+pub fn stub_function_bodies(mut text: Mapped) -> (Mapped, StubReport) {
+    let size_before = text.text.len();
+    let file = SourceFile::parse(&text.text).tree();
+    let mut functions = 0;
+    // The removed parts of the bodies, by start, with their replacement.
+    let mut removed: BTreeMap<TextSize, (TextRange, &str)> = BTreeMap::new();
+    for func in file.syntax().descendants().filter_map(ast::Fn::cast) {
+        if func.const_token().is_some() {
+            continue;
+        }
+        let body = match func.body() {
+            Some(it) => it,
+            None => continue,
+        };
+        let range = body.syntax().text_range();
+        // Nested functions are already gone with the outer body, unless they
+        // are in one of the items it keeps.
+        let is_removed = removed
+            .range(..=range.start())
+            .next_back()
+            .map_or(false, |(_, (it, _))| it.contains_range(range));
+        if is_removed {
+            continue;
+        }
+        let items = body_items(&body);
+        if items.is_empty() && range.len() <= (STUB.len() as u32).into() {
+            continue;
+        }
+        functions += 1;
+        // Everything around the items goes.
+        let mut start = range.start();
+        for item in &items {
+            let item = item.text_range();
+            let replacement = if start == range.start() { "{ " } else { "\n" };
+            removed.insert(start, (TextRange::new(start, item.start()), replacement));
+            start = item.end();
+        }
+        let replacement = if items.is_empty() { STUB } else { "\nloop {} }" };
+        removed.insert(start, (TextRange::new(start, range.end()), replacement));
+    }
+
+    text.edit(
+        removed
+            .into_values()
+            .map(|(range, replacement)| (range, Mapped::plain(replacement.to_string())))
+            .collect(),
+    );
+
+    let report = StubReport { functions, size_before, size_after: text.text.len() };
+    (text, report)
+}
+
+/// The items declared in `body`, at any depth but outside other items. Macro
+/// calls are left out, they are mostly statements.
+fn body_items(body: &ast::BlockExpr) -> Vec<SyntaxNode> {
+    let mut res = Vec::new();
+    let mut preorder = body.syntax().preorder();
+    preorder.next();
+    while let Some(event) = preorder.next() {
+        let node = match event {
+            WalkEvent::Enter(node) => node,
+            WalkEvent::Leave(_) => continue,
+        };
+        if ast::Item::can_cast(node.kind()) {
+            if !ast::MacroCall::can_cast(node.kind()) {
+                res.push(node);
+            }
+            preorder.skip_subtree();
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::stub_function_bodies;
+    use crate::source_map::Mapped;
+
+    #[test]
+    fn stubs() {
+        let text = r#"
+fn plain<'a>(x: &'a str) -> &'a str {
+    let s = "} fn not_a_fn() {";
+    x
+}
+const fn kept() -> u32 { 1 }
+fn with_items() {
+    struct Local;
+    impl Local {
+        fn method(&self) -> u32 { 42 + 1 + 1 }
+    }
+    Local.method();
+}
+fn short() {}
+macro_rules! m { () => { fn in_macro() { 42 + 1 + 1 } } }
+"#;
+        let (res, report) = stub_function_bodies(Mapped::file(text.to_string(), 0));
+        assert_eq!(
+            res.text,
+            r#"
+fn plain<'a>(x: &'a str) -> &'a str { loop {} }
+const fn kept() -> u32 { 1 }
+fn with_items() { struct Local;
+    impl Local {
+        fn method(&self) -> u32 { loop {} }
+    }
+loop {} }
+fn short() {}
+macro_rules! m { () => { fn in_macro() { 42 + 1 + 1 } } }
+"#
+        );
+        assert_eq!(report.functions, 3);
+        assert_eq!(report.size_before, text.len());
+        assert_eq!(report.size_after, res.text.len());
+    }
+}
@@ -0,0 +1,153 @@
+//! The subset of rustfmt's options we support, read from a `rustfmt.toml`.
+//!
+//! The names and values are rustfmt's, so a project's `rustfmt.toml` can be
+//! used as is as long as it only sets these options.
+use serde::Deserialize;
+
+use crate::error::Error;
+
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Maximum width of a line, the width prettyplease fills lines to.
+    pub max_width: usize,
+    /// Number of spaces per indentation level.
+    pub tab_spaces: usize,
+    /// Indent with tabs rather than spaces.
+    pub hard_tabs: bool,
+    pub newline_style: NewlineStyle,
+    /// How `use` trees are merged or split.
+    pub imports_granularity: ImportsGranularity,
+    /// How consecutive `use` items are grouped.
+    pub group_imports: GroupImports,
+    /// Sort `use` items.
+    pub reorder_imports: bool,
+    /// Sort `mod` declarations.
+    pub reorder_modules: bool,
+    /// How wide the short constructs, calls, arrays and the like, can be
+    /// before they are broken into lines.
+    pub use_small_heuristics: Heuristics,
+    /// Format the `rust` code blocks of doc comments.
+    pub format_code_in_doc_comments: bool,
+    /// Maximum width of the code in doc comments.
+    pub doc_comment_code_block_width: usize,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            max_width: 100,
+            tab_spaces: 4,
+            hard_tabs: false,
+            newline_style: NewlineStyle::Auto,
+            imports_granularity: ImportsGranularity::Preserve,
+            group_imports: GroupImports::Preserve,
+            reorder_imports: true,
+            reorder_modules: true,
+            use_small_heuristics: Heuristics::Default,
+            format_code_in_doc_comments: false,
+            doc_comment_code_block_width: 100,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NewlineStyle {
+    /// The line ending of the first line of the input.
+    Auto,
+    /// `\r\n` on Windows, `\n` elsewhere.
+    Native,
+    Unix,
+    Windows,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportsGranularity {
+    /// Keep the `use` trees as written.
+    Preserve,
+    /// One `use` per crate.
+    Crate,
+    /// One `use` per module.
+    Module,
+    /// One `use` per imported item.
+    Item,
+    /// A single `use` for everything.
+    One,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GroupImports {
+    /// Keep the groups separated by blank lines as written.
+    Preserve,
+    /// `std`, `core` and `alloc`, then other crates, then `crate`, `self` and
+    /// `super`.
+    StdExternalCrate,
+    /// A single group.
+    One,
+}
+
+/// prettyplease only breaks what doesn't fit in the line, so rustfmt's
+/// `Default` and `Off`, which break short constructs sooner, are approximated
+/// by laying everything out like `Max`. A call that fits in `max_width` stays
+/// on one line where rustfmt may break it.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Heuristics {
+    /// Short constructs can use a share of `max_width`, laid out like `Max`.
+    Default,
+    /// Short constructs are always broken, laid out like `Max`.
+    Off,
+    /// Everything can use up to `max_width`.
+    Max,
+}
+
+impl Config {
+    /// Reads the options of a `rustfmt.toml`, those it doesn't set keep their
+    /// default value.
+    pub fn from_toml(text: &str) -> Result<Config, Error> {
+        let config: Config = toml::from_str(text)
+            .map_err(|e| Error::Config { message: format!("Invalid rustfmt.toml: {}", e) })?;
+        if config.tab_spaces == 0 {
+            let message = "Invalid rustfmt.toml: tab_spaces must be at least 1".to_string();
+            return Err(Error::Config { message });
+        }
+        if config.max_width == 0 {
+            let message = "Invalid rustfmt.toml: max_width must be at least 1".to_string();
+            return Err(Error::Config { message });
+        }
+        Ok(config)
+    }
+
+    /// The line ending to use for `code`.
+    pub(crate) fn newline(&self, code: &str) -> &'static str {
+        let native = if cfg!(windows) { "\r\n" } else { "\n" };
+        match self.newline_style {
+            NewlineStyle::Unix => "\n",
+            NewlineStyle::Windows => "\r\n",
+            NewlineStyle::Native => native,
+            NewlineStyle::Auto => match code.find('\n') {
+                Some(idx) if code[..idx].ends_with('\r') => "\r\n",
+                Some(_) => "\n",
+                None => native,
+            },
+        }
+    }
+
+    /// The configuration for formatting a part of `code`, with the line
+    /// endings of `code`.
+    pub(crate) fn for_part_of(&self, code: &str) -> Config {
+        let newline_style = match self.newline(code) {
+            "\n" => NewlineStyle::Unix,
+            _ => NewlineStyle::Windows,
+        };
+        Config { newline_style, ..self.clone() }
+    }
+
+    /// The indentation of `level` levels.
+    pub(crate) fn indent(&self, level: usize) -> String {
+        if self.hard_tabs {
+            "\t".repeat(level)
+        } else {
+            " ".repeat(level * self.tab_spaces)
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//! Shortest edit scripts between two sequences, with Myers' algorithm, and
+//! the text edits between two texts.
+//!
+//! [`matches`] keeps the furthest points of every step to backtrack from, so
+//! its memory grows with the edits and it gives up after a limit. [`align`]
+//! finds the middle of the script instead and recurses on both halves, in
+//! linear space and without limit.
+use std::ops::Range;
+
+/// Texts further apart than that many lines are replaced as a whole.
+const MAX_LINE_EDITS: usize = 2000;
+
+/// The edits turning `original` into `formatted`, the byte ranges of
+/// `original` to replace, in order. Lines are diffed, then the common start
+/// and end of the changed lines are left out.
+pub(crate) fn edits(original: &str, formatted: &str) -> Vec<(Range<usize>, String)> {
+    let a: Vec<&str> = original.split_inclusive('\n').collect();
+    let b: Vec<&str> = formatted.split_inclusive('\n').collect();
+    let pairs = matches(&a, &b, MAX_LINE_EDITS).unwrap_or_default();
+
+    let line_offsets = |lines: &[&str]| -> Vec<usize> {
+        let mut res = vec![0];
+        for line in lines {
+            res.push(res.last().unwrap() + line.len());
+        }
+        res
+    };
+    let (a_offsets, b_offsets) = (line_offsets(&a), line_offsets(&b));
+    let mut res = Vec::new();
+    let (mut x, mut y) = (0, 0);
+    // The pairs, then the end of both texts.
+    for (next_x, next_y) in pairs.into_iter().chain([(a.len(), b.len())]) {
+        if next_x > x || next_y > y {
+            let old = a_offsets[x]..a_offsets[next_x];
+            let new = &formatted[b_offsets[y]..b_offsets[next_y]];
+            res.push(trim_common(original, old, new));
+        }
+        x = next_x + 1;
+        y = next_y + 1;
+    }
+    res
+}
+
+/// The edit replacing `range` of `text` by `new`, without the start and end
+/// they have in common.
+fn trim_common(text: &str, range: Range<usize>, new: &str) -> (Range<usize>, String) {
+    let old = &text[range.clone()];
+    let prefix: usize =
+        old.chars().zip(new.chars()).take_while(|(a, b)| a == b).map(|(c, _)| c.len_utf8()).sum();
+    let (old_rest, new_rest) = (&old[prefix..], &new[prefix..]);
+    let suffix: usize = old_rest
+        .chars()
+        .rev()
+        .zip(new_rest.chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(c, _)| c.len_utf8())
+        .sum();
+    let start = range.start + prefix;
+    let end = range.end - suffix;
+    (start..end, new_rest[..new_rest.len() - suffix].to_string())
+}
+
+/// The indices of the elements of `a` and `b` kept by a shortest edit script
+/// between them, `None` when it takes more than `max` edits.
+pub(crate) fn matches(a: &[&str], b: &[&str], max: usize) -> Option<Vec<(usize, usize)>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m).min(max as isize);
+    let offset = max + 1;
+    let mut v = vec![0isize; 2 * max as usize + 3];
+    // The furthest points of each diagonal before each step, between `-d`
+    // and `d`.
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    for d in 0..=max {
+        trace.push(v[(offset - d) as usize..=(offset + d) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let idx = (offset + k) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                return Some(backtrack(&trace, n, m));
+            }
+        }
+    }
+    None
+}
+
+/// The indices of the elements of `a` and `b` kept by a shortest edit script
+/// between them, however long.
+pub(crate) fn align(a: &[&str], b: &[&str]) -> Vec<(usize, usize)> {
+    let mut res = Vec::new();
+    align_from(a, b, (0, 0), &mut res);
+    res
+}
+
+/// [`align`] of slices starting at `start` in the whole sequences.
+fn align_from(a: &[&str], b: &[&str], start: (usize, usize), res: &mut Vec<(usize, usize)>) {
+    let (x0, y0) = start;
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    res.extend((0..prefix).map(|it| (x0 + it, y0 + it)));
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    let (x0, y0) = (x0 + prefix, y0 + prefix);
+    let suffix = a.iter().rev().zip(b.iter().rev()).take_while(|(x, y)| x == y).count();
+    let (n, m) = (a.len() - suffix, b.len() - suffix);
+    // Otherwise only insertions or deletions are left.
+    if n > 0 && m > 0 {
+        let ((x, y), (u, v)) = middle_snake(&a[..n], &b[..m]);
+        align_from(&a[..x], &b[..y], (x0, y0), res);
+        res.extend((x..u).map(|it| (x0 + it, y0 + y + it - x)));
+        align_from(&a[u..n], &b[v..m], (x0 + u, y0 + v), res);
+    }
+    res.extend((0..suffix).map(|it| (x0 + n + it, y0 + m + it)));
+}
+
+/// The start and end of the diagonal in the middle of a shortest edit script,
+/// where the paths from both ends meet. The halves it splits the script into
+/// each take fewer edits than the whole, which takes at least two since `a`
+/// and `b` have different first elements.
+fn middle_snake(a: &[&str], b: &[&str]) -> ((usize, usize), (usize, usize)) {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let delta = n - m;
+    let max = (n + m + 1) / 2;
+    let offset = max + 1;
+    // The furthest `x` on each diagonal, from the start and from the end.
+    let mut forward = vec![0isize; 2 * max as usize + 3];
+    let mut backward = vec![0isize; 2 * max as usize + 3];
+    // Backward, `x` and `y` count from the ends, and the diagonal `k` is
+    // `delta - k` forward.
+    let furthest = |v: &[isize], k: isize, d: isize| {
+        let idx = (offset + k) as usize;
+        if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+            v[idx + 1]
+        } else {
+            v[idx - 1] + 1
+        }
+    };
+    for d in 0..=max {
+        for k in (-d..=d).step_by(2) {
+            let start = furthest(&forward, k, d);
+            let mut x = start;
+            while x < n && x - k < m && a[x as usize] == b[(x - k) as usize] {
+                x += 1;
+            }
+            forward[(offset + k) as usize] = x;
+            let back_k = delta - k;
+            if delta % 2 != 0
+                && (-(d - 1)..=d - 1).contains(&back_k)
+                && x + backward[(offset + back_k) as usize] >= n
+            {
+                let at = |x: isize| (x as usize, (x - k) as usize);
+                return (at(start), at(x));
+            }
+        }
+        for k in (-d..=d).step_by(2) {
+            let start = furthest(&backward, k, d);
+            let mut x = start;
+            while x < n && x - k < m && a[(n - x - 1) as usize] == b[(m - (x - k) - 1) as usize] {
+                x += 1;
+            }
+            backward[(offset + k) as usize] = x;
+            let forward_k = delta - k;
+            if delta % 2 == 0
+                && (-d..=d).contains(&forward_k)
+                && forward[(offset + forward_k) as usize] + x >= n
+            {
+                let at = |x: isize| ((n - x) as usize, (m - (x - k)) as usize);
+                return (at(x), at(start));
+            }
+        }
+    }
+    unreachable!("the paths meet after at most (n + m) / 2 steps each")
+}
+
+fn backtrack(trace: &[Vec<isize>], n: isize, m: isize) -> Vec<(usize, usize)> {
+    let mut res = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let at = |k: isize| v[(k + d) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) { k + 1 } else { k - 1 };
+        let (prev_x, prev_y) = if d == 0 { (0, 0) } else { (at(prev_k), at(prev_k) - prev_k) };
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            res.push((x as usize, y as usize));
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    res.reverse();
+    res
+}
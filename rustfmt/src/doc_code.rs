@@ -0,0 +1,251 @@
+//! Formats the `rust` code blocks of `///` and `//!` doc comments, for the
+//! `format_code_in_doc_comments` option.
+//!
+//! The lines rustdoc hides, starting with `# `, are formatted with the others
+//! and hidden again, the lines they end up on being found by aligning the
+//! tokens. Code that doesn't parse as items is formatted in a `fn main`, the
+//! way rustdoc runs it. Blocks that can't be formatted are left as they are.
+use crate::config::{Config, NewlineStyle};
+use crate::diff::matches;
+use crate::layout;
+use crate::lexer::{lex, Kind};
+
+/// Code blocks further apart than that after formatting are left as they are.
+const MAX_EDITS: usize = 1000;
+
+const WRAPPER: &str = "fn main() {";
+
+/// `text`, formatted, with the code blocks of its doc comments formatted.
+pub(crate) fn format_code_blocks(text: &str, config: &Config) -> String {
+    let lexemes = lex(text);
+    let mut edits = Vec::new();
+    let mut idx = 0;
+    while idx < lexemes.len() {
+        let Some(prefix) = doc_prefix(&text[lexemes[idx].range.clone()]) else {
+            idx += 1;
+            continue;
+        };
+        // The doc comments of consecutive lines.
+        let mut end = idx + 1;
+        while end < lexemes.len()
+            && doc_prefix(&text[lexemes[end].range.clone()]) == Some(prefix)
+            && text[lexemes[end - 1].range.end..lexemes[end].range.start].matches('\n').count() == 1
+        {
+            end += 1;
+        }
+        let start = lexemes[idx].range.start;
+        let line_start = text[..start].rfind('\n').map_or(0, |it| it + 1);
+        let indent = &text[line_start..start];
+        let lines: Vec<&str> = lexemes[idx..end]
+            .iter()
+            .map(|it| &text[it.range.start + prefix.len()..it.range.end])
+            .collect();
+        let width: usize =
+            indent.chars().map(|c| if c == '\t' { config.tab_spaces } else { 1 }).sum();
+        if let Some(lines) = format_doc(&lines, width + prefix.len(), config) {
+            let mut res = String::new();
+            for (jdx, line) in lines.iter().enumerate() {
+                if jdx > 0 {
+                    res.push('\n');
+                    res.push_str(indent);
+                }
+                res.push_str(prefix);
+                res.push_str(line);
+            }
+            edits.push((start..lexemes[end - 1].range.end, res));
+        }
+        idx = end;
+    }
+
+    let mut res = String::with_capacity(text.len());
+    let mut cursor = 0;
+    for (range, replacement) in edits {
+        res.push_str(&text[cursor..range.start]);
+        res.push_str(&replacement);
+        cursor = range.end;
+    }
+    res.push_str(&text[cursor..]);
+    res
+}
+
+fn doc_prefix(token: &str) -> Option<&'static str> {
+    if token.starts_with("///") && !token.starts_with("////") {
+        Some("///")
+    } else if token.starts_with("//!") {
+        Some("//!")
+    } else {
+        None
+    }
+}
+
+/// The lines of a doc comment, without their `///`, with their code blocks
+/// formatted, `None` if none changed. `width` is that of what's before them.
+fn format_doc(lines: &[&str], width: usize, config: &Config) -> Option<Vec<String>> {
+    let mut res: Vec<String> = Vec::new();
+    let mut is_changed = false;
+    let mut idx = 0;
+    while idx < lines.len() {
+        let line = lines[idx];
+        res.push(line.to_string());
+        idx += 1;
+        let content = line.trim_start();
+        let fence = if content.starts_with("```") {
+            "```"
+        } else if content.starts_with("~~~") {
+            "~~~"
+        } else {
+            continue;
+        };
+        let Some(len) = lines[idx..].iter().position(|it| it.trim_start().starts_with(fence))
+        else {
+            continue;
+        };
+        let code = &lines[idx..idx + len];
+        let fence_indent = &line[..line.len() - content.len()];
+        let formatted = is_rust(&content[fence.len()..])
+            .then(|| format_block(code, fence_indent, width + fence_indent.len(), config))
+            .flatten();
+        match formatted {
+            Some(formatted) if formatted.iter().ne(code) => {
+                res.extend(formatted);
+                is_changed = true;
+            }
+            _ => res.extend(code.iter().map(|it| it.to_string())),
+        }
+        res.push(lines[idx + len].to_string());
+        idx += len + 1;
+    }
+    is_changed.then_some(res)
+}
+
+/// Whether the code block of a fence with `info` is Rust, as for rustdoc.
+fn is_rust(info: &str) -> bool {
+    info.split([',', ' ', '\t']).all(|it| {
+        matches!(
+            it.trim(),
+            "" | "rust"
+                | "should_panic"
+                | "no_run"
+                | "edition2015"
+                | "edition2018"
+                | "edition2021"
+                | "edition2024"
+        )
+    })
+}
+
+/// Whether rustdoc hides `line` of a code block.
+fn is_hidden(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed == "#" || trimmed.starts_with("# ") || trimmed.starts_with("#\t")
+}
+
+/// The lines of a code block, indented by `indent` after the `///`,
+/// formatted, `width` being that of what's before the code.
+fn format_block(
+    lines: &[&str],
+    indent: &str,
+    width: usize,
+    config: &Config,
+) -> Option<Vec<String>> {
+    let mut code = String::new();
+    // The hidden lines of `code`, by offset.
+    let mut hidden_lines = Vec::new();
+    for line in lines {
+        let line = line.strip_prefix(indent).or_else(|| line.trim().is_empty().then_some(""))?;
+        let is_hidden = is_hidden(line);
+        let line = if is_hidden {
+            let rest = &line.trim_start()[1..];
+            rest.strip_prefix([' ', '\t']).unwrap_or(rest)
+        } else if line.trim_start().starts_with("##") {
+            // rustdoc's escape for a line starting with `#`.
+            return None;
+        } else {
+            line
+        };
+        if is_hidden {
+            hidden_lines.push(code.len()..code.len() + line.len() + 1);
+        }
+        code.push_str(line);
+        code.push('\n');
+    }
+
+    // The code is shown with spaces whatever the file has.
+    let config = Config {
+        max_width: config.doc_comment_code_block_width.min(config.max_width.saturating_sub(width)),
+        hard_tabs: false,
+        newline_style: NewlineStyle::Unix,
+        format_code_in_doc_comments: false,
+        ..config.clone()
+    };
+    let formatted = match crate::format(&code, &config) {
+        Ok(formatted) => formatted,
+        Err(_) => {
+            let wrapped = format!("{}\n{}}}\n", WRAPPER, code);
+            let config =
+                Config { max_width: config.max_width + config.tab_spaces, ..config.clone() };
+            let formatted = crate::format(&wrapped, &config).ok()?;
+            let body = formatted.strip_prefix(WRAPPER)?.strip_prefix('\n')?.strip_suffix("}\n")?;
+            let level = config.indent(1);
+            layout::map_indents(body, |it| {
+                it.strip_prefix(level.as_str()).unwrap_or(it).to_string()
+            })
+        }
+    };
+
+    // A line is hidden if its tokens were, a line without tokens if the
+    // lines around it are.
+    let code_tokens = lex(&code).into_iter().filter(|it| it.kind == Kind::Token);
+    let (code_tokens, is_hidden): (Vec<&str>, Vec<bool>) = code_tokens
+        .map(|it| {
+            let is_hidden = hidden_lines.iter().any(|line| line.contains(&it.range.start));
+            (&code[it.range], is_hidden)
+        })
+        .unzip();
+    let fmt_lexemes: Vec<_> =
+        lex(&formatted).into_iter().filter(|it| it.kind == Kind::Token).collect();
+    let fmt_tokens: Vec<&str> = fmt_lexemes.iter().map(|it| &formatted[it.range.clone()]).collect();
+    let mut token_hidden = vec![None; fmt_tokens.len()];
+    for (a, b) in matches(&code_tokens, &fmt_tokens, MAX_EDITS)? {
+        token_hidden[b] = Some(is_hidden[a]);
+    }
+    let mut line_hidden: Vec<Option<bool>> = Vec::new();
+    let mut offset = 0;
+    for line in formatted.split_inclusive('\n') {
+        let range = offset..offset + line.len();
+        let flags: Vec<bool> = fmt_lexemes
+            .iter()
+            .zip(&token_hidden)
+            .filter(|(it, _)| range.contains(&it.range.start))
+            .filter_map(|(_, it)| *it)
+            .collect();
+        line_hidden.push(match flags.iter().filter(|it| **it).count() {
+            _ if flags.is_empty() => None,
+            0 => Some(false),
+            count if count == flags.len() => Some(true),
+            // Hidden and shown code on one line.
+            _ => return None,
+        });
+        offset += line.len();
+    }
+    let is_between_hidden = |idx: usize| {
+        let before = line_hidden[..idx].iter().rev().find_map(|it| *it);
+        let after = line_hidden[idx..].iter().find_map(|it| *it);
+        before == Some(true) && after == Some(true)
+    };
+
+    let res = formatted
+        .lines()
+        .enumerate()
+        .map(|(idx, line)| {
+            let is_hidden = line_hidden[idx].unwrap_or_else(|| is_between_hidden(idx));
+            match (is_hidden, line.is_empty()) {
+                (true, true) => format!("{}#", indent),
+                (true, false) => format!("{}# {}", indent, line),
+                (false, true) => String::new(),
+                (false, false) => format!("{}{}", indent, line),
+            }
+        })
+        .collect();
+    Some(res)
+}
@@ -0,0 +1,95 @@
+//! Formatting as a list of edits, so the editor keeps the cursor, the
+//! selection and the undo history, of the whole file or of the items in a
+//! range.
+use serde::Serialize;
+use syn::spanned::Spanned;
+use syn::File;
+
+use crate::config::Config;
+use crate::diff;
+use crate::error::{self, Error, ParseError};
+use crate::position::LineIndex;
+use crate::recover;
+
+/// A range of the editor, with 1-based lines and columns.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Range {
+    pub start_line_number: u32,
+    pub start_column: u32,
+    pub end_line_number: u32,
+    pub end_column: u32,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct TextEdit {
+    pub range: Range,
+    pub text: String,
+}
+
+/// The edits of a best-effort formatting, and the errors of the items left
+/// as they were, with their ranges after the edits.
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct RecoveredEdits {
+    pub edits: Vec<TextEdit>,
+    pub errors: Vec<ParseError>,
+}
+
+/// The edits formatting `code`.
+pub fn format_edits(code: &str, config: &Config) -> Result<Vec<TextEdit>, Error> {
+    let formatted = crate::format(code, config)?;
+    Ok(to_text_edits(code, 0, code, &formatted))
+}
+
+/// The edits formatting the top-level items of `code` that parse.
+pub fn format_edits_best_effort(code: &str, config: &Config) -> Result<RecoveredEdits, Error> {
+    let recovered = recover::format_best_effort(code, config)?;
+    let edits = to_text_edits(code, 0, code, &recovered.code);
+    Ok(RecoveredEdits { edits, errors: recovered.errors })
+}
+
+/// The edits formatting the top-level items of `code` intersecting `range`.
+pub fn format_range(code: &str, config: &Config, range: Range) -> Result<Vec<TextEdit>, Error> {
+    let file: File = error::parse_file(code)?;
+    let index = LineIndex::new(code);
+    let start = index.offset(range.start_line_number, range.start_column);
+    let end = index.offset(range.end_line_number, range.end_column);
+
+    let items: Vec<(usize, usize)> = file
+        .items
+        .iter()
+        .map(|item| {
+            let span = item.span();
+            (index.span_offset(span.start()), index.span_offset(span.end()))
+        })
+        .filter(|(item_start, item_end)| *item_start <= end && start <= *item_end)
+        .collect();
+    let (Some(first), Some(last)) = (items.first(), items.last()) else {
+        return Ok(Vec::new());
+    };
+    let snippet = &code[first.0..last.1];
+
+    let formatted = format_part(code, snippet, config)?;
+    Ok(to_text_edits(code, first.0, snippet, &formatted))
+}
+
+/// Formats `part`, items of `code`, with the line endings of `code` and
+/// without a line ending of its own.
+pub(crate) fn format_part(code: &str, part: &str, config: &Config) -> Result<String, Error> {
+    let config = config.for_part_of(code);
+    let formatted = crate::format(part, &config)?;
+    let newline = config.newline(code);
+    Ok(formatted.strip_suffix(newline).unwrap_or(&formatted).to_string())
+}
+
+/// The edits turning `original`, at `offset` in `code`, into `formatted`.
+fn to_text_edits(code: &str, offset: usize, original: &str, formatted: &str) -> Vec<TextEdit> {
+    let index = LineIndex::new(code);
+    diff::edits(original, formatted)
+        .into_iter()
+        .map(|(range, text)| {
+            let range = error::editor_range(&index, offset + range.start..offset + range.end);
+            TextEdit { range, text }
+        })
+        .collect()
+}
@@ -0,0 +1,94 @@
+//! Errors of the formatter, parse errors with the ranges they're about.
+use std::fmt;
+use std::ops;
+
+use proc_macro2::LineColumn;
+use serde::Serialize;
+use syn::File;
+use wasm_bindgen::JsValue;
+
+use crate::edits::Range;
+use crate::position::LineIndex;
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    pub range: Range,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Error {
+    /// The code doesn't parse, one error per place syn stopped at.
+    Parse { errors: Vec<ParseError> },
+    /// The `rustfmt.toml` is invalid.
+    Config { message: String },
+    /// The code parses but couldn't be formatted.
+    Format { message: String },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse { errors } => {
+                for (idx, error) in errors.iter().enumerate() {
+                    if idx > 0 {
+                        writeln!(f)?;
+                    }
+                    let range = error.range;
+                    write!(
+                        f,
+                        "Parsing error at {}:{}: {}",
+                        range.start_line_number, range.start_column, error.message
+                    )?;
+                }
+                Ok(())
+            }
+            Error::Config { message } | Error::Format { message } => f.write_str(message),
+        }
+    }
+}
+
+impl From<Error> for JsValue {
+    fn from(error: Error) -> JsValue {
+        serde_wasm_bindgen::to_value(&error)
+            .unwrap_or_else(|_| JsValue::from_str(&error.to_string()))
+    }
+}
+
+pub(crate) fn parse_file(code: &str) -> Result<File, Error> {
+    syn::parse_str(code).map_err(|error| {
+        let index = LineIndex::new(code);
+        let errors = error_ranges(code, &error)
+            .into_iter()
+            .map(|(range, message)| ParseError { message, range: editor_range(&index, range) })
+            .collect();
+        Error::Parse { errors }
+    })
+}
+
+/// The byte ranges of `text` of the errors combined in `error`, with their
+/// messages. The spans must come from parsing `text`.
+pub(crate) fn error_ranges(text: &str, error: &syn::Error) -> Vec<(ops::Range<usize>, String)> {
+    let index = LineIndex::new(text);
+    error
+        .into_iter()
+        .map(|error| {
+            let span = error.span();
+            // Past the last token, syn points at no token.
+            let start_of_file = LineColumn { line: 1, column: 0 };
+            let range = if span.start() == start_of_file && span.end() == start_of_file {
+                text.len()..text.len()
+            } else {
+                index.span_offset(span.start())..index.span_offset(span.end())
+            };
+            (range, error.to_string())
+        })
+        .collect()
+}
+
+pub(crate) fn editor_range(index: &LineIndex, range: ops::Range<usize>) -> Range {
+    let (start_line_number, start_column) = index.position(range.start);
+    let (end_line_number, end_column) = index.position(range.end);
+    Range { start_line_number, start_column, end_line_number, end_column }
+}
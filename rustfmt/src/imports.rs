@@ -0,0 +1,388 @@
+//! Sorting, merging and grouping of `use` items, following the
+//! `imports_granularity`, `group_imports` and `reorder_imports` options.
+//!
+//! The trees are flattened into the names they import, which are merged back
+//! into one tree per crate, module or name, or kept as written.
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+
+use quote::ToTokens;
+use syn::{File, Item, ItemUse, UseTree};
+
+use crate::config::{Config, GroupImports, ImportsGranularity};
+use crate::layout;
+
+/// A segment of a path, ordered like rustfmt does, `self`, `super` and
+/// `crate`, then `snake_case`, `CamelCase` and `UPPER_CASE` names.
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Segment(String);
+
+impl Segment {
+    fn key(&self) -> (u8, &str) {
+        let name = self.0.trim_start_matches("::");
+        let rank = match name {
+            "self" => 0,
+            "super" => 1,
+            "crate" => 2,
+            _ if !name.starts_with(char::is_uppercase) => 3,
+            _ if name.chars().any(char::is_lowercase) => 4,
+            _ => 5,
+        };
+        (rank, &self.0)
+    }
+}
+
+impl Ord for Segment {
+    fn cmp(&self, other: &Segment) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl PartialOrd for Segment {
+    fn partial_cmp(&self, other: &Segment) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// What a path ends with.
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Leaf {
+    Name(Segment),
+    Rename(Segment, String),
+    Glob,
+}
+
+impl Ord for Leaf {
+    fn cmp(&self, other: &Leaf) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl PartialOrd for Leaf {
+    fn partial_cmp(&self, other: &Leaf) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Leaf {
+    /// Like the name, globs last.
+    fn key(&self) -> ((u8, &str), &str) {
+        match self {
+            Leaf::Name(name) => (name.key(), ""),
+            Leaf::Rename(name, rename) => (name.key(), rename),
+            Leaf::Glob => ((6, "*"), ""),
+        }
+    }
+
+    fn to_text(&self) -> String {
+        match self {
+            Leaf::Name(name) => name.0.clone(),
+            Leaf::Rename(name, rename) => format!("{} as {}", name.0, rename),
+            Leaf::Glob => "*".to_string(),
+        }
+    }
+}
+
+/// One imported name, `a::b::c`, `a::b::c as d` or `a::b::*`. A leading `::`
+/// is part of the first segment.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+struct Import {
+    path: Vec<Segment>,
+    leaf: Leaf,
+}
+
+impl Import {
+    /// Orders `a::b` before `a::c::d`, unlike the order of the paths.
+    fn key(&self) -> Vec<(u8, &str)> {
+        let mut key: Vec<_> = self.path.iter().map(Segment::key).collect();
+        let (leaf, rename) = self.leaf.key();
+        key.push(leaf);
+        key.push((0, rename));
+        key
+    }
+}
+
+fn flatten(item: &ItemUse) -> Vec<Import> {
+    fn go(tree: &UseTree, path: &mut Vec<Segment>, res: &mut Vec<Import>) {
+        let leaf = match tree {
+            UseTree::Path(it) => {
+                path.push(Segment(it.ident.to_string()));
+                go(&it.tree, path, res);
+                path.pop();
+                return;
+            }
+            UseTree::Group(it) => {
+                for tree in &it.items {
+                    go(tree, path, res);
+                }
+                return;
+            }
+            UseTree::Name(it) => Leaf::Name(Segment(it.ident.to_string())),
+            UseTree::Rename(it) => {
+                Leaf::Rename(Segment(it.ident.to_string()), it.rename.to_string())
+            }
+            UseTree::Glob(_) => Leaf::Glob,
+        };
+        res.push(Import { path: path.clone(), leaf });
+    }
+    let mut res = Vec::new();
+    go(&item.tree, &mut Vec::new(), &mut res);
+    if item.leading_colon.is_some() {
+        for import in &mut res {
+            match import.path.first_mut() {
+                Some(first) => first.0.insert_str(0, "::"),
+                None => {
+                    if let Leaf::Name(name) | Leaf::Rename(name, _) = &mut import.leaf {
+                        name.0.insert_str(0, "::");
+                    }
+                }
+            }
+        }
+    }
+    res
+}
+
+/// The names under a path, merged back into a tree.
+#[derive(Default)]
+struct Tree {
+    leaves: BTreeSet<Leaf>,
+    children: BTreeMap<Segment, Tree>,
+}
+
+impl Tree {
+    fn insert(&mut self, import: &Import) {
+        let mut tree = self;
+        for segment in &import.path {
+            tree = tree.children.entry(segment.clone()).or_default();
+        }
+        tree.leaves.insert(import.leaf.clone());
+    }
+
+    /// The tree as written after `use`, or after `prefix::` when nested.
+    fn to_text(&self, nested: bool) -> String {
+        let mut items: Vec<(Leaf, String)> = Vec::new();
+        for leaf in &self.leaves {
+            items.push((leaf.clone(), leaf.to_text()));
+        }
+        for (segment, child) in &self.children {
+            let text = format!("{}::{}", segment.0, child.to_text(true));
+            items.push((Leaf::Name(segment.clone()), text));
+        }
+        items.sort_by(|(a, _), (b, _)| a.cmp(b));
+        match items.as_slice() {
+            // `a::{self}` can't be `a::self`.
+            [(Leaf::Name(name), text)] if !(nested && name.0 == "self") => text.clone(),
+            _ => {
+                let texts: Vec<&str> = items.iter().map(|(_, text)| text.as_str()).collect();
+                format!("{{{}}}", texts.join(", "))
+            }
+        }
+    }
+}
+
+/// Items with the same key can be merged.
+fn merge_key(item: &ItemUse) -> String {
+    let mut key = item.vis.to_token_stream().to_string();
+    for attr in &item.attrs {
+        key.push_str(&attr.to_token_stream().to_string());
+    }
+    key
+}
+
+fn build(model: &ItemUse, imports: &[Import]) -> ItemUse {
+    let mut tree = Tree::default();
+    for import in imports {
+        tree.insert(import);
+    }
+    let text = format!("use {};", tree.to_text(false));
+    let mut item: ItemUse = syn::parse_str(&text).expect("merged use tree should parse");
+    item.attrs = model.attrs.clone();
+    item.vis = model.vis.clone();
+    item
+}
+
+/// `std`, `core` and `alloc`, other crates, then the current crate.
+fn origin(item: &ItemUse) -> u8 {
+    let first = flatten(item).into_iter().next().map(|it| match it.path.first() {
+        Some(segment) => segment.0.clone(),
+        None => match it.leaf {
+            Leaf::Name(name) | Leaf::Rename(name, _) => name.0,
+            Leaf::Glob => String::new(),
+        },
+    });
+    match first.as_deref() {
+        Some("std" | "core" | "alloc") => 0,
+        Some("crate" | "self" | "super") => 2,
+        _ => 1,
+    }
+}
+
+/// A `use` item with the comments going with it.
+pub(crate) struct Use {
+    pub(crate) item: ItemUse,
+    /// Comments on their own lines before the item.
+    pub(crate) comments: Vec<String>,
+    /// Comments after the item, on its last line.
+    pub(crate) trailing: Vec<String>,
+    /// The item as written, kept as is when it has comments inside.
+    pub(crate) verbatim: Option<String>,
+}
+
+impl Use {
+    /// The text of the item with its comments, the lines after the first
+    /// indented by `indent`.
+    pub(crate) fn to_text(&self, indent: &str, max_width: usize) -> String {
+        let mut res = String::new();
+        for comment in &self.comments {
+            res.push_str(comment);
+            res.push('\n');
+            res.push_str(indent);
+        }
+        match &self.verbatim {
+            Some(text) => res.push_str(text),
+            None => res.push_str(print(&self.item, max_width).trim_end()),
+        }
+        for comment in &self.trailing {
+            res.push(' ');
+            res.push_str(comment);
+        }
+        res
+    }
+}
+
+/// Applies the granularity to the items of a group, and sorts the trees
+/// when `sort`. Items with comments inside are left as they are.
+fn merge(items: Vec<Use>, granularity: ImportsGranularity, sort: bool) -> Vec<Use> {
+    if granularity == ImportsGranularity::Preserve {
+        return items
+            .into_iter()
+            .map(|it| match it.verbatim.is_none() && sort {
+                true => Use { item: build(&it.item, &flatten(&it.item)), ..it },
+                false => it,
+            })
+            .collect();
+    }
+    // The names of each new item with the item its attributes come from, in
+    // order of appearance.
+    let mut merged: Vec<(Use, BTreeSet<Import>)> = Vec::new();
+    let mut index: BTreeMap<(String, Vec<Segment>, Option<Leaf>), usize> = BTreeMap::new();
+    for mut it in items {
+        if it.verbatim.is_some() {
+            merged.push((it, BTreeSet::new()));
+            continue;
+        }
+        let key = merge_key(&it.item);
+        for import in flatten(&it.item) {
+            // `use a;` and `use b;` are in different crates and modules, and
+            // `::a` can't be in braces.
+            let own = match import.path.is_empty() {
+                true => Some(import.leaf.clone()),
+                false => None,
+            };
+            let root = import.path.first().map_or(&own, |_| &None);
+            let is_absolute = match (import.path.first(), &import.leaf) {
+                (Some(segment), _) | (None, Leaf::Name(segment) | Leaf::Rename(segment, _)) => {
+                    segment.0.starts_with("::")
+                }
+                (None, Leaf::Glob) => false,
+            };
+            let (path, leaf) = match granularity {
+                ImportsGranularity::One if !is_absolute => (Vec::new(), None),
+                ImportsGranularity::Preserve
+                | ImportsGranularity::One
+                | ImportsGranularity::Crate => {
+                    (import.path.iter().take(1).cloned().collect(), root.clone())
+                }
+                ImportsGranularity::Module => (import.path.clone(), own),
+                ImportsGranularity::Item => (import.path.clone(), Some(import.leaf.clone())),
+            };
+            let idx = *index.entry((key.clone(), path, leaf)).or_insert_with(|| {
+                let new = Use {
+                    item: it.item.clone(),
+                    comments: Vec::new(),
+                    trailing: Vec::new(),
+                    verbatim: None,
+                };
+                merged.push((new, BTreeSet::new()));
+                merged.len() - 1
+            });
+            // The comments go with the first item the names go to.
+            let new = &mut merged[idx].0;
+            new.comments.append(&mut it.comments);
+            new.trailing.append(&mut it.trailing);
+            merged[idx].1.insert(import);
+        }
+    }
+    merged
+        .into_iter()
+        .map(|(mut it, imports)| {
+            if it.verbatim.is_none() {
+                it.item = build(&it.item, &imports.into_iter().collect::<Vec<_>>());
+            }
+            // Only one comment fits at the end of the line.
+            if it.trailing.len() > 1 {
+                it.comments.append(&mut it.trailing);
+            }
+            it
+        })
+        .collect()
+}
+
+/// prettyplease's output for `item`.
+pub(crate) fn print(item: &ItemUse, max_width: usize) -> String {
+    let file = File { shebang: None, attrs: Vec::new(), items: vec![Item::Use(item.clone())] };
+    layout::unparse(&file, max_width)
+}
+
+/// Orders `use` items, given in groups separated by blank lines, into the
+/// groups to print.
+pub(crate) fn organize(groups: Vec<Vec<Use>>, config: &Config) -> Vec<Vec<Use>> {
+    let groups = match config.group_imports {
+        GroupImports::Preserve => groups,
+        GroupImports::One => vec![groups.into_iter().flatten().collect()],
+        GroupImports::StdExternalCrate => {
+            let mut res = vec![Vec::new(), Vec::new(), Vec::new()];
+            for it in groups.into_iter().flatten() {
+                res[origin(&it.item) as usize].push(it);
+            }
+            res
+        }
+    };
+    let mut res = Vec::new();
+    for group in groups {
+        let mut group = merge(group, config.imports_granularity, config.reorder_imports);
+        if config.reorder_imports {
+            let mut keyed: Vec<(Vec<Import>, Use)> = group
+                .into_iter()
+                .map(|it| {
+                    let mut imports = flatten(&it.item);
+                    imports.sort_by(|a, b| a.key().cmp(&b.key()));
+                    (imports, it)
+                })
+                .collect();
+            keyed
+                .sort_by(|(a, _), (b, _)| a.iter().map(Import::key).cmp(b.iter().map(Import::key)));
+            // The same item twice, merging didn't catch it with `Preserve`.
+            let mut deduped: Vec<(Vec<Import>, Use)> = Vec::new();
+            for (imports, mut it) in keyed {
+                match deduped.last_mut() {
+                    Some((last_imports, last))
+                        if *last_imports == imports
+                            && last.verbatim.is_none()
+                            && it.verbatim.is_none()
+                            && merge_key(&last.item) == merge_key(&it.item) =>
+                    {
+                        last.comments.append(&mut it.comments);
+                        last.comments.append(&mut it.trailing);
+                    }
+                    _ => deduped.push((imports, it)),
+                }
+            }
+            group = deduped.into_iter().map(|(_, it)| it).collect();
+        }
+        if !group.is_empty() {
+            res.push(group);
+        }
+    }
+    res
+}
@@ -0,0 +1,80 @@
+//! Line width and indentation for the options prettyplease doesn't have.
+//!
+//! prettyplease is vendored with the width it fills lines to as a parameter,
+//! see `vendor/prettyplease`. It indents by four spaces, the indentation is
+//! rewritten afterwards.
+use syn::File;
+
+use crate::config::Config;
+use crate::lexer::lex;
+use crate::macros;
+
+pub(crate) const INDENT: usize = 4;
+
+/// prettyplease's output for `file` with lines of at most `max_width`
+/// columns, as far as prettyplease can go.
+pub(crate) fn unparse(file: &File, max_width: usize) -> String {
+    let mut file = file.clone();
+    let macros = macros::prepare(&mut file);
+    let text = prettyplease::unparse_with_margin(&file, max_width);
+    macros.restore(&brace_self_imports(&text), max_width)
+}
+
+/// prettyplease prints `use a::{self};` as `use a::self;`, which doesn't
+/// parse.
+fn brace_self_imports(text: &str) -> String {
+    let lexemes = lex(text);
+    let token = |idx: usize| lexemes.get(idx).map_or("", |it| &text[it.range.clone()]);
+    let mut res = String::with_capacity(text.len());
+    let mut cursor = 0;
+    for (idx, lexeme) in lexemes.iter().enumerate().skip(2) {
+        if token(idx) == "self"
+            && token(idx - 1) == ":"
+            && token(idx - 2) == ":"
+            && token(idx + 1) != ":"
+        {
+            res.push_str(&text[cursor..lexeme.range.start]);
+            res.push_str("{self}");
+            cursor = lexeme.range.end;
+        }
+    }
+    res.push_str(&text[cursor..]);
+    res
+}
+
+/// Indents `text`, indented by prettyplease, as configured.
+pub(crate) fn reindent(text: &str, config: &Config) -> String {
+    if !config.hard_tabs && config.tab_spaces == INDENT {
+        return text.to_string();
+    }
+    map_indents(text, |indent| {
+        let width = indent.len();
+        let mut res = config.indent(width / INDENT);
+        res.push_str(&" ".repeat(width % INDENT));
+        res
+    })
+}
+
+/// Replaces the leading spaces of the lines of `text`, but for lines starting
+/// within a string literal or a comment.
+pub(crate) fn map_indents(text: &str, mut f: impl FnMut(&str) -> String) -> String {
+    let lexemes = lex(text);
+    let mut lexemes = lexemes.iter().peekable();
+    let mut res = String::with_capacity(text.len());
+    let mut line_start = 0;
+    for line in text.split_inclusive('\n') {
+        while lexemes.peek().is_some_and(|it| it.range.end <= line_start) {
+            lexemes.next();
+        }
+        let is_inside = lexemes.peek().is_some_and(|it| it.range.start < line_start);
+        let content = line.trim_start_matches(' ');
+        if is_inside || content.trim().is_empty() {
+            res.push_str(line);
+        } else {
+            res.push_str(&f(&line[..line.len() - content.len()]));
+            res.push_str(content);
+        }
+        line_start += line.len();
+    }
+    res
+}
@@ -0,0 +1,196 @@
+//! A lexer splitting Rust code into tokens and comments, enough to compare
+//! the tokens of two texts and to find where comments are.
+//!
+//! Punctuation is split into single characters, and doc comments are tokens,
+//! since syn turns them into attributes.
+use std::ops::Range;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Kind {
+    Token,
+    LineComment,
+    BlockComment,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Lexeme {
+    pub(crate) kind: Kind,
+    pub(crate) range: Range<usize>,
+}
+
+impl Lexeme {
+    pub(crate) fn is_comment(&self) -> bool {
+        self.kind != Kind::Token
+    }
+}
+
+pub(crate) fn lex(text: &str) -> Vec<Lexeme> {
+    let mut lexer = Lexer { text, pos: 0 };
+    let mut res = Vec::new();
+    while let Some(lexeme) = lexer.next() {
+        res.push(lexeme);
+    }
+    res
+}
+
+struct Lexer<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Lexer<'_> {
+    fn next(&mut self) -> Option<Lexeme> {
+        self.eat_while(char::is_whitespace);
+        let start = self.pos;
+        let rest = self.rest();
+        let first = rest.chars().next()?;
+        let kind = if rest.starts_with("//") {
+            self.eat_while(|c| c != '\n');
+            let text = &self.text[start..self.pos];
+            let is_doc =
+                (text.starts_with("///") && !text.starts_with("////")) || text.starts_with("//!");
+            if is_doc {
+                Kind::Token
+            } else {
+                Kind::LineComment
+            }
+        } else if rest.starts_with("/*") {
+            self.block_comment();
+            let text = &self.text[start..self.pos];
+            let is_doc = (text.starts_with("/**") && !text.starts_with("/***") && text != "/**/")
+                || text.starts_with("/*!");
+            if is_doc {
+                Kind::Token
+            } else {
+                Kind::BlockComment
+            }
+        } else {
+            self.token(first);
+            Kind::Token
+        };
+        // A lone non-ASCII punctuation character still has to move on.
+        if self.pos == start {
+            self.pos += first.len_utf8();
+        }
+        Some(Lexeme { kind, range: start..self.pos })
+    }
+
+    fn token(&mut self, first: char) {
+        let rest = self.rest();
+        // Raw strings, `r#"…"#`, `br"…"` or `cr"…"`.
+        let raw_prefix = ["r", "br", "cr"].iter().find(|prefix| {
+            rest.strip_prefix(**prefix)
+                .is_some_and(|it| it.trim_start_matches('#').starts_with('"'))
+        });
+        if let Some(prefix) = raw_prefix {
+            self.pos += prefix.len();
+            let hashes = self.rest().len() - self.rest().trim_start_matches('#').len();
+            self.pos += hashes + 1;
+            let end = format!("\"{}", "#".repeat(hashes));
+            match self.rest().find(&end) {
+                Some(it) => self.pos += it + end.len(),
+                None => self.pos = self.text.len(),
+            }
+            return;
+        }
+        if rest.starts_with("b\"") || rest.starts_with("c\"") || rest.starts_with("b'") {
+            self.pos += 1;
+            self.quoted(self.rest().chars().next().unwrap());
+            return;
+        }
+        if rest.starts_with("r#") && rest[2..].chars().next().is_some_and(is_ident_start) {
+            self.pos += 2;
+            self.eat_while(is_ident_continue);
+            return;
+        }
+        match first {
+            '"' => self.quoted('"'),
+            '\'' => {
+                let mut chars = rest.chars().skip(1);
+                let is_char =
+                    matches!((chars.next(), chars.next()), (Some('\\'), _) | (Some(_), Some('\'')));
+                if is_char {
+                    self.quoted('\'');
+                } else {
+                    // A lifetime or a label.
+                    self.pos += 1;
+                    self.eat_while(is_ident_continue);
+                }
+            }
+            c if c.is_ascii_digit() => self.number(),
+            c if is_ident_start(c) => self.eat_while(is_ident_continue),
+            c => self.pos += c.len_utf8(),
+        }
+    }
+
+    /// A literal delimited by `quote`, with escapes.
+    fn quoted(&mut self, quote: char) {
+        self.pos += quote.len_utf8();
+        let mut chars = self.rest().char_indices();
+        while let Some((idx, c)) = chars.next() {
+            if c == '\\' {
+                chars.next();
+            } else if c == quote {
+                self.pos += idx + c.len_utf8();
+                return;
+            }
+        }
+        self.pos = self.text.len();
+    }
+
+    fn number(&mut self) {
+        let start = self.pos;
+        self.eat_while(|c| c.is_ascii_alphanumeric() || c == '_');
+        let mut rest = self.rest().chars();
+        // `1.5`, but not `1..2` or `1.max(2)`.
+        if rest.next() == Some('.') && rest.next().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+            self.eat_while(|c| c.is_ascii_alphanumeric() || c == '_');
+        }
+        // `1e-5`
+        let is_hex = self.text[start..].starts_with("0x");
+        if !is_hex && self.text[start..self.pos].ends_with(['e', 'E']) {
+            if let Some(sign @ ('+' | '-')) = self.rest().chars().next() {
+                self.pos += sign.len_utf8();
+                self.eat_while(|c| c.is_ascii_alphanumeric() || c == '_');
+            }
+        }
+    }
+
+    fn block_comment(&mut self) {
+        self.pos += 2;
+        let mut depth = 1;
+        while depth > 0 {
+            let rest = self.rest();
+            if rest.is_empty() {
+                return;
+            } else if rest.starts_with("/*") {
+                depth += 1;
+                self.pos += 2;
+            } else if rest.starts_with("*/") {
+                depth -= 1;
+                self.pos += 2;
+            } else {
+                self.pos += rest.chars().next().unwrap().len_utf8();
+            }
+        }
+    }
+
+    fn eat_while(&mut self, f: impl Fn(char) -> bool) {
+        let rest = self.rest();
+        let len = rest.find(|c| !f(c)).unwrap_or(rest.len());
+        self.pos += len;
+    }
+
+    fn rest(&self) -> &str {
+        &self.text[self.pos..]
+    }
+}
+
+fn is_ident_start(c: char) -> bool {
+    c == '_' || c.is_alphabetic()
+}
+
+fn is_ident_continue(c: char) -> bool {
+    c == '_' || c.is_alphanumeric()
+}
@@ -1,69 +1,108 @@
-use prettyplease::unparse;
+mod config;
+mod diff;
+mod doc_code;
+mod edits;
+mod error;
+mod imports;
+mod layout;
+mod lexer;
+mod macros;
+mod position;
+mod recover;
+mod reorder;
+mod trivia;
+
+#[cfg(test)]
+mod tests;
+
 use syn::File;
 use wasm_bindgen::prelude::*;
 
+pub use config::{Config, GroupImports, Heuristics, ImportsGranularity, NewlineStyle};
+pub use edits::{
+    format_edits, format_edits_best_effort, format_range, Range, RecoveredEdits, TextEdit,
+};
+pub use error::{Error, ParseError};
+pub use recover::{format_best_effort, Recovered};
+
 #[wasm_bindgen]
 pub fn format_rust_code(code: &str) -> Result<String, JsValue> {
-    // Parse the input code into a syntax tree
-    let syntax_tree: File =
-        syn::parse_str(code).map_err(|e| JsValue::from_str(&format!("Parsing error: {}", e)))?;
-
-    // Format the code using prettyplease
-    let formatted = unparse(&syntax_tree);
+    Ok(format(code, &Config::default())?)
+}
 
-    let mut result = formatted;
+/// Formats `code` with the options of `config`, the text of a `rustfmt.toml`.
+#[wasm_bindgen]
+pub fn format_rust_code_with_config(code: &str, config: &str) -> Result<String, JsValue> {
+    let config = Config::from_toml(config)?;
+    Ok(format(code, &config)?)
+}
 
-    result = format_imports_and_modules(&result);
+/// The edits formatting `code`, Monaco `TextEdit`s.
+#[wasm_bindgen]
+pub fn format_rust_code_edits(code: &str, config: &str) -> Result<JsValue, JsValue> {
+    let config = Config::from_toml(config)?;
+    let edits = format_edits(code, &config)?;
+    Ok(serde_wasm_bindgen::to_value(&edits)?)
+}
 
-    result = add_spaces_after_blocks_expr(&result);
+/// The edits formatting the top-level items of `code` that parse, and the
+/// errors of the others, `{ edits, errors }`.
+#[wasm_bindgen]
+pub fn format_rust_code_best_effort(code: &str, config: &str) -> Result<JsValue, JsValue> {
+    let config = Config::from_toml(config)?;
+    let recovered = format_edits_best_effort(code, &config)?;
+    Ok(serde_wasm_bindgen::to_value(&recovered)?)
+}
 
-    Ok(result)
+/// The edits formatting the items of `code` in a range of the editor.
+#[wasm_bindgen]
+pub fn format_rust_code_range(
+    code: &str,
+    config: &str,
+    start_line_number: u32,
+    start_column: u32,
+    end_line_number: u32,
+    end_column: u32,
+) -> Result<JsValue, JsValue> {
+    let config = Config::from_toml(config)?;
+    let range = Range { start_line_number, start_column, end_line_number, end_column };
+    let edits = format_range(code, &config, range)?;
+    Ok(serde_wasm_bindgen::to_value(&edits)?)
 }
 
-fn add_spaces_after_blocks_expr(code: &str) -> String {
-    let mut result = String::new();
-    let mut lines = code.lines().peekable();
+pub fn format(code: &str, config: &Config) -> Result<String, Error> {
+    // Spans of earlier calls are never used again
+    proc_macro2::extra::invalidate_current_thread_spans();
 
-    while let Some(line) = lines.next() {
-        result.push_str(line);
-        result.push('\n');
+    let newline = config.newline(code);
+    let code = code.replace("\r\n", "\n");
 
-        // Check if the current line ends with a closing brace `}`
-        if line.trim() == "}" {
-            // Add an extra newline after the closing brace
-            result.push('\n');
-        }
+    // Parse the input code into a syntax tree
+    let mut syntax_tree: File = error::parse_file(&code)?;
+
+    // Sort the imports and modules of every scope, with their comments
+    let reordered = reorder::reorder(&code, &syntax_tree, config);
+    if reordered != code {
+        syntax_tree = error::parse_file(&reordered)?;
     }
+    let code = reordered;
 
-    result
-}
+    // Format the code using prettyplease
+    let formatted = layout::unparse(&syntax_tree, config.max_width);
 
-fn format_imports_and_modules(code: &str) -> String {
-    let mut result = String::new();
-    let mut imports = Vec::new();
-    let mut other_code = Vec::new();
-
-    for line in code.lines() {
-        if line.trim().starts_with("use ") || line.trim().starts_with("mod ") {
-            imports.push(line.trim());
-        } else {
-            other_code.push(line);
-        }
-    }
+    // Put back the comments and blank lines the syntax tree doesn't have
+    let mut result =
+        trivia::reattach(&code, &formatted).map_err(|message| Error::Format { message })?;
 
-    // Sort and format imports
-    imports.sort();
-    for import in imports {
-        result.push_str(&format!("{}\n", import));
-    }
+    result = layout::reindent(&result, config);
 
-    result.push_str(&format!("\n\n"));
+    if config.format_code_in_doc_comments {
+        result = doc_code::format_code_blocks(&result, config);
+    }
 
-    // Add the rest of the code
-    for line in other_code {
-        result.push_str(line);
-        result.push('\n');
+    if newline != "\n" {
+        result = result.replace('\n', newline);
     }
 
-    result
+    Ok(result)
 }
@@ -0,0 +1,410 @@
+//! Macro invocations and `macro_rules!` arms, which prettyplease prints as
+//! tokens.
+//!
+//! Before printing, invocations whose arguments parse are swapped for code
+//! prettyplease formats: calls to a function named as wide as `name!` for
+//! expressions, modules for items. The arms of a `macro_rules!` whose bodies
+//! parse once `$x` is read as `zx`, as rustfmt does, are swapped for a
+//! placeholder and formatted on their own, with the width they're left with.
+use std::collections::{HashMap, HashSet};
+
+use proc_macro2::{Delimiter, Group, Ident, TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::visit_mut::{self, VisitMut};
+use syn::{
+    Attribute, Block, Expr, ExprCall, ExprPath, File, Item, ItemFn, ItemMod, Macro, MacroDelimiter,
+    Stmt, Token,
+};
+
+use crate::layout::{self, INDENT};
+use crate::lexer::lex;
+
+#[derive(Default)]
+pub(crate) struct Macros {
+    /// The functions standing for invocations, and what they stand for.
+    calls: HashMap<String, Call>,
+    /// The modules standing for invocations, and the `path!` they stand for.
+    mods: HashMap<String, String>,
+    /// The placeholders of `macro_rules!` arms, and their bodies.
+    arms: HashMap<String, Arm>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Call {
+    name: String,
+    is_bracket: bool,
+    trailing_comma: bool,
+}
+
+struct Arm {
+    stmts: Vec<Stmt>,
+    /// Whether the body is a block, `{{ … }}`.
+    is_double: bool,
+    /// The metavariables, `x` for `$x`.
+    vars: Vec<String>,
+}
+
+/// Swaps the macros of `file` that can be formatted for code prettyplease
+/// formats, to be swapped back by `Macros::restore`.
+pub(crate) fn prepare(file: &mut File) -> Macros {
+    let mut idents = HashSet::new();
+    collect_idents(file.to_token_stream(), &mut idents);
+    let mut prepare = Prepare { idents, macros: Macros::default() };
+    prepare.visit_file_mut(file);
+    prepare.macros
+}
+
+struct Prepare {
+    /// The identifiers a placeholder mustn't be.
+    idents: HashSet<String>,
+    macros: Macros,
+}
+
+impl Prepare {
+    /// The call standing for an invocation whose arguments are expressions.
+    fn call(&mut self, attrs: &[Attribute], mac: &Macro) -> Option<Expr> {
+        let is_bracket = match mac.delimiter {
+            MacroDelimiter::Paren(_) => false,
+            MacroDelimiter::Bracket(_) => true,
+            MacroDelimiter::Brace(_) => return None,
+        };
+        if mac.tokens.is_empty() {
+            return None;
+        }
+        let args =
+            Punctuated::<Expr, Token![,]>::parse_terminated.parse2(mac.tokens.clone()).ok()?;
+        let mut path = mac.path.clone();
+        let last = path.segments.last_mut()?;
+        let call = Call {
+            name: last.ident.to_string(),
+            is_bracket,
+            trailing_comma: args.trailing_punct(),
+        };
+        last.ident = Ident::new(&self.function(call), last.ident.span());
+        Some(Expr::Call(ExprCall {
+            attrs: attrs.to_vec(),
+            func: Box::new(Expr::Path(ExprPath { attrs: Vec::new(), qself: None, path })),
+            paren_token: Default::default(),
+            args,
+        }))
+    }
+
+    /// A function name as wide as `name!`, for the invocations like `call`.
+    fn function(&mut self, call: Call) -> String {
+        if let Some((name, _)) = self.macros.calls.iter().find(|(_, it)| **it == call) {
+            return name.clone();
+        }
+        let chars: Vec<char> = call.name.chars().collect();
+        let name = (0..)
+            .map(|idx: usize| {
+                let suffix = if idx == 0 { "_".to_string() } else { format!("_{}", idx - 1) };
+                let keep = (chars.len() + 1).saturating_sub(suffix.len());
+                chars[..keep].iter().collect::<String>() + &suffix
+            })
+            .find(|it| !self.idents.contains(it) && !self.macros.calls.contains_key(it))
+            .unwrap();
+        self.macros.calls.insert(name.clone(), call);
+        name
+    }
+
+    /// The module standing for an invocation whose arguments are items.
+    fn module(&mut self, attrs: &[Attribute], mac: &Macro) -> Option<Item> {
+        let MacroDelimiter::Brace(brace) = mac.delimiter else { return None };
+        let items = parse_items.parse2(mac.tokens.clone()).ok()?;
+        if items.is_empty() {
+            return None;
+        }
+        let ident = format!("__rustfmt_macro_{}", self.macros.mods.len());
+        let path = mac.path.segments.iter().map(|it| it.ident.to_string()).collect::<Vec<_>>();
+        let colon = if mac.path.leading_colon.is_some() { "::" } else { "" };
+        self.macros.mods.insert(ident.clone(), format!("{}{}!", colon, path.join("::")));
+        Some(Item::Mod(ItemMod {
+            attrs: attrs.to_vec(),
+            vis: syn::Visibility::Inherited,
+            unsafety: None,
+            mod_token: Default::default(),
+            ident: Ident::new(&ident, proc_macro2::Span::call_site()),
+            content: Some((brace, items)),
+            semi: None,
+        }))
+    }
+
+    /// Swaps the arms of a `macro_rules!` whose bodies parse for
+    /// placeholders.
+    fn macro_rules(&mut self, mac: &mut Macro) {
+        let tokens: Vec<TokenTree> = mac.tokens.clone().into_iter().collect();
+        let mut res = TokenStream::new();
+        let mut idx = 0;
+        while idx < tokens.len() {
+            let (
+                Some(TokenTree::Group(_)),
+                Some(TokenTree::Punct(eq)),
+                Some(TokenTree::Punct(gt)),
+                Some(TokenTree::Group(body)),
+            ) = (tokens.get(idx), tokens.get(idx + 1), tokens.get(idx + 2), tokens.get(idx + 3))
+            else {
+                return;
+            };
+            if eq.as_char() != '=' || gt.as_char() != '>' {
+                return;
+            }
+            res.extend(tokens[idx..idx + 3].iter().cloned());
+            match self.arm(body) {
+                Some(ident) => {
+                    let ident = TokenTree::Ident(Ident::new(&ident, body.span()));
+                    let group = Group::new(Delimiter::Brace, ident.into());
+                    res.extend([TokenTree::Group(group)]);
+                }
+                None => res.extend([TokenTree::Group(body.clone())]),
+            }
+            idx += 4;
+            if let Some(TokenTree::Punct(semi)) = tokens.get(idx) {
+                if semi.as_char() == ';' {
+                    res.extend([tokens[idx].clone()]);
+                    idx += 1;
+                }
+            }
+        }
+        mac.tokens = res;
+    }
+
+    /// The placeholder of the body of an arm, if it parses as statements.
+    fn arm(&mut self, body: &Group) -> Option<String> {
+        if body.delimiter() != Delimiter::Brace {
+            return None;
+        }
+        let mut stream = body.stream();
+        let mut inner = stream.clone().into_iter();
+        let is_double = match (inner.next(), inner.next()) {
+            (Some(TokenTree::Group(group)), None) if group.delimiter() == Delimiter::Brace => {
+                stream = group.stream();
+                true
+            }
+            _ => false,
+        };
+
+        let mut idents = HashSet::new();
+        collect_idents(stream.clone(), &mut idents);
+        let mut vars = Vec::new();
+        let stream = replace_vars(stream, &mut vars)?;
+        if vars.iter().any(|var| idents.contains(&format!("z{}", var))) {
+            return None;
+        }
+        let stmts = Block::parse_within.parse2(stream).ok()?;
+        if stmts.is_empty() {
+            return None;
+        }
+        let ident = format!("__rustfmt_arm_{}", self.macros.arms.len());
+        self.macros.arms.insert(ident.clone(), Arm { stmts, is_double, vars });
+        Some(ident)
+    }
+}
+
+impl VisitMut for Prepare {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        if let Expr::Macro(it) = expr {
+            if let Some(call) = self.call(&it.attrs, &it.mac) {
+                *expr = call;
+            }
+        }
+        visit_mut::visit_expr_mut(self, expr);
+    }
+
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        if let Stmt::Macro(it) = stmt {
+            if let Some(call) = self.call(&it.attrs, &it.mac) {
+                *stmt = Stmt::Expr(call, it.semi_token);
+            } else if let Some(item) = self.module(&it.attrs, &it.mac) {
+                *stmt = Stmt::Item(item);
+            }
+        }
+        visit_mut::visit_stmt_mut(self, stmt);
+    }
+
+    fn visit_item_mut(&mut self, item: &mut Item) {
+        if let Item::Macro(it) = item {
+            if it.ident.is_some() && it.mac.path.is_ident("macro_rules") {
+                self.macro_rules(&mut it.mac);
+            } else if let Some(module) = self.module(&it.attrs, &it.mac) {
+                *item = module;
+            }
+        }
+        visit_mut::visit_item_mut(self, item);
+    }
+}
+
+impl Macros {
+    /// `text`, printed from the file `prepare` was given, with the macros
+    /// back.
+    pub(crate) fn restore(&self, text: &str, max_width: usize) -> String {
+        if self.calls.is_empty() && self.mods.is_empty() && self.arms.is_empty() {
+            return text.to_string();
+        }
+        let text = self.restore_invocations(text);
+        self.restore_arms(&text, max_width)
+    }
+
+    fn restore_invocations(&self, text: &str) -> String {
+        let lexemes: Vec<_> = lex(text).into_iter().filter(|it| !it.is_comment()).collect();
+        let token = |idx: usize| lexemes.get(idx).map_or("", |it| &text[it.range.clone()]);
+        let mut edits = Vec::new();
+        for (idx, lexeme) in lexemes.iter().enumerate() {
+            if let Some(call) = self.calls.get(token(idx)).filter(|_| token(idx + 1) == "(") {
+                let close = matching(idx + 1, token);
+                let (open, close_text) = if call.is_bracket { ("![", "]") } else { ("!(", ")") };
+                edits.push((
+                    lexeme.range.start..lexemes[idx + 1].range.end,
+                    call.name.clone() + open,
+                ));
+                if !call.trailing_comma && token(close - 1) == "," {
+                    edits.push((lexemes[close - 1].range.clone(), String::new()));
+                }
+                edits.push((lexemes[close].range.clone(), close_text.to_string()));
+            } else if let Some(path) = self.mods.get(token(idx + 1)).filter(|_| token(idx) == "mod")
+            {
+                edits.push((lexeme.range.start..lexemes[idx + 1].range.end, path.clone()));
+            }
+        }
+        edits.sort_by_key(|(range, _)| range.start);
+        let mut res = String::with_capacity(text.len());
+        let mut cursor = 0;
+        for (range, replacement) in edits {
+            res.push_str(&text[cursor..range.start]);
+            res.push_str(&replacement);
+            cursor = range.end;
+        }
+        res.push_str(&text[cursor..]);
+        res
+    }
+
+    fn restore_arms(&self, text: &str, max_width: usize) -> String {
+        let mut res = String::with_capacity(text.len());
+        let mut close_double = false;
+        for line in text.split_inclusive('\n') {
+            let content = line.trim_start_matches(' ');
+            let indent = &line[..line.len() - content.len()];
+            if close_double {
+                res.push_str(indent);
+                res.push('}');
+                res.push_str(content);
+                close_double = false;
+                continue;
+            }
+            let Some(arm) = self.arms.get(content.trim_end()) else {
+                res.push_str(line);
+                continue;
+            };
+            if arm.is_double {
+                // The arm's `{` ends the previous line.
+                res.insert(res.trim_end().len(), '{');
+                close_double = true;
+            }
+            res.push_str(&arm.body(indent, max_width));
+        }
+        res
+    }
+}
+
+impl Arm {
+    /// The body formatted at `indent`.
+    fn body(&self, indent: &str, max_width: usize) -> String {
+        let mut item: ItemFn = syn::parse_quote!(
+            fn __rustfmt_arm() {}
+        );
+        item.block.stmts = self.stmts.clone();
+        let file = File { shebang: None, attrs: Vec::new(), items: vec![Item::Fn(item)] };
+        let width = max_width.saturating_sub(indent.len()) + INDENT;
+        let text = layout::unparse(&file, width);
+
+        let lines: Vec<&str> = text.lines().collect();
+        let mut body = String::new();
+        for line in &lines[1..lines.len() - 1] {
+            body.push_str(line);
+            body.push('\n');
+        }
+        let body = layout::map_indents(&body, |it| {
+            format!("{}{}", indent, it.get(INDENT..).unwrap_or(""))
+        });
+
+        // Back to `$x`.
+        let vars: HashSet<String> = self.vars.iter().map(|it| format!("z{}", it)).collect();
+        let mut res = String::with_capacity(body.len());
+        let mut cursor = 0;
+        for lexeme in lex(&body) {
+            if vars.contains(&body[lexeme.range.clone()]) {
+                res.push_str(&body[cursor..lexeme.range.start]);
+                res.push('$');
+                res.push_str(&body[lexeme.range.start + 1..lexeme.range.end]);
+                cursor = lexeme.range.end;
+            }
+        }
+        res.push_str(&body[cursor..]);
+        res
+    }
+}
+
+/// The index of the token closing the one at `open`.
+fn matching<'a>(open: usize, token: impl Fn(usize) -> &'a str) -> usize {
+    let mut depth = 0;
+    let mut idx = open;
+    loop {
+        match token(idx) {
+            "(" | "[" | "{" => depth += 1,
+            ")" | "]" | "}" => depth -= 1,
+            "" => return idx - 1,
+            _ => {}
+        }
+        if depth == 0 {
+            return idx;
+        }
+        idx += 1;
+    }
+}
+
+fn parse_items(input: syn::parse::ParseStream) -> syn::Result<Vec<Item>> {
+    let mut items = Vec::new();
+    while !input.is_empty() {
+        items.push(input.parse()?);
+    }
+    Ok(items)
+}
+
+/// `stream` with the metavariables `$x` as `zx`, `None` if it has
+/// repetitions.
+fn replace_vars(stream: TokenStream, vars: &mut Vec<String>) -> Option<TokenStream> {
+    let mut res = Vec::new();
+    let mut tokens = stream.into_iter();
+    while let Some(token) = tokens.next() {
+        match token {
+            TokenTree::Punct(punct) if punct.as_char() == '$' => {
+                let Some(TokenTree::Ident(ident)) = tokens.next() else { return None };
+                let name = ident.to_string();
+                res.push(TokenTree::Ident(Ident::new(&format!("z{}", name), ident.span())));
+                if !vars.contains(&name) {
+                    vars.push(name);
+                }
+            }
+            TokenTree::Group(group) => {
+                let mut new = Group::new(group.delimiter(), replace_vars(group.stream(), vars)?);
+                new.set_span(group.span());
+                res.push(TokenTree::Group(new));
+            }
+            token => res.push(token),
+        }
+    }
+    Some(res.into_iter().collect())
+}
+
+fn collect_idents(stream: TokenStream, idents: &mut HashSet<String>) {
+    for token in stream {
+        match token {
+            TokenTree::Ident(ident) => {
+                idents.insert(ident.to_string());
+            }
+            TokenTree::Group(group) => collect_idents(group.stream(), idents),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! Conversions between byte offsets and line/column positions, those of
+//! proc-macro2 spans and those of the editor.
+use proc_macro2::LineColumn;
+
+pub(crate) struct LineIndex<'a> {
+    text: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub(crate) fn new(text: &'a str) -> LineIndex<'a> {
+        let mut line_starts = vec![0];
+        line_starts.extend(text.match_indices('\n').map(|(idx, _)| idx + 1));
+        LineIndex { text, line_starts }
+    }
+
+    /// The offset of a span's position, with 1-based lines and columns
+    /// counting characters.
+    pub(crate) fn span_offset(&self, pos: LineColumn) -> usize {
+        let line_start = self.line_starts[pos.line - 1];
+        let line = &self.text[line_start..];
+        line.char_indices().nth(pos.column).map_or(self.text.len(), |(idx, _)| line_start + idx)
+    }
+
+    /// The offset of an editor position, with 1-based lines and columns
+    /// counting UTF-16 code units, clamped to the text.
+    pub(crate) fn offset(&self, line: u32, column: u32) -> usize {
+        let Some(&line_start) = self.line_starts.get(line.saturating_sub(1) as usize) else {
+            return self.text.len();
+        };
+        let line = &self.text[line_start..];
+        let line = &line[..line.find('\n').unwrap_or(line.len())];
+        let mut col = 1;
+        for (idx, c) in line.char_indices() {
+            if col >= column {
+                return line_start + idx;
+            }
+            col += c.len_utf16() as u32;
+        }
+        line_start + line.len()
+    }
+
+    /// The editor position of an offset.
+    pub(crate) fn position(&self, offset: usize) -> (u32, u32) {
+        let line = self.line_starts.partition_point(|it| *it <= offset) - 1;
+        let line_start = self.line_starts[line];
+        let column: usize = self.text[line_start..offset].chars().map(char::len_utf16).sum();
+        (line as u32 + 1, column as u32 + 1)
+    }
+}
@@ -0,0 +1,155 @@
+//! Formatting code that doesn't parse, item by item.
+//!
+//! The code is cut into its top-level items with the lexer, by counting
+//! delimiters. The runs of items that parse are formatted, the others are
+//! left as they are and their errors reported.
+use std::ops::Range;
+
+use syn::File;
+
+use crate::config::Config;
+use crate::error::{self, Error, ParseError};
+use crate::lexer::{lex, Lexeme};
+use crate::position::LineIndex;
+
+/// Keywords starting an item, an unindented line starting with one ends an
+/// item whose delimiters aren't closed.
+const ITEM_STARTS: &[&str] = &[
+    "#", "const", "enum", "extern", "fn", "impl", "mod", "pub", "static", "struct", "trait",
+    "type", "union", "unsafe", "use",
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Recovered {
+    pub code: String,
+    /// The errors of the items left as they were, in `code`.
+    pub errors: Vec<ParseError>,
+}
+
+/// Top-level items, with their parse errors.
+struct Chunk {
+    range: Range<usize>,
+    errors: Vec<(Range<usize>, String)>,
+}
+
+/// `code` with the top-level items that parse formatted.
+pub fn format_best_effort(code: &str, config: &Config) -> Result<Recovered, Error> {
+    if syn::parse_str::<File>(code).is_ok() {
+        let code = crate::format(code, config)?;
+        return Ok(Recovered { code, errors: Vec::new() });
+    }
+
+    // The errors are found before anything is formatted, formatting
+    // invalidates their spans.
+    let chunks: Vec<Chunk> = chunks(code)
+        .into_iter()
+        .map(|range| {
+            let errors = match syn::parse_str::<File>(&code[range.clone()]) {
+                Ok(_) => Vec::new(),
+                Err(err) => error::error_ranges(&code[range.clone()], &err),
+            };
+            Chunk { range, errors }
+        })
+        .collect();
+
+    let config = config.for_part_of(code);
+    let mut res = String::with_capacity(code.len());
+    let mut errors = Vec::new();
+    let mut idx = 0;
+    while idx < chunks.len() {
+        let chunk = &chunks[idx];
+        if !chunk.errors.is_empty() {
+            let offset = res.len();
+            errors.extend(chunk.errors.iter().map(|(range, message)| {
+                (offset + range.start..offset + range.end, message.clone())
+            }));
+            res.push_str(&code[chunk.range.clone()]);
+            idx += 1;
+            continue;
+        }
+        let end = chunks[idx..]
+            .iter()
+            .position(|it| !it.errors.is_empty())
+            .map_or(chunks.len(), |it| idx + it);
+        let run = &code[chunk.range.start..chunks[end - 1].range.end];
+        let text = run.trim_start();
+        if text.is_empty() {
+            res.push_str(run);
+        } else {
+            match crate::format(text, &config) {
+                Ok(formatted) => {
+                    // Keeps a blank line after the item left as it was.
+                    if idx > 0 && run[..run.len() - text.len()].contains('\n') {
+                        res.push_str(config.newline(code));
+                    }
+                    res.push_str(&formatted);
+                }
+                Err(_) => res.push_str(run),
+            }
+        }
+        idx = end;
+    }
+
+    let index = LineIndex::new(&res);
+    let errors = errors
+        .into_iter()
+        .map(|(range, message)| ParseError { message, range: error::editor_range(&index, range) })
+        .collect();
+    Ok(Recovered { code: res, errors })
+}
+
+/// The ranges of the top-level items of `code`, with the comments and blank
+/// lines before them and the comments ending their last line, covering it.
+fn chunks(code: &str) -> Vec<Range<usize>> {
+    let lexemes = lex(code);
+    let text = |lexeme: &Lexeme| &code[lexeme.range.clone()];
+    let mut res = Vec::new();
+    let mut start = 0;
+    let mut depth = 0usize;
+    for (idx, lexeme) in lexemes.iter().enumerate() {
+        if lexeme.is_comment() {
+            continue;
+        }
+        let line_start = code[..lexeme.range.start].rfind('\n').map_or(0, |it| it + 1);
+        let is_unindented = line_start == lexeme.range.start;
+        if depth > 0 && is_unindented && ITEM_STARTS.contains(&text(lexeme)) && line_start > start {
+            res.push(start..line_start);
+            start = line_start;
+            depth = 0;
+        }
+        match text(lexeme) {
+            "(" | "[" | "{" => depth += 1,
+            ")" | "]" | "}" => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        let next = lexemes[idx + 1..].iter().find(|it| !it.is_comment()).map(text);
+        let is_end = depth == 0
+            && match text(lexeme) {
+                ";" => true,
+                "}" => next != Some(";"),
+                _ => false,
+            };
+        if !is_end {
+            continue;
+        }
+        // The comments left on the line go with the item.
+        let mut end = lexeme.range.end;
+        for it in lexemes[idx + 1..].iter().take_while(|it| it.is_comment()) {
+            if code[end..it.range.start].contains('\n') {
+                break;
+            }
+            end = it.range.end;
+        }
+        let rest = &code[end..];
+        let line_end = rest.find('\n').map_or(code.len(), |it| end + it + 1);
+        if code[end..line_end].trim().is_empty() {
+            end = line_end;
+        }
+        res.push(start..end);
+        start = end;
+    }
+    if start < code.len() || res.is_empty() {
+        res.push(start..code.len());
+    }
+    res
+}
@@ -0,0 +1,289 @@
+//! Sorts the `use` items and `mod` declarations of every scope, the file,
+//! inline modules and blocks, in the source before it is formatted.
+//!
+//! Consecutive items are reordered among themselves, a blank line starts a
+//! new group. The comments before an item and at the end of its line move
+//! with it, other comments stay where they are and split the items around
+//! them.
+use syn::spanned::Spanned;
+use syn::visit::{self, Visit};
+use syn::{Block, File, Item, ItemMod, Stmt};
+
+use crate::config::Config;
+use crate::imports::{self, Use};
+use crate::lexer::{lex, Lexeme};
+use crate::position::LineIndex;
+
+/// `code` with its imports and modules sorted, `file` being its syntax tree.
+pub(crate) fn reorder(code: &str, file: &File, config: &Config) -> String {
+    let mut scopes = Scopes { runs: Vec::new() };
+    scopes.visit_file(file);
+    let source = Source::new(code);
+    let mut edits = Vec::new();
+    for run in &scopes.runs {
+        for units in source.split(run) {
+            edits.extend(source.rewrite(&units, config));
+        }
+    }
+    edits.sort_by_key(|(range, _)| range.start);
+    let mut res = String::with_capacity(code.len());
+    let mut cursor = 0;
+    for (range, text) in edits {
+        res.push_str(&code[cursor..range.start]);
+        res.push_str(&text);
+        cursor = range.end;
+    }
+    res.push_str(&code[cursor..]);
+    res
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Use,
+    /// `mod a;`, modules with a body are left where they are.
+    Mod,
+}
+
+fn kind(item: &Item) -> Option<Kind> {
+    match item {
+        Item::Use(_) => Some(Kind::Use),
+        Item::Mod(it) if it.content.is_none() => Some(Kind::Mod),
+        _ => None,
+    }
+}
+
+/// The runs of consecutive items of a kind in every scope.
+struct Scopes<'a> {
+    runs: Vec<Vec<&'a Item>>,
+}
+
+impl<'a> Scopes<'a> {
+    fn scope(&mut self, items: impl Iterator<Item = Option<&'a Item>>) {
+        let mut run: Vec<&Item> = Vec::new();
+        for item in items {
+            let item_kind = item.and_then(kind);
+            if run.first().and_then(|it| kind(it)) != item_kind && !run.is_empty() {
+                self.runs.push(std::mem::take(&mut run));
+            }
+            if let (Some(item), Some(_)) = (item, item_kind) {
+                run.push(item);
+            }
+        }
+        if !run.is_empty() {
+            self.runs.push(run);
+        }
+    }
+}
+
+impl<'a> Visit<'a> for Scopes<'a> {
+    fn visit_file(&mut self, file: &'a File) {
+        self.scope(file.items.iter().map(Some));
+        visit::visit_file(self, file);
+    }
+
+    fn visit_item_mod(&mut self, item: &'a ItemMod) {
+        if let Some((_, items)) = &item.content {
+            self.scope(items.iter().map(Some));
+        }
+        visit::visit_item_mod(self, item);
+    }
+
+    fn visit_block(&mut self, block: &'a Block) {
+        self.scope(block.stmts.iter().map(|stmt| match stmt {
+            Stmt::Item(item) => Some(item),
+            _ => None,
+        }));
+        visit::visit_block(self, block);
+    }
+}
+
+/// An item with the comments going with it.
+struct Unit<'a> {
+    item: &'a Item,
+    /// From the first comment before the item to the last one after it.
+    start: usize,
+    end: usize,
+    text: &'a str,
+    comments: Vec<&'a str>,
+    trailing: Vec<&'a str>,
+    has_inner_comments: bool,
+    /// Whether a blank line separates it from the previous item.
+    is_blank_before: bool,
+}
+
+struct Source<'a> {
+    code: &'a str,
+    lexemes: Vec<Lexeme>,
+    index: LineIndex<'a>,
+}
+
+impl<'a> Source<'a> {
+    fn new(code: &'a str) -> Source<'a> {
+        Source { code, lexemes: lex(code), index: LineIndex::new(code) }
+    }
+
+    fn text(&self, lexeme: &Lexeme) -> &'a str {
+        &self.code[lexeme.range.clone()]
+    }
+
+    /// The runs of items split at the comments between them that don't go
+    /// with either.
+    fn split(&self, run: &[&'a Item]) -> Vec<Vec<Unit<'a>>> {
+        let mut res: Vec<Vec<Unit>> = vec![Vec::new()];
+        for item in run {
+            let span = item.span();
+            let (start, end) =
+                (self.index.span_offset(span.start()), self.index.span_offset(span.end()));
+            let first = self.lexemes.partition_point(|it| it.range.start < start);
+            let last = self.lexemes.partition_point(|it| it.range.start < end);
+
+            // The comments at the end of the previous token's line are its
+            // own, then those followed by a blank line stay where they are.
+            let mut unit_start = start;
+            let mut comments = Vec::new();
+            let mut is_blank_before = false;
+            let mut idx = first;
+            while idx > 0 && self.lexemes[idx - 1].is_comment() {
+                let lexeme = &self.lexemes[idx - 1];
+                let gap = &self.code[lexeme.range.end..unit_start];
+                let before = match idx - 1 {
+                    0 => "\n",
+                    prev => &self.code[self.lexemes[prev - 1].range.end..lexeme.range.start],
+                };
+                if gap.matches('\n').count() > 1 {
+                    is_blank_before = true;
+                    break;
+                }
+                if !before.contains('\n') {
+                    break;
+                }
+                unit_start = lexeme.range.start;
+                comments.push(self.text(lexeme));
+                idx -= 1;
+            }
+            comments.reverse();
+            let gap_start = match idx {
+                0 => 0,
+                idx => self.lexemes[idx - 1].range.end,
+            };
+            if self.code[gap_start..unit_start].matches('\n').count() > 1 {
+                is_blank_before = true;
+            }
+            let previous = res.last().unwrap().last().map(|it: &Unit| it.end);
+            if previous.is_some_and(|it| it != gap_start) {
+                res.push(Vec::new());
+            }
+
+            let mut unit_end = end;
+            let mut trailing = Vec::new();
+            for lexeme in &self.lexemes[last..] {
+                if !lexeme.is_comment() || self.code[unit_end..lexeme.range.start].contains('\n') {
+                    break;
+                }
+                unit_end = lexeme.range.end;
+                trailing.push(self.text(lexeme));
+            }
+
+            res.last_mut().unwrap().push(Unit {
+                item,
+                start: unit_start,
+                end: unit_end,
+                text: &self.code[start..end],
+                comments,
+                trailing,
+                has_inner_comments: self.lexemes[first..last].iter().any(Lexeme::is_comment),
+                is_blank_before,
+            });
+        }
+        res.retain(|units| !units.is_empty());
+        res
+    }
+
+    /// The new text of a run of units.
+    fn rewrite(
+        &self,
+        units: &[Unit<'a>],
+        config: &Config,
+    ) -> Option<(std::ops::Range<usize>, String)> {
+        let start = units.first()?.start;
+        let end = units.last()?.end;
+        let line_start = self.code[..start].rfind('\n').map_or(0, |it| it + 1);
+        let line = &self.code[line_start..start];
+        let indent = &line[..line.len() - line.trim_start().len()];
+
+        let mut groups: Vec<Vec<String>> = Vec::new();
+        match kind(units[0].item)? {
+            Kind::Use => {
+                let mut uses: Vec<Vec<Use>> = Vec::new();
+                for (idx, unit) in units.iter().enumerate() {
+                    let Item::Use(item) = unit.item else { return None };
+                    if idx == 0 || unit.is_blank_before {
+                        uses.push(Vec::new());
+                    }
+                    uses.last_mut().unwrap().push(Use {
+                        item: item.clone(),
+                        comments: unit.comments.iter().map(|it| it.to_string()).collect(),
+                        trailing: unit.trailing.iter().map(|it| it.to_string()).collect(),
+                        verbatim: unit.has_inner_comments.then(|| unit.text.to_string()),
+                    });
+                }
+                for group in imports::organize(uses, config) {
+                    groups.push(
+                        group.iter().map(|it| it.to_text(indent, config.max_width)).collect(),
+                    );
+                }
+            }
+            Kind::Mod => {
+                let mut mods: Vec<Vec<&Unit>> = Vec::new();
+                for (idx, unit) in units.iter().enumerate() {
+                    if idx == 0 || unit.is_blank_before {
+                        mods.push(Vec::new());
+                    }
+                    mods.last_mut().unwrap().push(unit);
+                }
+                for mut group in mods {
+                    if config.reorder_modules {
+                        group.sort_by_key(|unit| match unit.item {
+                            Item::Mod(it) => it.ident.to_string(),
+                            _ => String::new(),
+                        });
+                    }
+                    groups.push(group.iter().map(|unit| unit_text(unit, indent)).collect());
+                }
+            }
+        }
+
+        let mut text = String::new();
+        for (idx, group) in groups.iter().enumerate() {
+            for (jdx, unit) in group.iter().enumerate() {
+                if idx > 0 && jdx == 0 {
+                    text.push('\n');
+                }
+                if idx > 0 || jdx > 0 {
+                    text.push('\n');
+                    text.push_str(indent);
+                }
+                text.push_str(unit);
+            }
+        }
+        if text == self.code[start..end] {
+            return None;
+        }
+        Some((start..end, text))
+    }
+}
+
+fn unit_text(unit: &Unit, indent: &str) -> String {
+    let mut res = String::new();
+    for comment in &unit.comments {
+        res.push_str(comment);
+        res.push('\n');
+        res.push_str(indent);
+    }
+    res.push_str(unit.text);
+    for comment in &unit.trailing {
+        res.push(' ');
+        res.push_str(comment);
+    }
+    res
+}
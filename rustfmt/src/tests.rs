@@ -0,0 +1,545 @@
+//! Formats the files of `test_data` and random code, checking that the
+//! output doesn't change when formatted again and keeps the tokens and the
+//! comments of the input.
+//!
+//! Each `name.rs` of `test_data` is compared with `name.out.rs`, run with
+//! `UPDATE_EXPECT=1` to write them.
+use std::borrow::Cow;
+use std::fs;
+use std::path::Path;
+
+use proptest::prelude::*;
+
+use crate::config::{Config, GroupImports, ImportsGranularity};
+use crate::diff;
+use crate::error::Error;
+use crate::lexer::{lex, Kind};
+
+/// `code` formatted with `config`, after checking the invariants of the
+/// formatter on it.
+fn check(code: &str, config: &Config) -> String {
+    let formatted = crate::format(code, config).unwrap_or_else(|e| panic!("{}\n{}", e, code));
+    let again =
+        crate::format(&formatted, config).unwrap_or_else(|e| panic!("{}\n{}", e, formatted));
+    assert_eq!(again, formatted, "not idempotent, from:\n{}", code);
+    let docs = config.format_code_in_doc_comments;
+    let (before, after) = (tokens(code, docs), tokens(&formatted, docs));
+    let same = before.iter().zip(&after).take_while(|(a, b)| a == b).count();
+    assert!(
+        before == after,
+        "tokens changed after `{}`:\n{}",
+        before[same.saturating_sub(8)..same].join(" "),
+        formatted
+    );
+    assert_eq!(comments(&formatted), comments(code), "comments changed:\n{}", formatted);
+    formatted
+}
+
+/// The tokens of `code` but the `use` items and `mod` declarations, which
+/// are sorted and merged, and the commas and braces prettyplease adds and
+/// removes at the end of lists, around closure bodies and match arms. Doc
+/// attributes are the doc comments prettyplease prints them as. With `docs`,
+/// the code in doc comments is formatted, so each run of doc comments is one
+/// token without whitespace.
+fn tokens(code: &str, docs: bool) -> Vec<Cow<'_, str>> {
+    let raw: Vec<&str> = lex(code)
+        .into_iter()
+        .filter(|it| it.kind == Kind::Token)
+        .map(|it| &code[it.range])
+        .collect();
+    let mut tokens: Vec<Cow<str>> = Vec::new();
+    let mut is_import = false;
+    let mut prev_doc = None;
+    let mut idx = 0;
+    while idx < raw.len() {
+        let (token, len) = match raw[idx..] {
+            ["#", "[", "doc", "=", lit, "]", ..] if lit.starts_with('"') => {
+                (Cow::Owned(format!("///{}", lit.trim_matches('"').trim_end())), 6)
+            }
+            ["#", "!", "[", "doc", "=", lit, "]", ..] if lit.starts_with('"') => {
+                (Cow::Owned(format!("//!{}", lit.trim_matches('"').trim_end())), 7)
+            }
+            [token, ..] if token.starts_with("//") => (Cow::Borrowed(token.trim_end()), 1),
+            [token, ..] => (Cow::Borrowed(token), 1),
+            [] => unreachable!(),
+        };
+        idx += len;
+        let doc = ["///", "//!"].into_iter().find(|it| token.starts_with(it));
+        if let (true, Some(prefix)) = (docs, doc) {
+            let text: String = token[3..].chars().filter(|it| !it.is_whitespace()).collect();
+            match tokens.last_mut() {
+                Some(last) if prev_doc == doc => last.to_mut().push_str(&text),
+                _ => tokens.push(format!("{}{}", prefix, text).into()),
+            }
+            prev_doc = doc;
+            continue;
+        }
+        prev_doc = None;
+        let is_mod_decl = token == ";" && tokens.len() >= 2 && tokens[tokens.len() - 2] == "mod";
+        if is_mod_decl {
+            tokens.truncate(tokens.len() - 2);
+            pop_item_start(&mut tokens);
+        } else if is_import {
+            is_import = token != ";";
+        } else if token == "use" {
+            is_import = true;
+            // Its attributes are copied when it is split.
+            pop_item_start(&mut tokens);
+        } else {
+            tokens.push(token);
+        }
+    }
+    tokens.retain(|it| !matches!(it.as_ref(), "," | "{" | "}"));
+    tokens
+}
+
+/// Removes the visibility, outer attributes and doc comments of an item from
+/// the end of `tokens`.
+fn pop_item_start(tokens: &mut Vec<Cow<str>>) {
+    if tokens.last().is_some_and(|it| it == "pub") {
+        tokens.pop();
+    }
+    while let Some(last) = tokens.last() {
+        if last.starts_with("///") || last.starts_with("/**") {
+            tokens.pop();
+            continue;
+        }
+        if last != "]" {
+            return;
+        }
+        let mut depth = 0;
+        let open = tokens.iter().rposition(|it| {
+            match it.as_ref() {
+                "]" => depth += 1,
+                "[" => depth -= 1,
+                _ => {}
+            }
+            depth == 0
+        });
+        match open {
+            Some(open) if open > 0 && tokens[open - 1] == "#" => tokens.truncate(open - 1),
+            _ => return,
+        }
+    }
+}
+
+/// The comments of `code`, sorted since they move with the imports.
+fn comments(code: &str) -> Vec<&str> {
+    let mut res: Vec<&str> = lex(code)
+        .into_iter()
+        .filter(|it| it.is_comment())
+        .map(|it| code[it.range].trim_end())
+        .collect();
+    res.sort();
+    res
+}
+
+#[test]
+fn fixtures() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("test_data");
+    let update = std::env::var_os("UPDATE_EXPECT").is_some();
+    let mut paths: Vec<_> = fs::read_dir(&dir).unwrap().map(|it| it.unwrap().path()).collect();
+    paths.sort();
+    for path in paths {
+        let name = path.file_name().unwrap().to_str().unwrap();
+        if name.ends_with(".out.rs") {
+            continue;
+        }
+        let code = fs::read_to_string(&path).unwrap();
+        let formatted = check(&code, &Config::default());
+        let expected_path = path.with_extension("out.rs");
+        if update {
+            fs::write(&expected_path, &formatted).unwrap();
+            continue;
+        }
+        let expected = fs::read_to_string(&expected_path).unwrap_or_default();
+        assert_eq!(formatted, expected, "{} changed", name);
+    }
+}
+
+/// The code the editor opens with.
+#[test]
+fn shipped_example() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../www/example-code.rs");
+    check(&fs::read_to_string(path).unwrap(), &Config::default());
+}
+
+#[test]
+fn fixtures_with_options() {
+    let config =
+        Config::from_toml("max_width = 64\ntab_spaces = 2\nimports_granularity = \"Crate\"")
+            .unwrap();
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("test_data");
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if !path.to_str().unwrap().ends_with(".out.rs") {
+            check(&fs::read_to_string(path).unwrap(), &config);
+        }
+    }
+}
+
+/// Unknown options and values are errors.
+#[test]
+fn config_errors() {
+    let message = |toml: &str| match Config::from_toml(toml) {
+        Err(Error::Config { message }) => message,
+        it => panic!("{:?}", it),
+    };
+    assert_eq!(message("max_width = 0"), "Invalid rustfmt.toml: max_width must be at least 1");
+    assert!(message("use_small_heuristics = \"Small\"").contains("unknown variant `Small`"));
+    assert!(message("indent_style = \"Visual\"").contains("unknown field `indent_style`"));
+    assert_eq!(
+        Config::from_toml("max_width = 100\nuse_small_heuristics = \"Default\""),
+        Ok(Config::default())
+    );
+    for heuristics in ["Default", "Off", "Max"] {
+        let toml = format!("use_small_heuristics = \"{}\"", heuristics);
+        assert!(Config::from_toml(&toml).is_ok(), "{}", heuristics);
+    }
+}
+
+/// A line of 94 columns fits in the default `max_width`, not in 89, and one
+/// of 103 fits in 120.
+#[test]
+fn max_width() {
+    let code = "fn main() {
+    let value = call(first_argument, second_argument, third_argument, fourth_argument, fifth);
+}
+";
+    assert_eq!(crate::format(code, &Config::default()).unwrap(), code);
+    let config = Config::from_toml("max_width = 89").unwrap();
+    let formatted = crate::format(code, &config).unwrap();
+    assert_ne!(formatted, code);
+    assert!(formatted.lines().all(|it| it.len() <= 89), "{}", formatted);
+
+    let code = "fn main() {
+    let value = call(first_argument, second_argument, third_argument, fourth_argument, fifth_argument);
+}
+";
+    let config = Config::from_toml("max_width = 120").unwrap();
+    assert_eq!(crate::format(code, &config).unwrap(), code);
+    assert_ne!(crate::format(code, &Config::default()).unwrap(), code);
+}
+
+/// Comments are put back however many tokens prettyplease changes.
+#[test]
+fn many_changes() {
+    let calls: String = (0..1200).map(|it| format!("    f({},);\n    // {}\n", it, it)).collect();
+    let code = format!("fn main() {{\n{}}}\n", calls);
+    let formatted = check(&code, &Config::default());
+    assert!(formatted.contains("    f(1199);\n    // 1199\n}"), "{}", formatted);
+}
+
+#[test]
+fn doc_code_blocks() {
+    let code = r#"/// Adds.
+///
+/// ```
+/// # use soroban_sdk::Env;
+/// let env = Env::default();
+/// let x = add( 1,2 );
+/// # assert_eq!(x,3);
+/// ```
+///
+/// ```text
+/// let  a = 1;
+/// ```
+pub fn add(a: u32, b: u32) -> u32 {
+    a + b
+}
+
+mod m {
+    //! ```rust,no_run
+    //! # fn f() -> u32 {
+    //! #     let v = vec![1,2,3];
+    //! v.iter().map(|x| x * 2).fold(0, |a, b| a + b)
+    //! # }
+    //! struct S { a: u32 }
+    //! ```
+}
+"#;
+    let expected = r#"/// Adds.
+///
+/// ```
+/// # use soroban_sdk::Env;
+/// let env = Env::default();
+/// let x = add(1, 2);
+/// # assert_eq!(x, 3);
+/// ```
+///
+/// ```text
+/// let  a = 1;
+/// ```
+pub fn add(a: u32, b: u32) -> u32 {
+    a + b
+}
+
+mod m {
+    //! ```rust,no_run
+    //! # fn f() -> u32 {
+    //! #     let v = vec![1, 2, 3];
+    //!     v.iter().map(|x| x * 2).fold(0, |a, b| a + b)
+    //! # }
+    //! struct S {
+    //!     a: u32,
+    //! }
+    //! ```
+}
+"#;
+    let config = Config { format_code_in_doc_comments: true, ..Config::default() };
+    let formatted = crate::format(code, &config).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(crate::format(&formatted, &config).unwrap(), formatted);
+    assert_eq!(crate::format(code, &Config::default()).unwrap(), code);
+}
+
+fn ident() -> impl Strategy<Value = String> {
+    prop::sample::select(vec!["a", "b", "env", "value", "counter", "to"]).prop_map(String::from)
+}
+
+fn expr() -> impl Strategy<Value = String> {
+    let leaf = prop_oneof![
+        ident(),
+        (0u32..1000).prop_map(|it| it.to_string()),
+        "[a-z ]{0,12}".prop_map(|it| format!("{:?}", it)),
+    ];
+    leaf.prop_recursive(4, 48, 4, |inner| {
+        let args = prop::collection::vec(inner.clone(), 0..5).prop_map(|it| it.join(", "));
+        prop_oneof![
+            (inner.clone(), prop::sample::select(vec!["+", "*", "&&", "|"]), inner.clone())
+                .prop_map(|(a, op, b)| {
+                    // prettyplease parenthesizes a block-like left operand
+                    // in a closure body, then prints the parentheses in a
+                    // block the next time. A closure would take `op b`.
+                    let is_block_like =
+                        ["if", "match", "{", "|"].iter().any(|it| a.starts_with(it));
+                    let a = if is_block_like { format!("({})", a) } else { a };
+                    format!("{} {} {}", a, op, b)
+                }),
+            (ident(), args.clone()).prop_map(|(f, args)| format!("{}({})", f, args)),
+            (inner.clone(), ident(), args.clone())
+                .prop_map(|(a, m, args)| format!("({}).{}({})", a, m, args)),
+            args.clone().prop_map(|args| format!("vec![&env, {}]", args)),
+            args.clone().prop_map(|args| format!("[{}]", args)),
+            (inner.clone(), inner.clone(), inner.clone())
+                .prop_map(|(c, a, b)| format!("if ({}) {{ {} }} else {{ {} }}", c, a, b)),
+            (inner.clone(), inner.clone(), inner.clone())
+                .prop_map(|(c, a, b)| format!("match ({}) {{ 0 => {}, _ => {{ {} }} }}", c, a, b)),
+            (ident(), inner.clone()).prop_map(|(x, a)| format!("{{ let {} = {}; {} }}", x, a, x)),
+            (ident(), inner.clone()).prop_map(|(x, a)| format!("|{}| {}", x, a)),
+            inner.prop_map(|a| format!("&({})", a)),
+        ]
+    })
+}
+
+/// `line` with comments, maybe, before it and at its end.
+fn commented(line: impl Strategy<Value = String>) -> impl Strategy<Value = String> {
+    (any::<bool>(), any::<bool>(), any::<bool>(), line).prop_map(|(before, after, blank, line)| {
+        let mut res = String::new();
+        if blank {
+            res.push('\n');
+        }
+        if before {
+            res.push_str("// before\n");
+        }
+        res.push_str(&line);
+        if after {
+            res.push_str(" // after");
+        }
+        res
+    })
+}
+
+/// A `use` tree below a path, with globs, renames, `self` and nested groups.
+fn use_tree() -> impl Strategy<Value = String> {
+    let leaf = prop_oneof![
+        ident(),
+        (ident(), ident()).prop_map(|(a, b)| format!("{} as {}", a, b)),
+        Just("*".to_string()),
+    ];
+    leaf.prop_recursive(3, 12, 4, |inner| {
+        let group =
+            prop::collection::vec(prop_oneof![inner.clone(), Just("self".to_string())], 1..4);
+        prop_oneof![
+            (ident(), inner).prop_map(|(a, tree)| format!("{}::{}", a, tree)),
+            group.prop_map(|it| format!("{{{}}}", it.join(", "))),
+        ]
+    })
+}
+
+fn use_item() -> impl Strategy<Value = String> {
+    let root = prop::sample::select(vec!["std", "core", "crate", "soroban_sdk", "super", "self"]);
+    (any::<bool>(), root, use_tree())
+        .prop_map(|(is_pub, root, tree)| format!("{}use {}::{};", pub_(is_pub), root, tree))
+}
+
+fn pub_(is_pub: bool) -> &'static str {
+    if is_pub {
+        "pub "
+    } else {
+        ""
+    }
+}
+
+/// Inner or outer doc comments, as lines or a block, maybe with code blocks.
+fn doc(inner: bool) -> impl Strategy<Value = String> {
+    let paragraph = prop_oneof![
+        "[a-z ]{0,20}".prop_map(|it| it.trim_end().to_string()),
+        Just("```\nlet x = add( 1,2 );\n# assert_eq!(x,3);\n```".to_string()),
+        Just("```text\nlet  a = 1;\n```".to_string()),
+    ];
+    (prop::collection::vec(paragraph, 1..3), any::<bool>()).prop_map(move |(text, is_block)| {
+        let text = text.join("\n\n");
+        let (line, block) = if inner { ("//!", "/*!") } else { ("///", "/**") };
+        if is_block {
+            return format!("{}\n{}\n*/", block, text);
+        }
+        let lines: Vec<_> =
+            text.lines().map(|it| format!("{} {}", line, it).trim_end().to_string()).collect();
+        lines.join("\n")
+    })
+}
+
+fn attrs() -> impl Strategy<Value = String> {
+    let attr = prop_oneof![
+        doc(false),
+        Just("#[derive(Clone, Debug)]".to_string()),
+        Just("#[cfg(test)]".to_string()),
+        Just("#[cfg_attr(feature = \"testutils\", derive(Debug))]".to_string()),
+        Just("#[allow(dead_code, clippy::too_many_arguments)]".to_string()),
+        "[a-z ]{0,12}".prop_map(|it| format!("#[doc = {:?}]", it)),
+    ];
+    prop::collection::vec(attr, 0..3)
+        .prop_map(|it| it.iter().map(|it| format!("{}\n", it)).collect())
+}
+
+/// Macro calls as items and statements, with each delimiter.
+fn macro_call() -> impl Strategy<Value = String> {
+    let args = prop::collection::vec(expr(), 0..4).prop_map(|it| it.join(", "));
+    let name = prop::sample::select(vec!["println", "assert_eq", "m", "symbol_short"]);
+    (name, args, prop::sample::select(vec![("(", ")"), ("[", "]"), ("{", "}")])).prop_map(
+        |(name, args, (open, close))| {
+            let semicolon = if open == "{" { "" } else { ";" };
+            format!("{}!{}{}{}{}", name, open, args, close, semicolon)
+        },
+    )
+}
+
+fn stmts() -> impl Strategy<Value = String> {
+    let stmt = prop_oneof![
+        3 => (ident(), expr()).prop_map(|(x, e)| format!("let {} = {};", x, e)),
+        1 => macro_call(),
+        1 => use_item(),
+    ];
+    prop::collection::vec(commented(stmt), 0..5).prop_map(|it| it.join("\n"))
+}
+
+fn item() -> impl Strategy<Value = String> {
+    let fields = prop::collection::vec((ident(), any::<bool>()), 0..4).prop_map(|fields| {
+        let fields: Vec<String> = fields
+            .iter()
+            .enumerate()
+            .map(|(idx, (name, is_pub))| format!("{}{}{}: u32", pub_(*is_pub), name, idx))
+            .collect();
+        fields.join(", ")
+    });
+    let arm =
+        (ident(), expr()).prop_map(|(x, e)| format!("(${}:expr) => {{ ({}) + ${} }};", x, e, x));
+    let item = prop_oneof![
+        (ident(), stmts()).prop_map(|(name, stmts)| format!("fn {}() {{\n{}\n}}", name, stmts)),
+        (ident(), fields).prop_map(|(name, fields)| format!("struct S{} {{ {} }}", name, fields)),
+        use_item(),
+        (ident(), expr()).prop_map(|(name, e)| format!("const C{}: u32 = {};", name, e)),
+        (ident(), stmts()).prop_map(|(name, stmts)| {
+            format!("impl T {{\npub fn {}(&self) {{\n{}\n}}\n}}", name, stmts)
+        }),
+        prop::collection::vec(arm, 1..3)
+            .prop_map(|arms| format!("macro_rules! m {{ {} }}", arms.join(" "))),
+        macro_call(),
+        (any::<bool>(), ident()).prop_map(|(p, name)| format!("{}mod {};", pub_(p), name)),
+    ];
+    (attrs(), item).prop_map(|(attrs, item)| attrs + &item)
+}
+
+fn file() -> impl Strategy<Value = String> {
+    let inner = prop_oneof![
+        doc(true),
+        Just("#![no_std]".to_string()),
+        Just("#![allow(unused)]".to_string()),
+    ];
+    let items = || prop::collection::vec(commented(item()), 0..6).prop_map(|it| it.join("\n"));
+    let module = (ident(), items(), doc(true))
+        .prop_map(|(name, items, doc)| format!("mod m{} {{\n{}\n{}\n}}", name, doc, items));
+    (prop::collection::vec(inner, 0..2), items(), prop::option::of(module)).prop_map(
+        |(inner, items, module)| {
+            let mut res = inner.join("\n");
+            res.push('\n');
+            res.push_str(&items);
+            if let Some(module) = module {
+                res.push('\n');
+                res.push_str(&module);
+            }
+            res + "\n"
+        },
+    )
+}
+
+fn config() -> impl Strategy<Value = Config> {
+    let granularity = prop::sample::select(vec![
+        ImportsGranularity::Preserve,
+        ImportsGranularity::Crate,
+        ImportsGranularity::Module,
+        ImportsGranularity::Item,
+        ImportsGranularity::One,
+    ]);
+    let group_imports = prop::sample::select(vec![
+        GroupImports::Preserve,
+        GroupImports::StdExternalCrate,
+        GroupImports::One,
+    ]);
+    let width = prop::sample::select(vec![61usize, 80, 100, 120]);
+    let layout = (width, 1usize..5, any::<bool>());
+    let imports = (granularity, group_imports, any::<bool>(), any::<bool>());
+    (layout, imports, any::<bool>()).prop_map(
+        |(
+            (max_width, tab_spaces, hard_tabs),
+            (imports_granularity, group_imports, reorder_imports, reorder_modules),
+            format_code_in_doc_comments,
+        )| Config {
+            max_width,
+            tab_spaces,
+            hard_tabs,
+            imports_granularity,
+            group_imports,
+            reorder_imports,
+            reorder_modules,
+            format_code_in_doc_comments,
+            ..Config::default()
+        },
+    )
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(128))]
+
+    #[test]
+    fn random_files(code in file(), config in config()) {
+        check(&code, &config);
+    }
+
+    #[test]
+    fn align_is_shortest(
+        a in prop::collection::vec(prop::sample::select(vec!["a", "b", "c"]), 0..40),
+        b in prop::collection::vec(prop::sample::select(vec!["a", "b", "c"]), 0..40),
+    ) {
+        let pairs = diff::align(&a, &b);
+        for window in pairs.windows(2) {
+            prop_assert!(window[0].0 < window[1].0 && window[0].1 < window[1].1);
+        }
+        for &(x, y) in &pairs {
+            prop_assert_eq!(a[x], b[y]);
+        }
+        let shortest = diff::matches(&a, &b, a.len() + b.len()).unwrap();
+        prop_assert_eq!(pairs.len(), shortest.len());
+    }
+}
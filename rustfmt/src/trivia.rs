@@ -0,0 +1,268 @@
+//! Puts the comments and blank lines of the original code back into the
+//! output of prettyplease, which only prints the syntax tree.
+//!
+//! The tokens of both texts are aligned with a diff, prettyplease only adds
+//! and removes a few commas and semicolons. A comment ending the line of a
+//! token goes back after it, other comments go before the next token.
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use crate::diff::align;
+use crate::lexer::{lex, Kind, Lexeme};
+
+enum Placement {
+    /// At the end of the line of a token.
+    Trailing(usize),
+    /// Just before a token, on the same line.
+    Inline(usize),
+    /// On its own line before a token, or at the end of the file.
+    OwnLine(Option<usize>),
+}
+
+/// `formatted` with the comments of `original`, an error if they couldn't all
+/// be put back.
+pub(crate) fn reattach(original: &str, formatted: &str) -> Result<String, String> {
+    let orig = lex(original);
+    let fmt = lex(formatted);
+    let orig_units = units(original, &orig);
+    let fmt_units = units(formatted, &fmt);
+    let orig_keys: Vec<&str> = orig_units.iter().map(|(_, key)| *key).collect();
+    let fmt_keys: Vec<&str> = fmt_units.iter().map(|(_, key)| *key).collect();
+
+    let pairs = align(&orig_keys, &fmt_keys);
+    let mut map = vec![None; orig_units.len()];
+    let mut is_matched = vec![false; fmt_units.len()];
+    for (a, b) in pairs {
+        map[a] = Some(b);
+        is_matched[b] = true;
+    }
+    let mapped_before = |unit: usize| (0..=unit).rev().find_map(|it| map[it]);
+    let mapped_after = |unit: usize| (unit..map.len()).find_map(|it| map[it]);
+
+    let mut placements = Vec::new();
+    let mut blank_before = HashSet::new();
+    for (idx, lexeme) in orig.iter().enumerate() {
+        let prev_end = if idx == 0 { 0 } else { orig[idx - 1].range.end };
+        let gap_before = &original[prev_end..lexeme.range.start];
+        let is_blank_before = idx > 0 && gap_before.matches('\n').count() >= 2;
+        let seen_units = orig_units.partition_point(|(range, _)| range.start < lexeme.range.start);
+        if !lexeme.is_comment() {
+            let starts_unit = orig_units
+                .get(seen_units)
+                .is_some_and(|(range, _)| range.start == lexeme.range.start);
+            if is_blank_before && starts_unit {
+                if let Some(it) = map[seen_units] {
+                    blank_before.insert(it);
+                }
+            }
+            continue;
+        }
+        let next_start = orig.get(idx + 1).map_or(original.len(), |it| it.range.start);
+        let newline_before = idx == 0 || gap_before.contains('\n');
+        let newline_after = lexeme.kind == Kind::LineComment
+            || next_start == original.len()
+            || original[lexeme.range.end..next_start].contains('\n');
+        let prev = if seen_units == 0 { None } else { mapped_before(seen_units - 1) };
+        let next = mapped_after(seen_units);
+        let placement = match (prev, next) {
+            (Some(prev), _) if !newline_before && newline_after => Placement::Trailing(prev),
+            (_, Some(next)) if !newline_after => {
+                // The blank line before the comment is before the token's line.
+                if is_blank_before && newline_before {
+                    blank_before.insert(next);
+                }
+                Placement::Inline(next)
+            }
+            (Some(prev), None) if !newline_before => Placement::Trailing(prev),
+            _ => Placement::OwnLine(next),
+        };
+        placements.push((placement, &original[lexeme.range.clone()], is_blank_before));
+    }
+
+    let mut edits: Vec<(usize, usize, String)> = Vec::new();
+    let mut own_lines: HashMap<Option<usize>, Vec<(&str, bool)>> = HashMap::new();
+    for (placement, comment, is_blank_before) in placements {
+        match placement {
+            Placement::Trailing(token) => {
+                // After the commas prettyplease added after the token.
+                let mut token = token;
+                while token + 1 < fmt_units.len()
+                    && !is_matched[token + 1]
+                    && matches!(fmt_keys[token + 1], "," | ";")
+                    && !formatted[fmt_units[token].0.end..fmt_units[token + 1].0.start]
+                        .contains('\n')
+                {
+                    token += 1;
+                }
+                let end = fmt_units[token].0.end;
+                let line_end = formatted[end..].find('\n').map_or(formatted.len(), |it| end + it);
+                let rest = &formatted[end..line_end];
+                if rest.trim().is_empty() || !comment.starts_with("//") {
+                    edits.push((end, end, format!(" {}", comment)));
+                } else {
+                    // The rest of the line goes on the next one.
+                    let indent = format!("{}    ", indent_of(formatted, end));
+                    let spaces = rest.len() - rest.trim_start().len();
+                    edits.push((end, end + spaces, format!(" {}\n{}", comment, indent)));
+                }
+            }
+            Placement::Inline(token) => {
+                let start = fmt_units[token].0.start;
+                let space = match formatted[..start].chars().next_back() {
+                    Some(c) if !c.is_whitespace() && !matches!(c, '(' | '[') => " ",
+                    _ => "",
+                };
+                edits.push((start, start, format!("{}{} ", space, comment)));
+            }
+            Placement::OwnLine(token) => {
+                own_lines.entry(token).or_default().push((comment, is_blank_before));
+            }
+        }
+    }
+
+    for (token, (range, _)) in fmt_units.iter().enumerate() {
+        let comments = own_lines.remove(&Some(token)).unwrap_or_default();
+        let is_blank_before = blank_before.contains(&token);
+        if comments.is_empty() && !is_blank_before {
+            continue;
+        }
+        let start = range.start;
+        let line_start = formatted[..start].rfind('\n').map_or(0, |it| it + 1);
+        let prefix = &formatted[line_start..start];
+        if prefix.trim().is_empty() {
+            let opens_block = opens_block(&formatted[..line_start]);
+            // The comments before a closing delimiter are inside.
+            let indent = match fmt_keys[token] {
+                "}" | ")" | "]" => format!("{}    ", prefix),
+                _ => prefix.to_string(),
+            };
+            let mut text = String::new();
+            for (idx, (comment, is_blank_before)) in comments.iter().enumerate() {
+                if *is_blank_before && !(idx == 0 && opens_block) {
+                    text.push('\n');
+                }
+                text.push_str(&indent);
+                text.push_str(comment);
+                text.push('\n');
+            }
+            if is_blank_before && !(comments.is_empty() && opens_block) {
+                text.push('\n');
+            }
+            edits.push((line_start, line_start, text));
+        } else if !comments.is_empty() {
+            // prettyplease joined the lines the comments were between.
+            let line_indent = indent_of(formatted, start);
+            let indent = format!("{}    ", line_indent);
+            let mut text = String::from("\n");
+            for (comment, _) in &comments {
+                text.push_str(&indent);
+                text.push_str(comment);
+                text.push('\n');
+            }
+            // Like the comments in `{}`, before a closing delimiter.
+            if matches!(fmt_keys[token], "}" | ")" | "]") {
+                text.push_str(line_indent);
+            } else {
+                text.push_str(&indent);
+            }
+            let spaces = prefix.len() - prefix.trim_end().len();
+            edits.push((start - spaces, start, text));
+        }
+    }
+    if let Some(comments) = own_lines.remove(&None) {
+        let mut text = String::new();
+        if !formatted.is_empty() && !formatted.ends_with('\n') {
+            text.push('\n');
+        }
+        for (idx, (comment, is_blank_before)) in comments.iter().enumerate() {
+            if *is_blank_before && !(idx == 0 && opens_block(formatted)) {
+                text.push('\n');
+            }
+            text.push_str(comment);
+            text.push('\n');
+        }
+        edits.push((formatted.len(), formatted.len(), text));
+    }
+
+    let res = apply(formatted, edits);
+    check(original, &orig, formatted, &fmt, &res)?;
+    Ok(res)
+}
+
+/// The tokens of `text` to align, with doc comments and `#[doc = "…"]`
+/// attributes as one unit each, as prettyplease prints the doc comments of
+/// macro arguments as attributes.
+fn units<'a>(text: &'a str, lexemes: &[Lexeme]) -> Vec<(Range<usize>, &'a str)> {
+    let tokens: Vec<_> = lexemes.iter().filter(|it| !it.is_comment()).collect();
+    let mut res = Vec::new();
+    let mut idx = 0;
+    while idx < tokens.len() {
+        let token = &text[tokens[idx].range.clone()];
+        let texts: Vec<_> =
+            tokens[idx..].iter().take(7).map(|it| &text[it.range.clone()]).collect();
+        let (len, key) = match texts.as_slice() {
+            _ if token.starts_with("//!") || token.starts_with("/*!") => (1, "#![doc]"),
+            _ if token.starts_with("//") || token.starts_with("/*") => (1, "#[doc]"),
+            ["#", "[", "doc", "=", lit, "]", ..] if lit.ends_with('"') => (6, "#[doc]"),
+            ["#", "!", "[", "doc", "=", lit, "]"] if lit.ends_with('"') => (7, "#![doc]"),
+            _ => (1, token),
+        };
+        res.push((tokens[idx].range.start..tokens[idx + len - 1].range.end, key));
+        idx += len;
+    }
+    res
+}
+
+/// Whether the text before a line ends a line opening a block, or an empty
+/// line, where a blank line would be one too many.
+fn opens_block(before: &str) -> bool {
+    before.is_empty() || before.ends_with("\n\n") || before.trim_end().ends_with(['{', '(', '['])
+}
+
+/// The leading whitespace of the line of `offset`.
+fn indent_of(text: &str, offset: usize) -> &str {
+    let line_start = text[..offset].rfind('\n').map_or(0, |it| it + 1);
+    let line = &text[line_start..];
+    &line[..line.len() - line.trim_start().len()]
+}
+
+fn apply(text: &str, mut edits: Vec<(usize, usize, String)>) -> String {
+    edits.sort_by_key(|(start, _, _)| *start);
+    let mut res = String::with_capacity(text.len());
+    let mut cursor = 0;
+    for (start, end, insert) in edits {
+        let start = start.max(cursor);
+        res.push_str(&text[cursor..start]);
+        res.push_str(&insert);
+        cursor = end.max(start);
+    }
+    res.push_str(&text[cursor..]);
+    res
+}
+
+/// Makes sure the comments went back in without changing the tokens of the
+/// formatted text.
+fn check(
+    original: &str,
+    orig: &[Lexeme],
+    formatted: &str,
+    fmt: &[Lexeme],
+    res: &str,
+) -> Result<(), String> {
+    let texts = |text: &'_ str, lexemes: &[Lexeme], comments: bool| -> Vec<String> {
+        lexemes
+            .iter()
+            .filter(|it| it.is_comment() == comments)
+            .map(|it| text[it.range.clone()].to_string())
+            .collect()
+    };
+    let lexemes = lex(res);
+    let mut comments = texts(res, &lexemes, true);
+    let mut expected = texts(original, orig, true);
+    comments.sort();
+    expected.sort();
+    if texts(res, &lexemes, false) != texts(formatted, fmt, false) || comments != expected {
+        return Err("Formatting would lose comments".to_string());
+    }
+    Ok(())
+}
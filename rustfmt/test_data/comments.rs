@@ -0,0 +1,31 @@
+//! Comments and blank lines in the places they usually are.
+
+// The first item.
+fn first() {}
+
+
+fn second() {
+    let a = 1; // one
+
+    /* block */ let b = 2;
+
+
+    // Before the end.
+}
+
+struct Point {
+    // The x coordinate.
+    x: i32, // x
+    y: i32,
+}
+
+impl Point {
+    fn new() -> Self { Self { x: 0, y: 0 } }
+}
+fn third() {
+    if true {
+        first();
+    }
+    second();
+}
+// At the end.
@@ -0,0 +1,14 @@
+use super::a;
+use crate::b::c;
+// Keeps its comment.
+use soroban_sdk::{contract, Address, Env};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+mod alpha;
+mod zeta;
+
+fn f() {
+    use core::mem;
+    use std::io;
+}
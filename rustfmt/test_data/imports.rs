@@ -0,0 +1,14 @@
+use crate::b::c;
+use std::fmt;
+// Keeps its comment.
+use soroban_sdk::{Env, Address, contract};
+use super::a;
+use std::collections::{HashMap, BTreeMap};
+
+mod zeta;
+mod alpha;
+
+fn f() {
+    use std::io;
+    use core::mem;
+}
@@ -0,0 +1,96 @@
+#![no_std]
+use core::cmp::Ordering;
+use soroban_sdk::{
+    contract, contracterror, contractimpl, contracttype, map, panic_with_error, symbol_short, vec,
+    Address, Env, Map, String, Symbol, Vec,
+};
+
+#[contracterror]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[repr(u32)]
+pub enum Error {
+    InsufficientBalance = 1,
+    NegativeAmount = 2,
+    /* Not yet */
+    NotInitialized = 3,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub enum DataKey {
+    Admin,
+    Balance(Address),
+    Allowance(AllowanceKey),
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct AllowanceKey {
+    pub from: Address,
+    pub spender: Address,
+}
+
+macro_rules! check_nonnegative {
+    ($env:expr, $amount:expr) => {
+        if $amount < 0 {
+            panic_with_error!($env, Error::NegativeAmount)
+        }
+    };
+}
+
+fn read_balance(e: &Env, id: Address) -> i128 {
+    let key = DataKey::Balance(id);
+    if let Some(balance) = e.storage().persistent().get::<DataKey, i128>(&key) {
+        balance
+    } else {
+        0
+    }
+}
+
+fn write_balance(e: &Env, id: Address, amount: i128) {
+    let key = DataKey::Balance(id);
+    e.storage().persistent().set(&key, &amount);
+}
+
+#[contract]
+pub struct Token;
+
+#[contractimpl]
+impl Token {
+    pub fn initialize(e: Env, admin: Address, decimal: u32, name: String, symbol: String) {
+        if e.storage().instance().has(&DataKey::Admin) {
+            panic!("already initialized")
+        }
+        e.storage().instance().set(&DataKey::Admin, &admin);
+        let metadata: Map<Symbol, String> = map![
+            &e,
+            (symbol_short!("name"), name),
+            (symbol_short!("symbol"), symbol)
+        ];
+        e.storage().instance().set(&symbol_short!("METADATA"), &metadata);
+        let _ = decimal;
+    }
+
+    pub fn transfer(e: Env, from: Address, to: Address, amount: i128) {
+        from.require_auth();
+        check_nonnegative!(&e, amount);
+        let balance = read_balance(&e, from.clone());
+        match balance.cmp(&amount) {
+            Ordering::Less => panic_with_error!(&e, Error::InsufficientBalance),
+            _ => {}
+        }
+        write_balance(&e, from.clone(), balance - amount);
+        write_balance(&e, to.clone(), read_balance(&e, to.clone()) + amount);
+        e.events().publish((symbol_short!("transfer"), from, to), amount);
+    }
+
+    pub fn holders(e: Env) -> Vec<Symbol> {
+        vec![
+            &e,
+            symbol_short!("alice"),
+            symbol_short!("bob"),
+            symbol_short!("carol"),
+            symbol_short!("dave")
+        ]
+    }
+}
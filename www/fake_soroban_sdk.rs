@@ -1,3 +1,9 @@
+// The macros, at the crate root like the rest of the SDK's items.
+pub use soroban_sdk_macros::{
+    contract, contractclient, contracterror, contractevent, contractimpl, contractimport,
+    contractmeta, contractspecfn, contracttype, symbol_short,
+};
+
 //! # Migrating from v21 to v22
 //!
 //! 1. [`Env::register`] and [`Env::register_at`] replace [`Env::register_contract`] and [`Env::register_contract_wasm`].
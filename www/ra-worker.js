@@ -9,9 +9,39 @@ const start = async () => {
 
 	const state = new WorldState();
 
+	// The caches are primed in the background once loaded, the progress is
+	// polled between requests and sent when it changes.
+	let progressTimer;
+	let progressDone;
+	const pollProgress = () => {
+		const progress = state.prime_progress();
+		if (!progress) return;
+		if (progress.done !== progressDone) {
+			progressDone = progress.done;
+			postMessage({ id: "ra-progress", progress });
+		}
+		if (progress.done < progress.total) {
+			progressTimer = setTimeout(pollProgress, 100);
+		}
+	};
+
 	onmessage = (e) => {
 		const { which, args, id } = e.data;
-		const result = state[which](...args);
+		let result;
+		try {
+			result = state[which](...args);
+			if (which === "init" || which === "init_bundle") {
+				clearTimeout(progressTimer);
+				progressDone = undefined;
+				pollProgress();
+			}
+		} catch (error) {
+			postMessage({
+				id: id,
+				error: String(error),
+			});
+			return;
+		}
 
 		postMessage({
 			id: id,
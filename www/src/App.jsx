@@ -1,15 +1,53 @@
 import { useCallback, useEffect, useRef, useState } from "react";
-import { start } from "./utils/worker";
+import * as monaco from "monaco-editor/esm/vs/editor/editor.api";
+import { modeId, start } from "./utils/worker";
 import { Panel, PanelGroup, PanelResizeHandle } from "react-resizable-panels";
-import init, { format_rust_code } from "../../rustfmt/pkg/rustfmt_wasm.js";
+import init, {
+    format_rust_code_best_effort,
+    format_rust_code_edits,
+    format_rust_code_range,
+} from "../../rustfmt/pkg/rustfmt_wasm.js";
 
 export default function App() {
     const [editor, setEditor] = useState(null);
     const [loading, setLoading] = useState(true);
+    const [progress, setProgress] = useState({
+        label: "Loading",
+        done: 0,
+        total: 0,
+    });
     const [editorContent, setEditorContent] = useState("");
     const monacoElementRef = useRef(null);
     const [monacoEditor, setMonacoEditor] = useState(null);
 
+    useEffect(() => {
+        // Only called once the editor is shown, after `init`
+        const providers = [
+            monaco.languages.registerDocumentFormattingEditProvider(modeId, {
+                provideDocumentFormattingEdits(m) {
+                    return format_rust_code_edits(m.getValue(), "");
+                },
+            }),
+            monaco.languages.registerDocumentRangeFormattingEditProvider(
+                modeId,
+                {
+                    provideDocumentRangeFormattingEdits(m, range) {
+                        return format_rust_code_range(
+                            m.getValue(),
+                            "",
+                            range.startLineNumber,
+                            range.startColumn,
+                            range.endLineNumber,
+                            range.endColumn
+                        );
+                    },
+                }
+            ),
+        ];
+
+        return () => providers.forEach((provider) => provider.dispose());
+    }, []);
+
     useEffect(() => {
         if (monacoElementRef) {
             setEditor(async (editor) => {
@@ -19,7 +57,8 @@ export default function App() {
 
                 const { myEditor, model } = await start(
                     monacoElementRef,
-                    setEditorContent
+                    setEditorContent,
+                    setProgress
                 );
                 setLoading(false);
                 setMonacoEditor(model);
@@ -40,12 +79,37 @@ export default function App() {
         console.log("Formatting code...");
 
         if (monacoEditor) {
-            const formattedCode = format_rust_code(editorContent);
+            // The items that don't parse are left as they are
+            const { edits, errors } = format_rust_code_best_effort(
+                editorContent,
+                ""
+            );
 
-            monacoEditor.setValue(formattedCode);
+            // Only the changed parts, the cursor and the undo history stay
+            monacoEditor.pushEditOperations([], edits, () => null);
+            monaco.editor.setModelMarkers(
+                monacoEditor,
+                "rustfmt",
+                errors.map(({ message, range }) => ({
+                    ...range,
+                    message,
+                    severity: monaco.MarkerSeverity.Error,
+                }))
+            );
         }
     }, [editorContent, monacoEditor]);
 
+    useEffect(() => {
+        if (!monacoEditor) return;
+
+        // The errors are those of the last format, gone once the code changes
+        const listener = monacoEditor.onDidChangeContent(() =>
+            monaco.editor.setModelMarkers(monacoEditor, "rustfmt", [])
+        );
+
+        return () => listener.dispose();
+    }, [monacoEditor]);
+
     useEffect(() => {
         const handleKeyPress = (event) => {
             if (
@@ -79,11 +143,38 @@ export default function App() {
                                 ref={monacoElementRef}
                             >
                                 {loading ? (
-                                    <div className="w-full h-full flex items-center justify-center bg-[#1e1e1e]">
-                                        <div className="border-4 border-t-4 border-gray-200 border-t-blue-500 rounded-full w-16 h-16 animate-spin" />
+                                    <div className="w-full h-full flex flex-col items-center justify-center gap-2 bg-[#1e1e1e]">
+                                        <div className="text-sm text-gray-300">
+                                            {progress.label}
+                                            {progress.total > 0 &&
+                                                ` (${progress.done}/${progress.total})`}
+                                        </div>
+                                        <div className="w-64 h-2 rounded bg-gray-700 overflow-hidden">
+                                            {progress.total > 0 ? (
+                                                <div
+                                                    className="h-full bg-blue-500 transition-all"
+                                                    style={{
+                                                        width: `${
+                                                            (100 *
+                                                                progress.done) /
+                                                            progress.total
+                                                        }%`,
+                                                    }}
+                                                />
+                                            ) : (
+                                                <div className="h-full w-1/3 bg-blue-500 animate-pulse" />
+                                            )}
+                                        </div>
                                     </div>
                                 ) : (
                                     <>
+                                        {/* Libraries are indexed in the background */}
+                                        {progress.done < progress.total && (
+                                            <div className="text-xs text-gray-400 absolute bottom-2 left-4 z-50 pointer-events-none">
+                                                {progress.label}
+                                                {` (${progress.done}/${progress.total})`}
+                                            </div>
+                                        )}
                                         <button
                                             disabled={loading}
                                             onClick={handleCompile}
@@ -59,10 +59,55 @@ import htmlWorker from "monaco-editor/esm/vs/language/html/html.worker?worker";
 import tsWorker from "monaco-editor/esm/vs/language/typescript/ts.worker?worker";
 import { conf, grammar } from "../../rust-grammar";
 import exampleCode from "../../example-code.rs?raw";
-import fake_std from "../../fake_std.rs?raw";
-import fake_core from "../../fake_core.rs?raw";
-import fake_alloc from "../../fake_alloc.rs?raw";
-import fake_soroban_sdk from "../../fake_soroban_sdk.rs?raw";
+
+// The compressed bundle emitted by `rust-pack`, copied to `public/`.
+const bundleUrl = `${import.meta.env.BASE_URL}bundle.bin`;
+
+// Bundle of the hand-packed libraries, in the format emitted by `rust-pack`,
+// used when there is no `bundle.bin`.
+const fakeCfg = ["unix", 'target_arch="x86_64"', 'target_pointer_width="64"'];
+const fakeCrate = (name, source, deps, extra = {}) => ({
+    id: name,
+    name,
+    edition: "2018",
+    cfg: fakeCfg,
+    features: [],
+    deps: deps.map((dep) => ({ crate: dep, name: dep })),
+    source,
+    root: true,
+    ...extra,
+});
+const fakeBundle = async () => {
+    // Separate chunks, only downloaded without a `bundle.bin`.
+    const [core, alloc, std, soroban_sdk] = await Promise.all([
+        import("../../fake_core.rs?raw"),
+        import("../../fake_alloc.rs?raw"),
+        import("../../fake_std.rs?raw"),
+        import("../../fake_soroban_sdk.rs?raw"),
+    ]);
+    return [
+        fakeCrate("core", core.default, []),
+        fakeCrate("alloc", alloc.default, ["core"]),
+        fakeCrate("std", std.default, ["core", "alloc"]),
+        fakeCrate("soroban_sdk_macros", "", [], {
+            proc_macro: true,
+            root: false,
+        }),
+        fakeCrate("soroban_sdk", soroban_sdk.default, ["soroban_sdk_macros"]),
+    ];
+};
+
+const fetchBundle = async () => {
+    try {
+        const res = await fetch(bundleUrl);
+        if (res.ok) {
+            return new Uint8Array(await res.arrayBuffer());
+        }
+    } catch (e) {
+        console.warn(`Can't fetch ${bundleUrl}:`, e);
+    }
+    return null;
+};
 
 self.MonacoEnvironment = {
     getWorker: (_, label) => {
@@ -96,6 +141,35 @@ monaco.languages.onLanguage(modeId, async () => {
     monaco.languages.setMonarchTokensProvider(modeId, grammar);
 });
 
+// Read-only views of library files, which rust-analyzer providers don't apply to.
+const libraryModeId = "rust-library";
+
+monaco.languages.register({
+    id: libraryModeId,
+});
+
+monaco.languages.onLanguage(libraryModeId, async () => {
+    monaco.languages.setLanguageConfiguration(libraryModeId, conf);
+    monaco.languages.setMonarchTokensProvider(libraryModeId, grammar);
+});
+
+// Links to library files come with their original path and the packed text of
+// the target, shown at its original line.
+const toLocation = (m, def) => {
+    if (!def.path) {
+        return { ...def, uri: m.uri };
+    }
+    const uri = monaco.Uri.file(def.path);
+    const text = "\n".repeat(def.range.startLineNumber - 1) + def.source;
+    const model = monaco.editor.getModel(uri);
+    if (model) {
+        model.setValue(text);
+    } else {
+        monaco.editor.createModel(text, libraryModeId, uri);
+    }
+    return { ...def, uri };
+};
+
 let state;
 // eslint-disable-next-line no-unused-vars
 let allTokens;
@@ -107,6 +181,7 @@ export const registerRA = async () => {
     monaco.languages.registerCodeLensProvider(modeId, {
         async provideCodeLenses(m) {
             const code_lenses = await state.code_lenses();
+            if (!code_lenses) return null;
             const lenses = code_lenses.map(({ range, command }) => {
                 const position = {
                     column: range.startColumn,
@@ -148,6 +223,7 @@ export const registerRA = async () => {
     monaco.languages.registerInlayHintsProvider(modeId, {
         async provideInlayHints() {
             let hints = await state.inlay_hints();
+            if (!hints) return [];
             return hints.map((hint) => {
                 if (hint.hint_type == 1) {
                     return {
@@ -229,7 +305,7 @@ export const registerRA = async () => {
         async provideDefinition(m, pos) {
             const list = await state.definition(pos.lineNumber, pos.column);
             if (list) {
-                return list.map((def) => ({ ...def, uri: m.uri }));
+                return list.map((def) => toLocation(m, def));
             }
         },
     });
@@ -240,7 +316,7 @@ export const registerRA = async () => {
                 pos.column
             );
             if (list) {
-                return list.map((def) => ({ ...def, uri: m.uri }));
+                return list.map((def) => toLocation(m, def));
             }
         },
     });
@@ -251,7 +327,7 @@ export const registerRA = async () => {
                 pos.column
             );
             if (list) {
-                return list.map((def) => ({ ...def, uri: m.uri }));
+                return list.map((def) => toLocation(m, def));
             }
         },
     });
@@ -266,6 +342,33 @@ export const registerRA = async () => {
             return await state.type_formatting(pos.lineNumber, pos.column, ch);
         },
     });
+    monaco.languages.registerCodeActionProvider(modeId, {
+        async provideCodeActions(m, range, context) {
+            const actions = await state.code_actions(
+                range.startLineNumber,
+                range.startColumn,
+                range.endLineNumber,
+                range.endColumn
+            );
+            if (!actions) return null;
+            return {
+                actions: actions.map((action) => ({
+                    title: action.title,
+                    kind: "quickfix",
+                    diagnostics: context.markers.filter(
+                        (marker) => marker.code === action.code
+                    ),
+                    edit: {
+                        edits: action.edits.map((edit) => ({
+                            resource: m.uri,
+                            edit,
+                        })),
+                    },
+                })),
+                dispose() {},
+            };
+        },
+    });
     monaco.languages.registerFoldingRangeProvider(modeId, {
         async provideFoldingRanges() {
             return await state.folding_ranges();
@@ -323,7 +426,7 @@ export const registerRA = async () => {
     // });
 };
 
-export const createRA = async () => {
+export const createRA = async (onProgress) => {
     const worker = new Worker(new URL("../../ra-worker.js", import.meta.url), {
         type: "module",
     });
@@ -333,9 +436,8 @@ export const createRA = async () => {
     let ready;
 
     const callWorker = async (which, ...args) => {
-        // eslint-disable-next-line no-unused-vars
-        return new Promise((resolve, _) => {
-            pendingResolve[id] = resolve;
+        return new Promise((resolve, reject) => {
+            pendingResolve[id] = { resolve, reject };
             worker.postMessage({
                 which: which,
                 args: args,
@@ -361,9 +463,17 @@ export const createRA = async () => {
             ready(new Proxy({}, proxyHandler));
             return;
         }
+        if (e.data.id == "ra-progress") {
+            onProgress(e.data.progress);
+            return;
+        }
         const pending = pendingResolve[e.data.id];
         if (pending) {
-            pending(e.data.result);
+            if ("error" in e.data) {
+                pending.reject(new Error(e.data.error));
+            } else {
+                pending.resolve(e.data.result);
+            }
             delete pendingResolve[e.data.id];
         }
     };
@@ -374,15 +484,28 @@ export const createRA = async () => {
     });
 };
 
-export const start = async (monacoElementRef, setEditorContent) => {
+// `setProgress` is called with the `{ label, done, total }` of the loading
+// steps, `total` being 0 while unknown.
+export const start = async (
+    monacoElementRef,
+    setEditorContent,
+    setProgress
+) => {
     console.log("Starting Monaco editor...");
     let model = monaco.editor.createModel(exampleCode, modeId);
     window.editor = monaco.editor;
     state = null;
 
     async function update() {
-        const res = await state.update(model.getValue());
-        setEditorContent(model.getValue());
+        const code = model.getValue();
+        const res = await state.update(code);
+        setEditorContent(code);
+        if (!res) {
+            // Not applied while libraries are being indexed, sent again
+            // unless the code changed, which sends the new code anyway.
+            setTimeout(() => model.getValue() === code && update(), 100);
+            return;
+        }
         monaco.editor.setModelMarkers(model, modeId, res.diagnostics);
         allTokens = res.highlights;
     }
@@ -402,16 +525,30 @@ export const start = async (monacoElementRef, setEditorContent) => {
     });
 
     const initRA = async () => {
-        state = await createRA();
+        setProgress({ label: "Starting rust-analyzer", done: 0, total: 0 });
+        state = await createRA(({ crateName, done, total }) =>
+            setProgress({
+                label: `Indexed ${crateName ?? "your code"}`,
+                done,
+                total,
+            })
+        );
 
         await registerRA();
-        await state.init(
-            model.getValue(),
-            fake_std,
-            fake_core,
-            fake_alloc,
-            fake_soroban_sdk
-        );
+        setProgress({ label: "Loading libraries", done: 0, total: 0 });
+        const bundle = await fetchBundle();
+        let loaded = false;
+        if (bundle) {
+            try {
+                await state.init_bundle(model.getValue(), bundle);
+                loaded = true;
+            } catch (e) {
+                console.error(`Can't load ${bundleUrl}:`, e);
+            }
+        }
+        if (!loaded) {
+            await state.init(model.getValue(), await fakeBundle());
+        }
         await update();
         model.onDidChangeContent(update);
     };